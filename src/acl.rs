@@ -0,0 +1,296 @@
+use crate::config::Config;
+use std::fmt;
+use std::net::IpAddr;
+
+/// IPv4 or IPv6 network in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Parse `addr/prefix`; a bare address is a single host
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = addr.parse().ok()?;
+        let max = Self::max_prefix(&network);
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|p| *p <= max)?,
+            None => max,
+        };
+        Some(Self { network, prefix })
+    }
+
+    /// The network of the given prefix length containing an address
+    pub fn around(ip: IpAddr, prefix: u8) -> Self {
+        let prefix = prefix.min(Self::max_prefix(&ip));
+        let host_bits = u32::from(Self::max_prefix(&ip) - prefix);
+        let network = match ip {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(host_bits).unwrap_or(0);
+                IpAddr::V4((u32::from(ip) & mask).into())
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(host_bits).unwrap_or(0);
+                IpAddr::V6((u128::from(ip) & mask).into())
+            }
+        };
+        Self { network, prefix }
+    }
+
+    fn max_prefix(ip: &IpAddr) -> u8 {
+        match ip {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    /// Whether the address lies inside this network
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let (network, ip, bits) = match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                (u32::from(network) as u128, u32::from(*ip) as u128, 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(*ip), 128),
+            _ => return false,
+        };
+        let shift = bits - u32::from(self.prefix);
+        shift == bits || network >> shift == ip >> shift
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Outcome of checking a caller against the access lists
+#[derive(Debug, Clone, PartialEq)]
+pub enum IpVerdict {
+    Allowed,
+    /// Caller is inside a denied network
+    Denied(Cidr),
+    /// An allow list applies and the caller is outside of it
+    NotAllowed,
+}
+
+impl IpVerdict {
+    /// Explanation returned to refused callers
+    pub fn reason(&self, client_ip: &str) -> String {
+        match self {
+            IpVerdict::Allowed => String::new(),
+            IpVerdict::Denied(cidr) => format!("caller {} is denied by {}", client_ip, cidr),
+            IpVerdict::NotAllowed => format!("caller {} is not in an allowed range", client_ip),
+        }
+    }
+}
+
+/// Global and per-homename CIDR allow/deny lists for callers
+#[derive(Debug, Clone, Default)]
+pub struct IpAccessList {
+    /// Networks allowed to update homenames without their own allow list
+    pub allow: Vec<Cidr>,
+    /// Networks never allowed to update
+    pub deny: Vec<Cidr>,
+    /// Per-homename allow lists replacing the global one, first match wins
+    pub host_allow: Vec<(String, Vec<Cidr>)>,
+    /// Per-homename deny lists added to the global one, first match wins
+    pub host_deny: Vec<(String, Vec<Cidr>)>,
+}
+
+impl IpAccessList {
+    /// Check whether the caller may update the homename
+    ///
+    /// Deny lists are evaluated first. When an allow list applies, callers
+    /// outside of it, including ones without a parseable address, are refused.
+    pub fn check(&self, homename: &str, client_ip: &str) -> IpVerdict {
+        let ip = client_ip.parse::<IpAddr>().ok();
+
+        let host_deny = Self::lookup(&self.host_deny, homename).unwrap_or_default();
+        if let Some(ip) = ip
+            && let Some(cidr) = self.deny.iter().chain(host_deny).find(|c| c.contains(&ip))
+        {
+            return IpVerdict::Denied(*cidr);
+        }
+
+        let allow = Self::lookup(&self.host_allow, homename).unwrap_or(&self.allow);
+        if allow.is_empty() || ip.is_some_and(|ip| allow.iter().any(|c| c.contains(&ip))) {
+            IpVerdict::Allowed
+        } else {
+            IpVerdict::NotAllowed
+        }
+    }
+
+    fn lookup<'a>(lists: &'a [(String, Vec<Cidr>)], homename: &str) -> Option<&'a [Cidr]> {
+        lists
+            .iter()
+            .find(|(pattern, _)| Config::glob_match(pattern, homename))
+            .map(|(_, cidrs)| cidrs.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidrs(values: &[&str]) -> Vec<Cidr> {
+        values.iter().map(|v| Cidr::parse(v).unwrap()).collect()
+    }
+
+    #[test]
+    fn cidr_matching() {
+        let test_cases = vec![
+            (
+                "203.0.113.0/24",
+                "203.0.113.77",
+                true,
+                "inside IPv4 network",
+            ),
+            (
+                "203.0.113.0/24",
+                "203.0.114.1",
+                false,
+                "outside IPv4 network",
+            ),
+            (
+                "203.0.113.7",
+                "203.0.113.7",
+                true,
+                "bare address matches itself",
+            ),
+            (
+                "203.0.113.7",
+                "203.0.113.8",
+                false,
+                "bare address is a single host",
+            ),
+            (
+                "0.0.0.0/0",
+                "198.51.100.1",
+                true,
+                "zero prefix matches everything",
+            ),
+            (
+                "2001:db8::/32",
+                "2001:db8:1::1",
+                true,
+                "inside IPv6 network",
+            ),
+            (
+                "2001:db8::/32",
+                "2001:db9::1",
+                false,
+                "outside IPv6 network",
+            ),
+            (
+                "2001:db8::/32",
+                "203.0.113.1",
+                false,
+                "families never match",
+            ),
+            ("::/0", "::1", true, "zero IPv6 prefix matches everything"),
+        ];
+
+        for (cidr, ip, expected, description) in test_cases {
+            let cidr = Cidr::parse(cidr).unwrap();
+            let ip: IpAddr = ip.parse().unwrap();
+            assert_eq!(cidr.contains(&ip), expected, "Failed: {}", description);
+        }
+
+        for invalid in [
+            "",
+            "203.0.113.0/33",
+            "2001:db8::/129",
+            "example.com/24",
+            "10.0.0.0/x",
+        ] {
+            assert!(Cidr::parse(invalid).is_none(), "accepted {}", invalid);
+        }
+        assert_eq!(
+            Cidr::parse(" 203.0.113.0/24 ").unwrap().to_string(),
+            "203.0.113.0/24"
+        );
+
+        let around = |ip: &str, prefix| Cidr::around(ip.parse().unwrap(), prefix).to_string();
+        assert_eq!(around("203.0.113.77", 24), "203.0.113.0/24");
+        assert_eq!(around("2001:db8:1:2::7", 48), "2001:db8:1::/48");
+        assert_eq!(around("203.0.113.77", 0), "0.0.0.0/0");
+        assert_eq!(around("203.0.113.77", 64), "203.0.113.77/32");
+    }
+
+    #[test]
+    fn access_list_checks() {
+        let acl = IpAccessList {
+            allow: cidrs(&["198.51.100.0/24"]),
+            deny: cidrs(&["198.51.100.13"]),
+            host_allow: vec![("office-*".to_string(), cidrs(&["203.0.113.0/24"]))],
+            host_deny: vec![("office-lab".to_string(), cidrs(&["203.0.113.128/25"]))],
+        };
+        let denied = |cidr: &str| IpVerdict::Denied(Cidr::parse(cidr).unwrap());
+
+        let test_cases = vec![
+            (
+                "home",
+                "198.51.100.1",
+                IpVerdict::Allowed,
+                "inside global allow list",
+            ),
+            (
+                "home",
+                "192.0.2.1",
+                IpVerdict::NotAllowed,
+                "outside global allow list",
+            ),
+            (
+                "home",
+                "198.51.100.13",
+                denied("198.51.100.13"),
+                "deny beats allow",
+            ),
+            (
+                "home",
+                "",
+                IpVerdict::NotAllowed,
+                "unknown caller with allow list",
+            ),
+            (
+                "office-berlin",
+                "203.0.113.5",
+                IpVerdict::Allowed,
+                "host allow list",
+            ),
+            (
+                "office-berlin",
+                "198.51.100.1",
+                IpVerdict::NotAllowed,
+                "host allow list replaces the global one",
+            ),
+            (
+                "office-lab",
+                "203.0.113.5",
+                IpVerdict::Allowed,
+                "outside host deny list",
+            ),
+            (
+                "office-lab",
+                "203.0.113.200",
+                denied("203.0.113.128/25"),
+                "inside host deny list",
+            ),
+        ];
+
+        for (homename, ip, expected, description) in test_cases {
+            assert_eq!(acl.check(homename, ip), expected, "Failed: {}", description);
+        }
+
+        let open = IpAccessList::default();
+        assert_eq!(open.check("home", ""), IpVerdict::Allowed);
+        assert_eq!(open.check("home", "192.0.2.1"), IpVerdict::Allowed);
+    }
+}
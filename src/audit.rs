@@ -0,0 +1,468 @@
+use crate::auth::AuthUtils;
+use crate::store::KeyValueStore;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+// Constants
+pub const AUTH_FAILURES_PATH: &str = "/admin/auth-failures";
+const FAILURE_KEY_PREFIX: &str = "authfail:";
+const FAILURE_COUNT_KEY_PREFIX: &str = "failcount:";
+const LOCKOUT_KEY_PREFIX: &str = "lockout:";
+/// Failures are kept for a week
+const FAILURE_RETENTION_SECS: u64 = 7 * 24 * 3600;
+/// Most failures returned by the admin endpoint
+const MAX_LISTED_FAILURES: usize = 100;
+/// Failures stored per source IP and window; further ones are only counted
+const MAX_STORED_FAILURES_PER_IP: u32 = 10;
+/// Window the stored failures are capped in when no lockout policy is set
+const DEFAULT_FAILURE_WINDOW_SECS: u64 = 3600;
+const FAILURE_ID_BYTES: usize = 4;
+
+/// Why a request failed to authenticate
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// No credential of any kind was sent
+    MissingCredentials,
+    /// Bearer or query token matched neither `API_TOKEN` nor a named token
+    InvalidToken,
+    /// Basic auth password did not match
+    InvalidBasicAuth,
+    /// Signed URL was forged, expired or replayed
+    InvalidSignedUrl,
+    /// Access JWT failed validation
+    InvalidAccessJwt,
+    /// Device signature was forged, stale, replayed or from an unapproved device
+    InvalidDeviceSignature,
+}
+
+/// One failed authentication attempt, stored under `authfail:{timestamp}:{id}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthFailure {
+    pub client_ip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homename: Option<String>,
+    /// Unix time in milliseconds
+    pub timestamp: u64,
+    pub reason: FailureReason,
+}
+
+/// Source IP temporarily refused after too many failures
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Lockout {
+    pub client_ip: String,
+    /// Unix time in milliseconds when the lockout ends
+    pub until: u64,
+}
+
+/// Failures tolerated from one source IP before it is locked out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LockoutPolicy {
+    pub max_failures: u32,
+    pub window_secs: u64,
+    pub duration_secs: u64,
+}
+
+impl LockoutPolicy {
+    /// Parse `failures/window[/duration]` in seconds; the duration defaults to the window
+    pub fn parse(value: &str) -> Option<Self> {
+        let parts: Vec<u64> = value
+            .split('/')
+            .map(|part| part.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let (max_failures, window_secs, duration_secs) = match parts[..] {
+            [failures, window] => (failures, window, window),
+            [failures, window, duration] => (failures, window, duration),
+            _ => return None,
+        };
+        if max_failures == 0 || window_secs == 0 || duration_secs == 0 {
+            return None;
+        }
+        Some(Self {
+            max_failures: u32::try_from(max_failures).ok()?,
+            window_secs,
+            duration_secs,
+        })
+    }
+}
+
+/// Failure count of the current window, stored under `failcount:{ip}`
+///
+/// The window is the lockout policy's, or an hour without one.
+#[derive(Serialize, Deserialize)]
+struct FailureWindow {
+    /// Unix time in milliseconds of the first failure in the window
+    started: u64,
+    count: u32,
+}
+
+/// Audit log of failed authentication attempts with per-IP lockout
+pub struct AuditLog<'a> {
+    kv: &'a dyn KeyValueStore,
+}
+
+impl<'a> AuditLog<'a> {
+    /// Create a new audit log over the given store
+    pub fn new(kv: &'a dyn KeyValueStore) -> Self {
+        Self { kv }
+    }
+
+    /// Store a failure and lock its source IP out once it exceeds the policy
+    ///
+    /// Only the first failures of each source IP and window are stored, so a
+    /// brute force costs a bounded number of KV writes. Returns the lockout
+    /// duration in seconds when this failure triggered one.
+    pub async fn record(
+        &self,
+        failure: &AuthFailure,
+        policy: Option<&LockoutPolicy>,
+    ) -> Result<Option<u64>> {
+        // Without a source address every caller would share one counter and lockout
+        if failure.client_ip.is_empty() {
+            self.store(failure).await?;
+            return Ok(None);
+        }
+
+        let now_ms = failure.timestamp;
+        let count_key = format!("{}{}", FAILURE_COUNT_KEY_PREFIX, failure.client_ip);
+        let window_secs = policy.map_or(DEFAULT_FAILURE_WINDOW_SECS, |p| p.window_secs);
+        let window_ms = window_secs.saturating_mul(1000);
+        let window = self
+            .kv
+            .get(&count_key)
+            .await?
+            .and_then(|v| serde_json::from_str::<FailureWindow>(&v).ok())
+            .filter(|w| now_ms.saturating_sub(w.started) < window_ms)
+            .map_or(
+                FailureWindow {
+                    started: now_ms,
+                    count: 1,
+                },
+                |w| FailureWindow {
+                    count: w.count + 1,
+                    ..w
+                },
+            );
+        if window.count <= MAX_STORED_FAILURES_PER_IP {
+            self.store(failure).await?;
+        }
+
+        let Some(policy) = policy else {
+            // Past the cap there is no lockout left to count towards
+            if window.count <= MAX_STORED_FAILURES_PER_IP {
+                let value = serde_json::to_string(&window)?;
+                self.kv
+                    .put_with_ttl(&count_key, &value, window_secs)
+                    .await?;
+            }
+            return Ok(None);
+        };
+
+        if window.count >= policy.max_failures {
+            let until = now_ms.saturating_add(policy.duration_secs.saturating_mul(1000));
+            self.kv
+                .put_with_ttl(
+                    &Self::lockout_key(&failure.client_ip),
+                    &until.to_string(),
+                    policy.duration_secs,
+                )
+                .await?;
+            self.kv.delete(&count_key).await?;
+            return Ok(Some(policy.duration_secs));
+        }
+
+        let value = serde_json::to_string(&window)?;
+        self.kv
+            .put_with_ttl(&count_key, &value, policy.window_secs)
+            .await?;
+        Ok(None)
+    }
+
+    /// Store a failure under a key sorting by its timestamp
+    async fn store(&self, failure: &AuthFailure) -> Result<()> {
+        let id = AuthUtils::random_hex(FAILURE_ID_BYTES)?;
+        let key = format!("{}{}:{}", FAILURE_KEY_PREFIX, failure.timestamp, id);
+        let value = serde_json::to_string(failure)?;
+        self.kv
+            .put_with_ttl(&key, &value, FAILURE_RETENTION_SECS)
+            .await
+    }
+
+    fn lockout_key(client_ip: &str) -> String {
+        format!("{}{}", LOCKOUT_KEY_PREFIX, client_ip)
+    }
+
+    /// Seconds the source IP remains locked out, `None` when it may authenticate
+    pub async fn locked_for(&self, client_ip: &str, now_ms: u64) -> Result<Option<u64>> {
+        if client_ip.is_empty() {
+            return Ok(None);
+        }
+        let until = self
+            .kv
+            .get(&Self::lockout_key(client_ip))
+            .await?
+            .and_then(|v| v.parse::<u64>().ok());
+        Ok(until
+            .filter(|&until| until > now_ms)
+            .map(|until| (until - now_ms).div_ceil(1000)))
+    }
+
+    /// Most recent failures, newest first
+    ///
+    /// Keys embed the timestamp, so only the newest ones are fetched; the
+    /// listing stays within the per-request KV limits during a brute force.
+    pub async fn recent(&self) -> Result<Vec<AuthFailure>> {
+        let mut keys = self.kv.list_keys(FAILURE_KEY_PREFIX).await?;
+        keys.sort();
+        let newest = keys.len().saturating_sub(MAX_LISTED_FAILURES);
+
+        let mut failures = Vec::with_capacity(keys.len() - newest);
+        for key in keys[newest..].iter().rev() {
+            if let Some(value) = self.kv.get(key).await?
+                && let Ok(failure) = serde_json::from_str::<AuthFailure>(&value)
+            {
+                failures.push(failure);
+            }
+        }
+        Ok(failures)
+    }
+
+    /// Source IPs currently locked out
+    pub async fn lockouts(&self, now_ms: u64) -> Result<Vec<Lockout>> {
+        let mut lockouts = Vec::new();
+        for key in self.kv.list_keys(LOCKOUT_KEY_PREFIX).await? {
+            let until = self.kv.get(&key).await?.and_then(|v| v.parse::<u64>().ok());
+            if let Some(until) = until.filter(|&until| until > now_ms) {
+                lockouts.push(Lockout {
+                    client_ip: key[LOCKOUT_KEY_PREFIX.len()..].to_string(),
+                    until,
+                });
+            }
+        }
+        Ok(lockouts)
+    }
+
+    /// Lift the lockout of a source IP; returns whether one was active
+    pub async fn unlock(&self, client_ip: &str) -> Result<bool> {
+        let key = Self::lockout_key(client_ip);
+        let locked = self.kv.get(&key).await?.is_some();
+        self.kv.delete(&key).await?;
+        Ok(locked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn failure(client_ip: &str, timestamp: u64) -> AuthFailure {
+        AuthFailure {
+            client_ip: client_ip.to_string(),
+            homename: Some("home".to_string()),
+            timestamp,
+            reason: FailureReason::InvalidToken,
+        }
+    }
+
+    #[test]
+    fn policy_parsing() {
+        let test_cases = vec![
+            ("5/300", Some((5, 300, 300)), "duration defaults to window"),
+            ("5/300/3600", Some((5, 300, 3600)), "explicit duration"),
+            (
+                " 10 / 60 / 60 ",
+                Some((10, 60, 60)),
+                "surrounding whitespace",
+            ),
+            ("0/300", None, "zero failures"),
+            ("5/0", None, "empty window"),
+            ("5", None, "missing window"),
+            ("5/300/3600/1", None, "too many parts"),
+            ("five/300", None, "non-numeric"),
+        ];
+
+        for (input, expected, description) in test_cases {
+            let expected =
+                expected.map(|(max_failures, window_secs, duration_secs)| LockoutPolicy {
+                    max_failures,
+                    window_secs,
+                    duration_secs,
+                });
+            assert_eq!(
+                LockoutPolicy::parse(input),
+                expected,
+                "Failed: {}",
+                description
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn failures_are_listed_newest_first() {
+        let kv = MemoryStore::new();
+        let audit = AuditLog::new(&kv);
+        audit
+            .record(&failure("192.0.2.1", NOW), None)
+            .await
+            .unwrap();
+        audit
+            .record(&failure("192.0.2.2", NOW + 1000), None)
+            .await
+            .unwrap();
+
+        let failures = audit.recent().await.unwrap();
+        assert_eq!(
+            failures,
+            vec![failure("192.0.2.2", NOW + 1000), failure("192.0.2.1", NOW)]
+        );
+        let json = serde_json::to_value(&failures[0]).unwrap();
+        assert_eq!(json["reason"], "invalid_token");
+        // Without a policy nothing is locked
+        assert_eq!(audit.locked_for("192.0.2.1", NOW).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn stored_failures_are_capped() {
+        let kv = MemoryStore::new();
+        let audit = AuditLog::new(&kv);
+        for offset in 0..MAX_STORED_FAILURES_PER_IP as u64 + 5 {
+            audit
+                .record(&failure("192.0.2.1", NOW + offset), None)
+                .await
+                .unwrap();
+        }
+        audit
+            .record(&failure("192.0.2.2", NOW + 100), None)
+            .await
+            .unwrap();
+
+        let failures = audit.recent().await.unwrap();
+        assert_eq!(failures.len(), MAX_STORED_FAILURES_PER_IP as usize + 1);
+        assert_eq!(failures[0], failure("192.0.2.2", NOW + 100));
+        assert_eq!(failures[1], failure("192.0.2.1", NOW + 9));
+
+        // A new window stores failures again
+        let later = NOW + DEFAULT_FAILURE_WINDOW_SECS * 1000;
+        audit
+            .record(&failure("192.0.2.1", later), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            audit.recent().await.unwrap()[0],
+            failure("192.0.2.1", later)
+        );
+    }
+
+    #[tokio::test]
+    async fn recent_failures_are_limited() {
+        let kv = MemoryStore::new();
+        let audit = AuditLog::new(&kv);
+        for offset in 0..MAX_LISTED_FAILURES as u64 + 20 {
+            audit
+                .record(&failure("", NOW + offset * 1000), None)
+                .await
+                .unwrap();
+        }
+
+        let failures = audit.recent().await.unwrap();
+        assert_eq!(failures.len(), MAX_LISTED_FAILURES);
+        assert_eq!(failures[0].timestamp, NOW + 119_000);
+        assert_eq!(failures[MAX_LISTED_FAILURES - 1].timestamp, NOW + 20_000);
+    }
+
+    #[tokio::test]
+    async fn repeated_failures_lock_the_source_out() {
+        let kv = MemoryStore::new();
+        let audit = AuditLog::new(&kv);
+        let policy = LockoutPolicy::parse("3/60/600").unwrap();
+
+        for offset in [0, 10_000] {
+            assert_eq!(
+                audit
+                    .record(&failure("192.0.2.1", NOW + offset), Some(&policy))
+                    .await
+                    .unwrap(),
+                None
+            );
+        }
+        // Failures from other sources are counted separately
+        assert_eq!(
+            audit
+                .record(&failure("192.0.2.2", NOW), Some(&policy))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            audit
+                .record(&failure("192.0.2.1", NOW + 20_000), Some(&policy))
+                .await
+                .unwrap(),
+            Some(600)
+        );
+
+        assert_eq!(
+            audit.locked_for("192.0.2.1", NOW + 80_000).await.unwrap(),
+            Some(540)
+        );
+        assert_eq!(audit.locked_for("192.0.2.2", NOW).await.unwrap(), None);
+        assert_eq!(
+            audit.locked_for("192.0.2.1", NOW + 620_000).await.unwrap(),
+            None
+        );
+        let lockouts = audit.lockouts(NOW + 80_000).await.unwrap();
+        assert_eq!(
+            lockouts,
+            vec![Lockout {
+                client_ip: "192.0.2.1".to_string(),
+                until: NOW + 620_000
+            }]
+        );
+
+        assert!(audit.unlock("192.0.2.1").await.unwrap());
+        assert!(!audit.unlock("192.0.2.1").await.unwrap());
+        assert_eq!(
+            audit.locked_for("192.0.2.1", NOW + 80_000).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn window_restarts_after_it_expires() {
+        let kv = MemoryStore::new();
+        let audit = AuditLog::new(&kv);
+        let policy = LockoutPolicy::parse("2/60").unwrap();
+
+        assert_eq!(
+            audit
+                .record(&failure("192.0.2.1", NOW), Some(&policy))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            audit
+                .record(&failure("192.0.2.1", NOW + 61_000), Some(&policy))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            audit
+                .record(&failure("", NOW), Some(&policy))
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            audit
+                .record(&failure("", NOW), Some(&policy))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
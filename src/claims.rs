@@ -0,0 +1,261 @@
+use crate::store::KeyValueStore;
+use crate::tokens::Credential;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+// Constants
+pub const ADMIN_CLAIMS_PATH: &str = "/admin/claims";
+const CLAIM_KEY_PREFIX: &str = "claim:";
+
+/// Ownership of a homename, stored in KV under `claim:{homename}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claim {
+    /// Credential owner allowed to update the homename, e.g. `token:{id}`
+    pub owner: String,
+    /// Name of the owning credential when the claim was made
+    #[serde(default)]
+    pub name: String,
+    /// Unix time of the claim in milliseconds
+    pub claimed: u64,
+}
+
+/// Claim as returned by the admin API
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClaimInfo {
+    pub homename: String,
+    #[serde(flatten)]
+    pub claim: Claim,
+}
+
+/// Body of `PUT /admin/claims/{homename}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransferRequest {
+    pub owner: String,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Homename claims binding each name to the first credential that updated it
+pub struct ClaimStore<'a> {
+    kv: &'a dyn KeyValueStore,
+}
+
+impl<'a> ClaimStore<'a> {
+    /// Create a claim store over the given KV store
+    pub fn new(kv: &'a dyn KeyValueStore) -> Self {
+        Self { kv }
+    }
+
+    fn claim_key(homename: &str) -> String {
+        format!("{}{}", CLAIM_KEY_PREFIX, homename)
+    }
+
+    /// Current claim of a homename
+    pub async fn get(&self, homename: &str) -> Result<Option<Claim>> {
+        match self.kv.get(&Self::claim_key(homename)).await? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, homename: &str, claim: &Claim) -> Result<()> {
+        let value = serde_json::to_string(claim)?;
+        self.kv.put(&Self::claim_key(homename), &value).await
+    }
+
+    /// Claim of another owner that keeps the credential from updating the homename
+    ///
+    /// Like `claim`, but never claims an unowned homename, so several names can
+    /// be checked before any of them is taken.
+    pub async fn conflict(&self, homename: &str, credential: &Credential) -> Result<Option<Claim>> {
+        let Some(owner) = credential.owner.as_deref() else {
+            return Ok(None);
+        };
+        Ok(self
+            .get(homename)
+            .await?
+            .filter(|claim| claim.owner != owner))
+    }
+
+    /// Claim an unowned homename for the credential, or check that it already owns it
+    ///
+    /// Returns the claim of another owner when the credential may not update the
+    /// homename. Credentials without an owner act for an admin: they are never
+    /// refused and never claim names.
+    pub async fn claim(
+        &self,
+        homename: &str,
+        credential: &Credential,
+        now_ms: u64,
+    ) -> Result<Option<Claim>> {
+        let Some(owner) = credential.owner.as_deref() else {
+            return Ok(None);
+        };
+        match self.get(homename).await? {
+            Some(claim) if claim.owner == owner => Ok(None),
+            Some(claim) => Ok(Some(claim)),
+            None => {
+                let claim = Claim {
+                    owner: owner.to_string(),
+                    name: credential.name.clone(),
+                    claimed: now_ms,
+                };
+                self.put(homename, &claim).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Every claimed homename
+    pub async fn list(&self) -> Result<Vec<ClaimInfo>> {
+        let mut claims = Vec::new();
+        for key in self.kv.list_keys(CLAIM_KEY_PREFIX).await? {
+            let homename = &key[CLAIM_KEY_PREFIX.len()..];
+            if let Some(claim) = self.get(homename).await? {
+                claims.push(ClaimInfo {
+                    homename: homename.to_string(),
+                    claim,
+                });
+            }
+        }
+        Ok(claims)
+    }
+
+    /// Hand a homename to another owner, claiming it when unowned
+    pub async fn transfer(
+        &self,
+        homename: &str,
+        request: TransferRequest,
+        now_ms: u64,
+    ) -> Result<ClaimInfo> {
+        let claim = Claim {
+            owner: request.owner,
+            name: request.name,
+            claimed: now_ms,
+        };
+        self.put(homename, &claim).await?;
+        Ok(ClaimInfo {
+            homename: homename.to_string(),
+            claim,
+        })
+    }
+
+    /// Release a homename so the next credential updating it claims it; returns whether it was claimed
+    pub async fn release(&self, homename: &str) -> Result<bool> {
+        let key = Self::claim_key(homename);
+        let claimed = self.kv.get(&key).await?.is_some();
+        self.kv.delete(&key).await?;
+        Ok(claimed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Scope;
+    use crate::store::MemoryStore;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn credential(owner: &str) -> Credential {
+        Credential {
+            name: format!("{} device", owner),
+            owner: Some(owner.to_string()),
+            hosts: vec!["*".to_string()],
+            scopes: Scope::defaults(),
+        }
+    }
+
+    #[tokio::test]
+    async fn first_credential_owns_the_homename() {
+        let kv = MemoryStore::new();
+        let claims = ClaimStore::new(&kv);
+        let first = credential("token:a");
+        let second = credential("token:b");
+
+        assert_eq!(claims.claim("home", &first, NOW).await.unwrap(), None);
+        assert_eq!(claims.claim("home", &first, NOW + 1).await.unwrap(), None);
+        let refused = claims.claim("home", &second, NOW).await.unwrap();
+        assert_eq!(refused.map(|c| c.owner).as_deref(), Some("token:a"));
+
+        // Admin credentials neither claim nor get refused
+        assert_eq!(
+            claims
+                .claim("home", &Credential::admin(), NOW)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            claims
+                .claim("vpn", &Credential::admin(), NOW)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(claims.get("vpn").await.unwrap().is_none());
+
+        assert_eq!(
+            claims.list().await.unwrap(),
+            vec![ClaimInfo {
+                homename: "home".to_string(),
+                claim: Claim {
+                    owner: "token:a".to_string(),
+                    name: "token:a device".to_string(),
+                    claimed: NOW,
+                },
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn conflicts_are_checked_without_claiming() {
+        let kv = MemoryStore::new();
+        let claims = ClaimStore::new(&kv);
+        let first = credential("token:a");
+        let second = credential("token:b");
+        claims.claim("home", &first, NOW).await.unwrap();
+
+        assert_eq!(claims.conflict("home", &first).await.unwrap(), None);
+        let conflict = claims.conflict("home", &second).await.unwrap();
+        assert_eq!(conflict.map(|c| c.owner).as_deref(), Some("token:a"));
+        assert_eq!(
+            claims.conflict("home", &Credential::admin()).await.unwrap(),
+            None
+        );
+
+        assert_eq!(claims.conflict("cam", &second).await.unwrap(), None);
+        assert!(claims.get("cam").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn claims_can_be_transferred_and_released() {
+        let kv = MemoryStore::new();
+        let claims = ClaimStore::new(&kv);
+        let first = credential("token:a");
+        let second = credential("token:b");
+        claims.claim("home", &first, NOW).await.unwrap();
+
+        let request = TransferRequest {
+            owner: "token:b".to_string(),
+            name: String::new(),
+        };
+        let info = claims.transfer("home", request, NOW + 1).await.unwrap();
+        assert_eq!(info.claim.owner, "token:b");
+        assert!(claims.claim("home", &first, NOW).await.unwrap().is_some());
+        assert_eq!(claims.claim("home", &second, NOW).await.unwrap(), None);
+
+        assert!(claims.release("home").await.unwrap());
+        assert!(!claims.release("home").await.unwrap());
+        assert_eq!(claims.claim("home", &first, NOW).await.unwrap(), None);
+        assert_eq!(
+            claims
+                .get("home")
+                .await
+                .unwrap()
+                .map(|c| c.owner)
+                .as_deref(),
+            Some("token:a")
+        );
+    }
+}
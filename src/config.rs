@@ -1,3 +1,9 @@
+use crate::acl::{Cidr, IpAccessList};
+use crate::audit::LockoutPolicy;
+use crate::dns::DEFAULT_DNS_TTL;
+use crate::notify::WebhookTarget;
+use crate::quarantine::{QuarantinePolicy, RiskRule};
+use crate::ratelimit::{RateKind, RateLimit, RateLimits};
 use worker::*;
 
 // Environment variable names
@@ -5,8 +11,154 @@ pub const ENV_API_TOKEN: &str = "API_TOKEN";
 pub const ENV_CF_ZONE_ID: &str = "CF_ZONE_ID";
 pub const ENV_CF_API_TOKEN: &str = "CF_API_TOKEN";
 pub const ENV_CF_DOMAIN: &str = "CF_DOMAIN";
+pub const ENV_STRICT_DNS_UPDATE: &str = "STRICT_DNS_UPDATE";
+pub const ENV_DNS_PROVIDER: &str = "DNS_PROVIDER";
+pub const ENV_DNS_PROVIDER_HOSTS: &str = "DNS_PROVIDER_HOSTS";
+pub const ENV_PDNS_API_URL: &str = "PDNS_API_URL";
+pub const ENV_PDNS_API_KEY: &str = "PDNS_API_KEY";
+pub const ENV_PDNS_SERVER_ID: &str = "PDNS_SERVER_ID";
+pub const ENV_PDNS_ZONE: &str = "PDNS_ZONE";
+pub const ENV_DNS_TTL: &str = "DNS_TTL";
+pub const ENV_DNS_REVALIDATE_INTERVAL: &str = "DNS_REVALIDATE_INTERVAL";
+pub const ENV_ROUTE53_HOSTED_ZONE_ID: &str = "ROUTE53_HOSTED_ZONE_ID";
+pub const ENV_ROUTE53_DOMAIN: &str = "ROUTE53_DOMAIN";
+pub const ENV_ROUTE53_API_URL: &str = "ROUTE53_API_URL";
+pub const ENV_AWS_ACCESS_KEY_ID: &str = "AWS_ACCESS_KEY_ID";
+pub const ENV_AWS_SECRET_ACCESS_KEY: &str = "AWS_SECRET_ACCESS_KEY";
+pub const ENV_AWS_REGION: &str = "AWS_REGION";
+pub const ENV_RFC2136_SERVER: &str = "RFC2136_SERVER";
+pub const ENV_RFC2136_ZONE: &str = "RFC2136_ZONE";
+pub const ENV_RFC2136_KEY_NAME: &str = "RFC2136_KEY_NAME";
+pub const ENV_RFC2136_KEY_SECRET: &str = "RFC2136_KEY_SECRET";
+pub const ENV_HEARTBEAT_TIMEOUT: &str = "HEARTBEAT_TIMEOUT";
+pub const ENV_HEARTBEAT_THRESHOLDS: &str = "HEARTBEAT_THRESHOLDS";
+pub const ENV_ALERT_WEBHOOK_URL: &str = "ALERT_WEBHOOK_URL";
+pub const ENV_WEBHOOKS: &str = "WEBHOOKS";
+pub const ENV_WEBHOOK_SECRET: &str = "WEBHOOK_SECRET";
+pub const ENV_URL_SIGNING_KEY: &str = "URL_SIGNING_KEY";
+pub const ENV_ACCESS_CERTS_URL: &str = "ACCESS_CERTS_URL";
+pub const ENV_ACCESS_AUD: &str = "ACCESS_AUD";
+pub const ENV_ACCESS_IDENTITIES: &str = "ACCESS_IDENTITIES";
+pub const ENV_ACCESS_ADMINS: &str = "ACCESS_ADMINS";
+pub const ENV_IP_ALLOW: &str = "IP_ALLOW";
+pub const ENV_IP_DENY: &str = "IP_DENY";
+pub const ENV_IP_ALLOW_HOSTS: &str = "IP_ALLOW_HOSTS";
+pub const ENV_IP_DENY_HOSTS: &str = "IP_DENY_HOSTS";
+pub const ENV_RATE_LIMITS: &str = "RATE_LIMITS";
+pub const ENV_DNS_WRITE_INTERVAL: &str = "DNS_WRITE_INTERVAL";
+pub const ENV_AUTH_LOCKOUT: &str = "AUTH_LOCKOUT";
+pub const ENV_RESERVED_NAMES: &str = "RESERVED_NAMES";
+pub const ENV_QUARANTINE_HOSTS: &str = "QUARANTINE_HOSTS";
+pub const ENV_QUARANTINE_RULES: &str = "QUARANTINE_RULES";
+
+// Defaults
+const DEFAULT_PDNS_SERVER_ID: &str = "localhost";
+const DEFAULT_ROUTE53_API_URL: &str = "https://route53.amazonaws.com";
+const DEFAULT_AWS_REGION: &str = "us-east-1";
+const DEFAULT_DNS_PORT: u16 = 53;
+const DEFAULT_DNS_REVALIDATE_INTERVAL: u64 = 3600;
+/// Names that usually hold production records; `@` is the zone apex
+const DEFAULT_RESERVED_NAMES: [&str; 14] = [
+    "@",
+    "www",
+    "mail",
+    "smtp",
+    "imap",
+    "pop",
+    "pop3",
+    "mx",
+    "ns",
+    "ns1",
+    "ns2",
+    "ftp",
+    "webmail",
+    "autodiscover",
+];
+const DEFAULT_QUARANTINE_RULES: &str = "country,asn,range,changes=5";
+
+/// DNS backends a homename can be served from
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ProviderKind {
+    #[default]
+    Cloudflare,
+    PowerDns,
+    Route53,
+    Rfc2136,
+}
+
+impl ProviderKind {
+    /// Parse a provider name as used in `DNS_PROVIDER` and `DNS_PROVIDER_HOSTS`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "cloudflare" => Some(ProviderKind::Cloudflare),
+            "powerdns" | "pdns" => Some(ProviderKind::PowerDns),
+            "route53" => Some(ProviderKind::Route53),
+            "rfc2136" | "nsupdate" => Some(ProviderKind::Rfc2136),
+            _ => None,
+        }
+    }
+}
+
+/// PowerDNS Authoritative API settings
+#[derive(Clone)]
+pub struct PowerDnsConfig {
+    /// Base URL of the API, e.g. `http://127.0.0.1:8081`
+    pub api_url: String,
+    /// Value for the `X-API-Key` header
+    pub api_key: String,
+    /// Server id in the API path, usually `localhost`
+    pub server_id: String,
+    /// Zone the homenames are created in
+    pub zone: String,
+}
+
+/// Amazon Route 53 settings
+#[derive(Clone)]
+pub struct Route53Config {
+    /// Base URL of the API, `https://route53.amazonaws.com` unless mocked
+    pub api_url: String,
+    /// IAM access key id used for SigV4 signing
+    pub access_key_id: String,
+    /// IAM secret access key used for SigV4 signing
+    pub secret_access_key: String,
+    /// Signing region; Route 53 is a global service signed for `us-east-1`
+    pub region: String,
+    /// Hosted zone id, with or without the `/hostedzone/` prefix
+    pub hosted_zone_id: String,
+    /// Domain of the hosted zone; homenames become `homename.domain`
+    pub domain: String,
+}
+
+/// RFC 2136 dynamic update settings
+#[derive(Clone)]
+pub struct Rfc2136Config {
+    /// Primary server accepting updates over TCP
+    pub host: String,
+    /// TCP port of the primary server, usually 53
+    pub port: u16,
+    /// Zone the homenames are created in
+    pub zone: String,
+    /// Name of the TSIG key allowed to update the zone
+    pub key_name: String,
+    /// Base64 HMAC-SHA256 secret of the TSIG key
+    pub key_secret: String,
+}
+
+/// Cloudflare Access settings
+#[derive(Clone)]
+pub struct AccessConfig {
+    /// JWKS endpoint, `https://<team>.cloudflareaccess.com/cdn-cgi/access/certs`
+    pub certs_url: String,
+    /// Application audience tag the tokens must be issued for
+    pub audience: String,
+    /// Identity patterns mapped onto the homenames they may update, first match wins
+    pub identities: Vec<(String, Vec<String>)>,
+    /// Identity patterns granted the `admin` scope
+    pub admins: Vec<String>,
+}
 
 /// Application configuration extracted from environment variables
+#[derive(Clone, Default)]
 pub struct Config {
     /// Optional API token for request authentication
     pub api_token: Option<String>,
@@ -16,16 +168,816 @@ pub struct Config {
     pub cf_api_token: String,
     /// Domain name to append to hostnames for DNS records
     pub cf_domain: String,
+    /// Fail requests with 502 when a DNS update fails
+    pub strict_dns_update: bool,
+    /// Backend used for homenames without an explicit mapping
+    pub dns_provider: ProviderKind,
+    /// Homename patterns mapped onto a specific backend, first match wins
+    pub provider_hosts: Vec<(String, ProviderKind)>,
+    /// PowerDNS settings, present when `PDNS_API_URL` is set
+    pub powerdns: Option<PowerDnsConfig>,
+    /// Route 53 settings, present when `ROUTE53_HOSTED_ZONE_ID` is set
+    pub route53: Option<Route53Config>,
+    /// RFC 2136 settings, present when `RFC2136_SERVER` is set
+    pub rfc2136: Option<Rfc2136Config>,
+    /// TTL in seconds for records written by any backend
+    pub dns_ttl: u32,
+    /// Seconds after which cached record state is re-read from the provider, 0 to disable
+    pub dns_revalidate_interval: u64,
+    /// Minimum seconds between two writes of the same record, 0 to disable
+    pub dns_write_interval: u64,
+    /// Seconds of silence after which any homename is reported down
+    pub heartbeat_timeout: Option<u64>,
+    /// Homename patterns with their own silence threshold in seconds, first match wins
+    pub heartbeat_thresholds: Vec<(String, u64)>,
+    /// URL receiving a JSON POST when a homename goes silent, recovers or has a change held
+    pub alert_webhook_url: Option<String>,
+    /// Webhooks told about every record change
+    pub webhooks: Vec<WebhookTarget>,
+    /// Default HMAC secret used to sign webhook payloads
+    pub webhook_secret: Option<String>,
+    /// HMAC key for signed update URLs, signed URLs are rejected when unset
+    pub url_signing_key: Option<String>,
+    /// Cloudflare Access settings, present when `ACCESS_CERTS_URL` is set
+    pub access: Option<AccessConfig>,
+    /// CIDR lists restricting which callers may update homenames
+    pub ip_access: IpAccessList,
+    /// Request limits per client IP, token and homename
+    pub rate_limits: RateLimits,
+    /// Failed authentications tolerated per source IP before it is locked out
+    pub auth_lockout: Option<LockoutPolicy>,
+    /// Homename patterns that can never be updated
+    pub reserved_names: Vec<String>,
+    /// Homenames whose suspicious changes wait for admin approval
+    pub quarantine: QuarantinePolicy,
 }
 
 impl Config {
     /// Extract configuration from environment variables
     pub fn from_env(env: &Env) -> Result<Self> {
+        let dns_provider = match env.var(ENV_DNS_PROVIDER) {
+            Ok(name) => ProviderKind::parse(&name.to_string()).ok_or_else(|| {
+                Error::RustError(format!("unknown {}: {}", ENV_DNS_PROVIDER, name))
+            })?,
+            Err(_) => ProviderKind::Cloudflare,
+        };
+        let provider_hosts = match env.var(ENV_DNS_PROVIDER_HOSTS) {
+            Ok(hosts) => Self::parse_provider_hosts(&hosts.to_string())?,
+            Err(_) => Vec::new(),
+        };
+
+        // Cloudflare settings are only mandatory when some homename uses Cloudflare
+        let uses_cloudflare = dns_provider == ProviderKind::Cloudflare
+            || provider_hosts
+                .iter()
+                .any(|(_, kind)| *kind == ProviderKind::Cloudflare);
+        let cloudflare = |value: Result<String>| match value {
+            Err(_) if !uses_cloudflare => Ok(String::new()),
+            value => value,
+        };
+
+        let powerdns = match env.var(ENV_PDNS_API_URL) {
+            Ok(api_url) => Some(PowerDnsConfig {
+                api_url: api_url.to_string(),
+                api_key: env.secret(ENV_PDNS_API_KEY)?.to_string(),
+                server_id: env
+                    .var(ENV_PDNS_SERVER_ID)
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|_| DEFAULT_PDNS_SERVER_ID.to_string()),
+                zone: env.var(ENV_PDNS_ZONE)?.to_string(),
+            }),
+            Err(_) => None,
+        };
+
+        let route53 = match env.var(ENV_ROUTE53_HOSTED_ZONE_ID) {
+            Ok(hosted_zone_id) => Some(Route53Config {
+                api_url: env
+                    .var(ENV_ROUTE53_API_URL)
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|_| DEFAULT_ROUTE53_API_URL.to_string()),
+                access_key_id: env.var(ENV_AWS_ACCESS_KEY_ID)?.to_string(),
+                secret_access_key: env.secret(ENV_AWS_SECRET_ACCESS_KEY)?.to_string(),
+                region: env
+                    .var(ENV_AWS_REGION)
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|_| DEFAULT_AWS_REGION.to_string()),
+                hosted_zone_id: hosted_zone_id.to_string(),
+                domain: env.var(ENV_ROUTE53_DOMAIN)?.to_string(),
+            }),
+            Err(_) => None,
+        };
+
+        let rfc2136 = match env.var(ENV_RFC2136_SERVER) {
+            Ok(server) => {
+                let (host, port) = Self::parse_server(&server.to_string())?;
+                Some(Rfc2136Config {
+                    host,
+                    port,
+                    zone: env.var(ENV_RFC2136_ZONE)?.to_string(),
+                    key_name: env.var(ENV_RFC2136_KEY_NAME)?.to_string(),
+                    key_secret: env.secret(ENV_RFC2136_KEY_SECRET)?.to_string(),
+                })
+            }
+            Err(_) => None,
+        };
+
+        let dns_ttl = match env.var(ENV_DNS_TTL) {
+            Ok(ttl) => ttl
+                .to_string()
+                .trim()
+                .parse()
+                .map_err(|_| Error::RustError(format!("invalid {}: {}", ENV_DNS_TTL, ttl)))?,
+            Err(_) => DEFAULT_DNS_TTL,
+        };
+        let dns_revalidate_interval = match env.var(ENV_DNS_REVALIDATE_INTERVAL) {
+            Ok(interval) => interval.to_string().trim().parse().map_err(|_| {
+                Error::RustError(format!(
+                    "invalid {}: {}",
+                    ENV_DNS_REVALIDATE_INTERVAL, interval
+                ))
+            })?,
+            Err(_) => DEFAULT_DNS_REVALIDATE_INTERVAL,
+        };
+        let dns_write_interval = match env.var(ENV_DNS_WRITE_INTERVAL) {
+            Ok(interval) => interval.to_string().trim().parse().map_err(|_| {
+                Error::RustError(format!("invalid {}: {}", ENV_DNS_WRITE_INTERVAL, interval))
+            })?,
+            Err(_) => 0,
+        };
+        let heartbeat_timeout = match env.var(ENV_HEARTBEAT_TIMEOUT) {
+            Ok(timeout) => Some(timeout.to_string().trim().parse().map_err(|_| {
+                Error::RustError(format!("invalid {}: {}", ENV_HEARTBEAT_TIMEOUT, timeout))
+            })?),
+            Err(_) => None,
+        };
+        let heartbeat_thresholds = match env.var(ENV_HEARTBEAT_THRESHOLDS) {
+            Ok(thresholds) => Self::parse_heartbeat_thresholds(&thresholds.to_string())?,
+            Err(_) => Vec::new(),
+        };
+        // Webhook URLs often embed credentials, so the list is read as a secret
+        let webhooks = match env.secret(ENV_WEBHOOKS) {
+            Ok(webhooks) => Self::parse_webhooks(&webhooks.to_string())?,
+            Err(_) => Vec::new(),
+        };
+
+        let access = match env.var(ENV_ACCESS_CERTS_URL) {
+            Ok(certs_url) => Some(AccessConfig {
+                certs_url: certs_url.to_string(),
+                audience: env.var(ENV_ACCESS_AUD)?.to_string(),
+                identities: match env.var(ENV_ACCESS_IDENTITIES) {
+                    Ok(identities) => Self::parse_access_identities(&identities.to_string())?,
+                    Err(_) => Vec::new(),
+                },
+                admins: match env.var(ENV_ACCESS_ADMINS) {
+                    Ok(admins) => Self::parse_list(&admins.to_string()),
+                    Err(_) => Vec::new(),
+                },
+            }),
+            Err(_) => None,
+        };
+
+        let cidrs = |name: &str| match env.var(name) {
+            Ok(value) => Self::parse_cidrs(name, &value.to_string()),
+            Err(_) => Ok(Vec::new()),
+        };
+        let host_cidrs = |name: &str| match env.var(name) {
+            Ok(value) => Self::parse_host_cidrs(name, &value.to_string()),
+            Err(_) => Ok(Vec::new()),
+        };
+        let ip_access = IpAccessList {
+            allow: cidrs(ENV_IP_ALLOW)?,
+            deny: cidrs(ENV_IP_DENY)?,
+            host_allow: host_cidrs(ENV_IP_ALLOW_HOSTS)?,
+            host_deny: host_cidrs(ENV_IP_DENY_HOSTS)?,
+        };
+        let auth_lockout = match env.var(ENV_AUTH_LOCKOUT) {
+            Ok(lockout) => Some(LockoutPolicy::parse(&lockout.to_string()).ok_or_else(|| {
+                Error::RustError(format!("invalid {}: {}", ENV_AUTH_LOCKOUT, lockout))
+            })?),
+            Err(_) => None,
+        };
+        let reserved_names = match env.var(ENV_RESERVED_NAMES) {
+            Ok(names) => Self::parse_list(&names.to_string()),
+            Err(_) => DEFAULT_RESERVED_NAMES.map(str::to_string).to_vec(),
+        };
+        let quarantine = match env.var(ENV_QUARANTINE_HOSTS) {
+            Ok(hosts) => QuarantinePolicy {
+                hosts: Self::parse_list(&hosts.to_string()),
+                rules: Self::parse_risk_rules(
+                    &env.var(ENV_QUARANTINE_RULES)
+                        .map(|v| v.to_string())
+                        .unwrap_or_else(|_| DEFAULT_QUARANTINE_RULES.to_string()),
+                )?,
+            },
+            Err(_) => QuarantinePolicy::default(),
+        };
+        let rate_limits = match env.var(ENV_RATE_LIMITS) {
+            Ok(limits) => Self::parse_rate_limits(&limits.to_string())?,
+            Err(_) => RateLimits::default(),
+        };
+
         Ok(Self {
             api_token: env.secret(ENV_API_TOKEN).ok().map(|s| s.to_string()),
-            cf_zone_id: env.var(ENV_CF_ZONE_ID)?.to_string(),
-            cf_api_token: env.secret(ENV_CF_API_TOKEN)?.to_string(),
-            cf_domain: env.var(ENV_CF_DOMAIN)?.to_string(),
+            cf_zone_id: cloudflare(env.var(ENV_CF_ZONE_ID).map(|v| v.to_string()))?,
+            cf_api_token: cloudflare(env.secret(ENV_CF_API_TOKEN).map(|v| v.to_string()))?,
+            cf_domain: cloudflare(env.var(ENV_CF_DOMAIN).map(|v| v.to_string()))?,
+            strict_dns_update: env
+                .var(ENV_STRICT_DNS_UPDATE)
+                .is_ok_and(|v| Self::parse_flag(&v.to_string())),
+            dns_provider,
+            provider_hosts,
+            powerdns,
+            route53,
+            rfc2136,
+            dns_ttl,
+            dns_revalidate_interval,
+            dns_write_interval,
+            heartbeat_timeout,
+            heartbeat_thresholds,
+            alert_webhook_url: env.var(ENV_ALERT_WEBHOOK_URL).ok().map(|v| v.to_string()),
+            webhooks,
+            webhook_secret: env.secret(ENV_WEBHOOK_SECRET).ok().map(|s| s.to_string()),
+            url_signing_key: env.secret(ENV_URL_SIGNING_KEY).ok().map(|s| s.to_string()),
+            access,
+            ip_access,
+            rate_limits,
+            auth_lockout,
+            reserved_names,
+            quarantine,
         })
     }
+
+    /// Split `host`, `host:port` or `[v6addr]:port` into host and port
+    pub fn parse_server(value: &str) -> Result<(String, u16)> {
+        let value = value.trim();
+        let invalid = || Error::RustError(format!("invalid {}: {}", ENV_RFC2136_SERVER, value));
+
+        let (host, port) = match value.strip_prefix('[') {
+            Some(rest) => {
+                let (host, rest) = rest.split_once(']').ok_or_else(invalid)?;
+                match rest {
+                    "" => (host, None),
+                    _ => (host, Some(rest.strip_prefix(':').ok_or_else(invalid)?)),
+                }
+            }
+            // A bare IPv6 address has several colons and no port
+            None => match value.split_once(':') {
+                Some((host, port)) if !port.contains(':') => (host, Some(port)),
+                _ => (value, None),
+            },
+        };
+
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None => DEFAULT_DNS_PORT,
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok((host.to_string(), port))
+    }
+
+    /// Parse `pattern=provider` pairs, e.g. `lab=powerdns,nas-*=powerdns`
+    pub fn parse_provider_hosts(value: &str) -> Result<Vec<(String, ProviderKind)>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (pattern, provider) = entry.split_once('=').ok_or_else(|| {
+                    Error::RustError(format!(
+                        "invalid {} entry: {}",
+                        ENV_DNS_PROVIDER_HOSTS, entry
+                    ))
+                })?;
+                let kind = ProviderKind::parse(provider).ok_or_else(|| {
+                    Error::RustError(format!("unknown DNS provider: {}", provider.trim()))
+                })?;
+                Ok((pattern.trim().to_string(), kind))
+            })
+            .collect()
+    }
+
+    /// Parse `pattern=seconds` pairs, e.g. `site-*=3600,home=600`
+    pub fn parse_heartbeat_thresholds(value: &str) -> Result<Vec<(String, u64)>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let invalid = || {
+                    Error::RustError(format!(
+                        "invalid {} entry: {}",
+                        ENV_HEARTBEAT_THRESHOLDS, entry
+                    ))
+                };
+                let (pattern, seconds) = entry.split_once('=').ok_or_else(invalid)?;
+                let seconds = seconds.trim().parse().map_err(|_| invalid())?;
+                Ok((pattern.trim().to_string(), seconds))
+            })
+            .collect()
+    }
+
+    /// Parse the JSON list of webhook targets, e.g. `[{"url": "...", "preset": "slack"}]`
+    pub fn parse_webhooks(value: &str) -> Result<Vec<WebhookTarget>> {
+        serde_json::from_str(value)
+            .map_err(|e| Error::RustError(format!("invalid {}: {}", ENV_WEBHOOKS, e)))
+    }
+
+    /// Parse `identity=host|host` pairs, e.g. `alice@example.com=home|vpn,*.access=cam-*`
+    pub fn parse_access_identities(value: &str) -> Result<Vec<(String, Vec<String>)>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (identity, hosts) = entry.split_once('=').ok_or_else(|| {
+                    Error::RustError(format!(
+                        "invalid {} entry: {}",
+                        ENV_ACCESS_IDENTITIES, entry
+                    ))
+                })?;
+                let hosts = hosts
+                    .split('|')
+                    .map(str::trim)
+                    .filter(|host| !host.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                Ok((identity.trim().to_string(), hosts))
+            })
+            .collect()
+    }
+
+    /// Parse a comma separated CIDR list, e.g. `203.0.113.0/24,2001:db8::/32`
+    pub fn parse_cidrs(name: &str, value: &str) -> Result<Vec<Cidr>> {
+        Self::parse_list(value)
+            .iter()
+            .map(|entry| {
+                Cidr::parse(entry)
+                    .ok_or_else(|| Error::RustError(format!("invalid {} entry: {}", name, entry)))
+            })
+            .collect()
+    }
+
+    /// Parse `homename=cidr|cidr` pairs, e.g. `office-*=203.0.113.0/24|2001:db8::/32`
+    pub fn parse_host_cidrs(name: &str, value: &str) -> Result<Vec<(String, Vec<Cidr>)>> {
+        Self::parse_list(value)
+            .iter()
+            .map(|entry| {
+                let invalid = || Error::RustError(format!("invalid {} entry: {}", name, entry));
+                let (pattern, cidrs) = entry.split_once('=').ok_or_else(invalid)?;
+                let cidrs = cidrs
+                    .split('|')
+                    .map(str::trim)
+                    .filter(|cidr| !cidr.is_empty())
+                    .map(|cidr| Cidr::parse(cidr).ok_or_else(invalid))
+                    .collect::<Result<_>>()?;
+                Ok((pattern.trim().to_string(), cidrs))
+            })
+            .collect()
+    }
+
+    /// Parse `kind=requests/seconds` pairs, e.g. `ip=60/60,token=120/60,homename=10/60`
+    pub fn parse_rate_limits(value: &str) -> Result<RateLimits> {
+        let mut limits = RateLimits::default();
+        for entry in Self::parse_list(value) {
+            let invalid =
+                || Error::RustError(format!("invalid {} entry: {}", ENV_RATE_LIMITS, entry));
+            let (kind, limit) = entry.split_once('=').ok_or_else(invalid)?;
+            let kind = RateKind::parse(kind).ok_or_else(invalid)?;
+            let limit = RateLimit::parse(limit).ok_or_else(invalid)?;
+            limits.set(kind, limit);
+        }
+        Ok(limits)
+    }
+
+    /// Split a comma separated list, dropping empty entries
+    pub fn parse_list(value: &str) -> Vec<String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Silence in seconds after which the given homename is reported down
+    ///
+    /// `None` means the homename is not monitored.
+    pub fn heartbeat_threshold(&self, homename: &str) -> Option<u64> {
+        self.heartbeat_thresholds
+            .iter()
+            .find(|(pattern, _)| Self::glob_match(pattern, homename))
+            .map(|(_, seconds)| *seconds)
+            .or(self.heartbeat_timeout)
+    }
+
+    /// Parse `QUARANTINE_RULES`, e.g. `country,range,changes=3`
+    pub fn parse_risk_rules(value: &str) -> Result<Vec<RiskRule>> {
+        Self::parse_list(value)
+            .iter()
+            .map(|rule| {
+                RiskRule::parse(rule).ok_or_else(|| {
+                    Error::RustError(format!("invalid {}: {}", ENV_QUARANTINE_RULES, rule))
+                })
+            })
+            .collect()
+    }
+
+    /// Whether the homename matches a reserved name pattern
+    pub fn is_reserved(&self, homename: &str) -> bool {
+        self.reserved_names
+            .iter()
+            .any(|pattern| Self::glob_match(pattern, homename))
+    }
+
+    /// Backend serving the given homename
+    pub fn provider_for(&self, homename: &str) -> ProviderKind {
+        self.provider_hosts
+            .iter()
+            .find(|(pattern, _)| Self::glob_match(pattern, homename))
+            .map(|(_, kind)| *kind)
+            .unwrap_or(self.dns_provider)
+    }
+
+    /// Domain appended to homenames served by the given backend
+    pub fn domain_for(&self, kind: ProviderKind) -> &str {
+        match kind {
+            ProviderKind::Cloudflare => &self.cf_domain,
+            ProviderKind::PowerDns => self
+                .powerdns
+                .as_ref()
+                .map(|pdns| pdns.zone.trim_end_matches('.'))
+                .unwrap_or_default(),
+            ProviderKind::Route53 => self
+                .route53
+                .as_ref()
+                .map(|r53| r53.domain.trim_end_matches('.'))
+                .unwrap_or_default(),
+            ProviderKind::Rfc2136 => self
+                .rfc2136
+                .as_ref()
+                .map(|rfc2136| rfc2136.zone.trim_end_matches('.'))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// All domains homenames may live under, default backend first
+    pub fn domains(&self) -> Vec<&str> {
+        let mut domains: Vec<&str> = Vec::new();
+        let kinds = std::iter::once(self.dns_provider)
+            .chain(self.provider_hosts.iter().map(|(_, kind)| *kind));
+        for domain in kinds.map(|kind| self.domain_for(kind)) {
+            if !domain.is_empty() && !domains.contains(&domain) {
+                domains.push(domain);
+            }
+        }
+        domains
+    }
+
+    /// Case-insensitive glob match supporting `*` (any run) and `?` (one character)
+    pub fn glob_match(pattern: &str, value: &str) -> bool {
+        let pattern: Vec<char> = pattern.to_ascii_lowercase().chars().collect();
+        let value: Vec<char> = value.to_ascii_lowercase().chars().collect();
+        let (mut p, mut v) = (0, 0);
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        while v < value.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    backtrack = Some((p, v));
+                    p += 1;
+                }
+                Some(&c) if c == '?' || c == value[v] => {
+                    p += 1;
+                    v += 1;
+                }
+                _ => match backtrack {
+                    Some((star_p, star_v)) => {
+                        p = star_p + 1;
+                        v = star_v + 1;
+                        backtrack = Some((star_p, star_v + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        pattern[p..].iter().all(|&c| c == '*')
+    }
+
+    /// Interprets a boolean flag from an environment variable or query parameter
+    pub fn parse_flag(value: &str) -> bool {
+        matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::WebhookPreset;
+
+    #[test]
+    fn flag_parsing() {
+        let test_cases = vec![
+            ("true", true),
+            ("TRUE", true),
+            ("1", true),
+            ("yes", true),
+            (" on ", true),
+            ("false", false),
+            ("0", false),
+            ("", false),
+            ("maybe", false),
+        ];
+
+        for (input, expected) in test_cases {
+            assert_eq!(Config::parse_flag(input), expected, "Failed: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn glob_matching() {
+        let test_cases = vec![
+            ("home", "home", true, "exact match"),
+            ("home", "Home", true, "case insensitive"),
+            ("home", "home2", false, "no implicit prefix match"),
+            ("*", "anything", true, "star matches all"),
+            ("cam-*", "cam-garden", true, "prefix glob"),
+            ("cam-*", "cam-", true, "star matches empty"),
+            ("cam-*", "camera", false, "prefix glob mismatch"),
+            ("*.lab", "nas.lab", true, "suffix glob"),
+            ("a*b*c", "axxbyyc", true, "multiple stars"),
+            ("a*b*c", "axxbyy", false, "multiple stars mismatch"),
+            ("host?", "host1", true, "single character wildcard"),
+            (
+                "host?",
+                "host12",
+                false,
+                "single character wildcard too long",
+            ),
+            ("", "", true, "empty pattern matches empty"),
+            ("", "x", false, "empty pattern matches nothing else"),
+        ];
+
+        for (pattern, value, expected, description) in test_cases {
+            assert_eq!(
+                Config::glob_match(pattern, value),
+                expected,
+                "Failed: {}",
+                description
+            );
+        }
+    }
+
+    #[test]
+    fn provider_hosts_parsing() {
+        let hosts =
+            Config::parse_provider_hosts(" lab=powerdns, nas-*=PDNS ,vpn=cloudflare,").unwrap();
+        assert_eq!(
+            hosts,
+            vec![
+                ("lab".to_string(), ProviderKind::PowerDns),
+                ("nas-*".to_string(), ProviderKind::PowerDns),
+                ("vpn".to_string(), ProviderKind::Cloudflare),
+            ]
+        );
+        assert_eq!(
+            Config::parse_provider_hosts("aws-*=route53,lab=nsupdate").unwrap(),
+            vec![
+                ("aws-*".to_string(), ProviderKind::Route53),
+                ("lab".to_string(), ProviderKind::Rfc2136),
+            ]
+        );
+        assert!(Config::parse_provider_hosts("lab").is_err());
+        assert!(Config::parse_provider_hosts("lab=bind").is_err());
+    }
+
+    #[test]
+    fn provider_routing() {
+        let config = Config {
+            cf_domain: "example.com".to_string(),
+            provider_hosts: vec![
+                ("vpn".to_string(), ProviderKind::Cloudflare),
+                ("*".to_string(), ProviderKind::PowerDns),
+            ],
+            powerdns: Some(PowerDnsConfig {
+                api_url: "http://127.0.0.1:8081".to_string(),
+                api_key: "key".to_string(),
+                server_id: DEFAULT_PDNS_SERVER_ID.to_string(),
+                zone: "internal.example.".to_string(),
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(config.provider_for("vpn"), ProviderKind::Cloudflare);
+        assert_eq!(config.provider_for("nas"), ProviderKind::PowerDns);
+        assert_eq!(
+            config.domain_for(ProviderKind::PowerDns),
+            "internal.example"
+        );
+        assert_eq!(config.domains(), vec!["example.com", "internal.example"]);
+
+        let default_only = Config {
+            cf_domain: "example.com".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(default_only.provider_for("nas"), ProviderKind::Cloudflare);
+        assert_eq!(default_only.domain_for(ProviderKind::PowerDns), "");
+        assert_eq!(default_only.domains(), vec!["example.com"]);
+
+        let route53 = Config {
+            dns_provider: ProviderKind::Route53,
+            route53: Some(Route53Config {
+                api_url: DEFAULT_ROUTE53_API_URL.to_string(),
+                access_key_id: "AKIDEXAMPLE".to_string(),
+                secret_access_key: "secret".to_string(),
+                region: DEFAULT_AWS_REGION.to_string(),
+                hosted_zone_id: "Z0123456789".to_string(),
+                domain: "aws.example.com.".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(route53.provider_for("nas"), ProviderKind::Route53);
+        assert_eq!(route53.domains(), vec!["aws.example.com"]);
+
+        let rfc2136 = Config {
+            cf_domain: "example.com".to_string(),
+            provider_hosts: vec![("lab-*".to_string(), ProviderKind::Rfc2136)],
+            rfc2136: Some(Rfc2136Config {
+                host: "ns1.example.net".to_string(),
+                port: DEFAULT_DNS_PORT,
+                zone: "lab.example.net".to_string(),
+                key_name: "ddns-key".to_string(),
+                key_secret: "c2VjcmV0".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(rfc2136.provider_for("lab-nas"), ProviderKind::Rfc2136);
+        assert_eq!(rfc2136.domains(), vec!["example.com", "lab.example.net"]);
+    }
+
+    #[test]
+    fn heartbeat_thresholds() {
+        let config = Config {
+            heartbeat_timeout: Some(900),
+            heartbeat_thresholds: Config::parse_heartbeat_thresholds(" site-*=3600, home = 600 ,")
+                .unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(config.heartbeat_threshold("site-berlin"), Some(3600));
+        assert_eq!(config.heartbeat_threshold("home"), Some(600));
+        assert_eq!(config.heartbeat_threshold("nas"), Some(900));
+        assert_eq!(Config::default().heartbeat_threshold("nas"), None);
+
+        assert!(Config::parse_heartbeat_thresholds("home").is_err());
+        assert!(Config::parse_heartbeat_thresholds("home=soon").is_err());
+    }
+
+    #[test]
+    fn webhooks_parsing() {
+        let webhooks = Config::parse_webhooks(
+            r#"[
+                {"url": "https://hooks.example.com/dns"},
+                {"url": "https://ntfy.sh/dns", "preset": "ntfy", "hosts": ["site-*"], "secret": "s"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(webhooks.len(), 2);
+        assert_eq!(webhooks[0].preset, WebhookPreset::Json);
+        assert!(webhooks[0].hosts.is_empty());
+        assert_eq!(webhooks[1].preset, WebhookPreset::Ntfy);
+        assert_eq!(webhooks[1].hosts, vec!["site-*".to_string()]);
+        assert_eq!(webhooks[1].secret.as_deref(), Some("s"));
+
+        assert!(Config::parse_webhooks(r#"[{"url": "x", "preset": "teams"}]"#).is_err());
+        assert!(Config::parse_webhooks("https://hooks.example.com").is_err());
+    }
+
+    #[test]
+    fn access_identities_parsing() {
+        let identities =
+            Config::parse_access_identities("alice@example.com=home|vpn, *.access = cam-* ,")
+                .unwrap();
+        assert_eq!(
+            identities,
+            vec![
+                (
+                    "alice@example.com".to_string(),
+                    vec!["home".to_string(), "vpn".to_string()]
+                ),
+                ("*.access".to_string(), vec!["cam-*".to_string()]),
+            ]
+        );
+        assert!(Config::parse_access_identities("alice@example.com").is_err());
+        assert_eq!(
+            Config::parse_list(" admin@example.com,,*@ops.example.com "),
+            vec!["admin@example.com", "*@ops.example.com"]
+        );
+    }
+
+    #[test]
+    fn cidr_list_parsing() {
+        let cidrs = Config::parse_cidrs(ENV_IP_ALLOW, "203.0.113.0/24, 2001:db8::/32,").unwrap();
+        assert_eq!(
+            cidrs.iter().map(Cidr::to_string).collect::<Vec<_>>(),
+            vec!["203.0.113.0/24", "2001:db8::/32"]
+        );
+        assert!(Config::parse_cidrs(ENV_IP_ALLOW, "203.0.113.0/24,office").is_err());
+
+        let hosts =
+            Config::parse_host_cidrs(ENV_IP_ALLOW_HOSTS, "office-*=203.0.113.0/24|192.0.2.1")
+                .unwrap();
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].0, "office-*");
+        assert_eq!(hosts[0].1[1].to_string(), "192.0.2.1/32");
+        assert!(Config::parse_host_cidrs(ENV_IP_DENY_HOSTS, "office-*").is_err());
+        assert!(Config::parse_host_cidrs(ENV_IP_DENY_HOSTS, "office-*=10.0.0.0/40").is_err());
+    }
+
+    #[test]
+    fn rate_limit_parsing() {
+        let limits = Config::parse_rate_limits("ip=60/60, homename = 10/3600").unwrap();
+        assert_eq!(
+            limits.ip,
+            Some(RateLimit {
+                requests: 60,
+                window_secs: 60
+            })
+        );
+        assert_eq!(limits.token, None);
+        assert_eq!(limits.homename.map(|l| l.window_secs), Some(3600));
+
+        for invalid in ["ip", "device=1/60", "token=1/0"] {
+            assert!(Config::parse_rate_limits(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn risk_rule_parsing() {
+        assert_eq!(
+            Config::parse_risk_rules(DEFAULT_QUARANTINE_RULES).unwrap(),
+            vec![
+                RiskRule::NewCountry,
+                RiskRule::NewAsn,
+                RiskRule::NewRange,
+                RiskRule::ChangesPerDay(5),
+            ]
+        );
+        assert_eq!(Config::parse_risk_rules("").unwrap(), vec![]);
+        assert!(Config::parse_risk_rules("country,city").is_err());
+    }
+
+    #[test]
+    fn reserved_names() {
+        let config = Config {
+            reserved_names: Config::parse_list("www, mail,_*"),
+            ..Default::default()
+        };
+        let test_cases = vec![
+            ("www", true, "exact name"),
+            ("WWW", true, "case insensitive"),
+            ("_acme-challenge", true, "glob pattern"),
+            ("home", false, "unreserved name"),
+            ("www2", false, "no implicit prefix match"),
+        ];
+
+        for (homename, expected, description) in test_cases {
+            assert_eq!(
+                config.is_reserved(homename),
+                expected,
+                "Failed: {}",
+                description
+            );
+        }
+        assert!(!Config::default().is_reserved("www"));
+    }
+
+    #[test]
+    fn server_parsing() {
+        let test_cases = vec![
+            ("ns1.example.net", Some(("ns1.example.net", 53))),
+            ("ns1.example.net:5353", Some(("ns1.example.net", 5353))),
+            ("192.0.2.1:53", Some(("192.0.2.1", 53))),
+            ("2001:db8::53", Some(("2001:db8::53", 53))),
+            ("[2001:db8::53]", Some(("2001:db8::53", 53))),
+            ("[2001:db8::53]:5353", Some(("2001:db8::53", 5353))),
+            ("ns1.example.net:dns", None),
+            ("[2001:db8::53]5353", None),
+            (":53", None),
+        ];
+
+        for (input, expected) in test_cases {
+            let parsed = Config::parse_server(input).ok();
+            assert_eq!(
+                parsed,
+                expected.map(|(host, port)| (host.to_string(), port)),
+                "Failed: {:?}",
+                input
+            );
+        }
+    }
 }
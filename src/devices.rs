@@ -0,0 +1,521 @@
+use crate::auth::Scope;
+use crate::store::KeyValueStore;
+use crate::tokens::{Credential, touch_last_used};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use worker::*;
+
+// Constants
+pub const ADMIN_DEVICES_PATH: &str = "/admin/devices";
+pub const ENROLL_PATH: &str = "/enroll";
+const DEVICE_KEY_PREFIX: &str = "device:";
+const SIGNATURE_KEY_PREFIX: &str = "devsig:";
+const SIGNATURE_SEPARATOR: char = '.';
+const DEVICE_ID_BYTES: usize = 8;
+/// Pending enrollments nobody approved are dropped after a week
+const PENDING_TTL_SECS: u64 = 7 * 24 * 3600;
+/// Accepted difference between the signed timestamp and the worker clock
+const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+/// Lifecycle of an enrolled device
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceStatus {
+    /// Enrolled, waiting for an admin
+    Pending,
+    /// Signed requests are accepted
+    Approved,
+    /// Signed requests are rejected and the key cannot enroll again
+    Revoked,
+}
+
+/// A device public key stored in KV under `device:{id}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceRecord {
+    /// Human readable name chosen at enrollment
+    pub name: String,
+    /// Base64 Ed25519 public key
+    pub public_key: String,
+    /// Homenames or glob patterns the device may update
+    pub hosts: Vec<String>,
+    #[serde(default = "Scope::defaults")]
+    pub scopes: Vec<Scope>,
+    pub status: DeviceStatus,
+    /// Unix time of enrollment in milliseconds
+    pub created: u64,
+    /// Unix time of approval in milliseconds
+    #[serde(default)]
+    pub approved: Option<u64>,
+    /// Unix time of the last verified request in milliseconds
+    #[serde(default)]
+    pub last_used: Option<u64>,
+}
+
+/// Device as returned by the enrollment and admin APIs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceInfo {
+    pub id: String,
+    #[serde(flatten)]
+    pub record: DeviceRecord,
+}
+
+/// Body a device posts to enroll its public key
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnrollRequest {
+    pub name: String,
+    /// Base64 Ed25519 public key
+    pub public_key: String,
+    /// Homenames the device asks to update
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+/// Body of an approval, overriding what the device asked for
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ApproveRequest {
+    #[serde(default)]
+    pub hosts: Option<Vec<String>>,
+    #[serde(default)]
+    pub scopes: Option<Vec<Scope>>,
+}
+
+/// Enrolled device keys kept in KV
+pub struct DeviceStore<'a> {
+    kv: &'a dyn KeyValueStore,
+}
+
+impl<'a> DeviceStore<'a> {
+    /// Create a device store over the given KV store
+    pub fn new(kv: &'a dyn KeyValueStore) -> Self {
+        Self { kv }
+    }
+
+    fn device_key(id: &str) -> String {
+        format!("{}{}", DEVICE_KEY_PREFIX, id)
+    }
+
+    /// Decode a base64 Ed25519 public key
+    pub fn parse_public_key(public_key: &str) -> Option<VerifyingKey> {
+        let bytes: [u8; 32] = BASE64.decode(public_key.trim()).ok()?.try_into().ok()?;
+        VerifyingKey::from_bytes(&bytes).ok()
+    }
+
+    /// Device id derived from the key, so enrolling the same key twice finds the same device
+    pub fn device_id(key: &VerifyingKey) -> String {
+        hex::encode(&Sha256::digest(key.as_bytes())[..DEVICE_ID_BYTES])
+    }
+
+    /// Text a device signs: method, path, canonical query and Unix time in seconds, one per line
+    pub fn signing_message(method: &str, path: &str, query: &str, timestamp: u64) -> String {
+        format!("{}\n{}\n{}\n{}", method, path, query, timestamp)
+    }
+
+    /// Query parameters sorted by name then value and form-urlencoded
+    ///
+    /// Signing every parameter, not just the homename, keeps an intercepted request
+    /// from being forwarded with other parameters while its timestamp is fresh.
+    pub fn canonical_query(url: &Url) -> String {
+        let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        pairs.sort();
+        let mut canonical = url.clone();
+        canonical.query_pairs_mut().clear().extend_pairs(pairs);
+        canonical.query().unwrap_or_default().to_string()
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<DeviceRecord>> {
+        match self.kv.get(&Self::device_key(id)).await? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, id: &str, record: &DeviceRecord) -> Result<()> {
+        let value = serde_json::to_string(record)?;
+        match record.status {
+            DeviceStatus::Pending => {
+                self.kv
+                    .put_with_ttl(&Self::device_key(id), &value, PENDING_TTL_SECS)
+                    .await
+            }
+            _ => self.kv.put(&Self::device_key(id), &value).await,
+        }
+    }
+
+    /// Register a public key as pending; returns `None` when the key is malformed
+    ///
+    /// Enrolling a known key returns the existing device unchanged, so a
+    /// device can poll this endpoint until it has been approved.
+    pub async fn enroll(&self, request: EnrollRequest, now_ms: u64) -> Result<Option<DeviceInfo>> {
+        let Some(key) = Self::parse_public_key(&request.public_key) else {
+            return Ok(None);
+        };
+        let id = Self::device_id(&key);
+        if let Some(record) = self.load(&id).await? {
+            return Ok(Some(DeviceInfo { id, record }));
+        }
+
+        let record = DeviceRecord {
+            name: request.name,
+            public_key: BASE64.encode(key.as_bytes()),
+            hosts: request.hosts,
+            scopes: Scope::defaults(),
+            status: DeviceStatus::Pending,
+            created: now_ms,
+            approved: None,
+            last_used: None,
+        };
+        self.put(&id, &record).await?;
+        Ok(Some(DeviceInfo { id, record }))
+    }
+
+    /// Every enrolled device
+    pub async fn list(&self) -> Result<Vec<DeviceInfo>> {
+        let mut devices = Vec::new();
+        for key in self.kv.list_keys(DEVICE_KEY_PREFIX).await? {
+            let id = &key[DEVICE_KEY_PREFIX.len()..];
+            if let Some(record) = self.load(id).await? {
+                devices.push(DeviceInfo {
+                    id: id.to_string(),
+                    record,
+                });
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Approve a pending device or change the grants of an approved one
+    ///
+    /// Returns `None` for unknown and revoked devices.
+    pub async fn approve(
+        &self,
+        id: &str,
+        request: ApproveRequest,
+        now_ms: u64,
+    ) -> Result<Option<DeviceInfo>> {
+        let Some(mut record) = self.load(id).await? else {
+            return Ok(None);
+        };
+        if record.status == DeviceStatus::Revoked {
+            return Ok(None);
+        }
+
+        if let Some(hosts) = request.hosts {
+            record.hosts = hosts;
+        }
+        if let Some(scopes) = request.scopes {
+            record.scopes = scopes;
+        }
+        record.approved = record.approved.or(Some(now_ms));
+        record.status = DeviceStatus::Approved;
+        self.put(id, &record).await?;
+        Ok(Some(DeviceInfo {
+            id: id.to_string(),
+            record,
+        }))
+    }
+
+    /// Revoke a device, returning whether it existed
+    pub async fn revoke(&self, id: &str) -> Result<bool> {
+        let Some(mut record) = self.load(id).await? else {
+            return Ok(false);
+        };
+        record.status = DeviceStatus::Revoked;
+        self.put(id, &record).await?;
+        Ok(true)
+    }
+
+    /// Resolve a presented `{id}.{timestamp}.{signature}` to the device credential
+    ///
+    /// The base64 signature must cover [`Self::signing_message`] for this request,
+    /// the timestamp must be within five minutes of `now_ms` and each signature is
+    /// accepted once. Returns `None` otherwise.
+    pub async fn verify(
+        &self,
+        presented: &str,
+        method: &str,
+        path: &str,
+        query: &str,
+        now_ms: u64,
+    ) -> Result<Option<Credential>> {
+        let mut parts = presented.trim().splitn(3, SIGNATURE_SEPARATOR);
+        let (Some(id), Some(timestamp), Some(signature)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Ok(None);
+        };
+        let Some(timestamp) = timestamp.parse::<u64>().ok() else {
+            return Ok(None);
+        };
+        if (now_ms / 1000).abs_diff(timestamp) > MAX_CLOCK_SKEW_SECS {
+            return Ok(None);
+        }
+        let Some(signature) = BASE64
+            .decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        else {
+            return Ok(None);
+        };
+        let Some(mut record) = self
+            .load(id)
+            .await?
+            .filter(|r| r.status == DeviceStatus::Approved)
+        else {
+            return Ok(None);
+        };
+        let Some(key) = Self::parse_public_key(&record.public_key) else {
+            return Ok(None);
+        };
+
+        let message = Self::signing_message(method, path, query, timestamp);
+        if key.verify_strict(message.as_bytes(), &signature).is_err() {
+            return Ok(None);
+        }
+
+        // A captured request cannot be replayed while its timestamp is still fresh
+        let seen_key = format!(
+            "{}{}",
+            SIGNATURE_KEY_PREFIX,
+            hex::encode(signature.to_bytes())
+        );
+        if self.kv.get(&seen_key).await?.is_some() {
+            return Ok(None);
+        }
+        self.kv
+            .put_with_ttl(&seen_key, id, 2 * MAX_CLOCK_SKEW_SECS)
+            .await?;
+
+        if touch_last_used(&mut record.last_used, now_ms) {
+            self.put(id, &record).await?;
+        }
+
+        Ok(Some(Credential {
+            name: record.name,
+            owner: Some(Self::device_key(id)),
+            hosts: record.hosts,
+            scopes: record.scopes,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7; 32])
+    }
+
+    fn router() -> EnrollRequest {
+        EnrollRequest {
+            name: "router".to_string(),
+            public_key: BASE64.encode(signing_key().verifying_key().as_bytes()),
+            hosts: vec!["home".to_string()],
+        }
+    }
+
+    fn sign(id: &str, method: &str, query: &str, timestamp: u64) -> String {
+        let message = DeviceStore::signing_message(method, "/", query, timestamp);
+        let signature = signing_key().sign(message.as_bytes());
+        format!(
+            "{}.{}.{}",
+            id,
+            timestamp,
+            BASE64.encode(signature.to_bytes())
+        )
+    }
+
+    #[tokio::test]
+    async fn enrollment_requires_approval() {
+        let kv = MemoryStore::new();
+        let devices = DeviceStore::new(&kv);
+
+        let enrolled = devices.enroll(router(), NOW).await.unwrap().unwrap();
+        assert_eq!(enrolled.record.status, DeviceStatus::Pending);
+        assert_eq!(enrolled.id.len(), 2 * DEVICE_ID_BYTES);
+        let presented = sign(&enrolled.id, "GET", "homename=home", NOW / 1000);
+        let verify = devices.verify(&presented, "GET", "/", "homename=home", NOW);
+        assert_eq!(verify.await.unwrap(), None);
+
+        // Enrolling again reports the same pending device
+        let again = devices.enroll(router(), NOW + 1000).await.unwrap().unwrap();
+        assert_eq!(again, enrolled);
+
+        let approved = devices
+            .approve(&enrolled.id, ApproveRequest::default(), NOW)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(approved.record.status, DeviceStatus::Approved);
+        assert_eq!(approved.record.approved, Some(NOW));
+        let credential = devices
+            .verify(&presented, "GET", "/", "homename=home", NOW)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(credential.name, "router");
+        assert!(credential.allows("home"));
+        assert!(!credential.allows("office"));
+
+        let json = serde_json::to_value(&approved).unwrap();
+        assert_eq!(json["status"], "approved");
+        assert_eq!(json["id"], enrolled.id);
+
+        let request = EnrollRequest {
+            public_key: "not a key".to_string(),
+            ..router()
+        };
+        assert!(devices.enroll(request, NOW).await.unwrap().is_none());
+        assert_eq!(devices.list().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn signature_validation() {
+        let kv = MemoryStore::new();
+        let devices = DeviceStore::new(&kv);
+        let id = devices.enroll(router(), NOW).await.unwrap().unwrap().id;
+        devices
+            .approve(&id, ApproveRequest::default(), NOW)
+            .await
+            .unwrap();
+        let now = NOW / 1000;
+
+        let test_cases = vec![
+            (
+                sign(&id, "GET", "homename=home", now),
+                "GET",
+                "homename=home",
+                true,
+                "valid",
+            ),
+            (
+                sign(&id, "GET", "homename=home", now),
+                "GET",
+                "homename=home",
+                false,
+                "replayed",
+            ),
+            (
+                sign(&id, "GET", "homename=home", now + 1),
+                "POST",
+                "homename=home",
+                false,
+                "other method",
+            ),
+            (
+                sign(&id, "GET", "homename=home", now + 2),
+                "GET",
+                "homename=office",
+                false,
+                "other homename",
+            ),
+            (
+                sign(&id, "GET", "homename=home&myip=192.0.2.1", now + 3),
+                "GET",
+                "homename=home&myip=198.51.100.7",
+                false,
+                "other address",
+            ),
+            (
+                sign(&id, "GET", "homename=home", now - 301),
+                "GET",
+                "homename=home",
+                false,
+                "stale timestamp",
+            ),
+            (
+                sign(&id, "GET", "homename=home", now + 301),
+                "GET",
+                "homename=home",
+                false,
+                "future timestamp",
+            ),
+            (
+                sign(&id, "GET", "homename=home", now - 299),
+                "GET",
+                "homename=home",
+                true,
+                "small clock skew",
+            ),
+            (
+                sign("0000000000000000", "GET", "homename=home", now),
+                "GET",
+                "homename=home",
+                false,
+                "unknown device",
+            ),
+            (
+                format!("{}.{}", id, now),
+                "GET",
+                "homename=home",
+                false,
+                "missing signature",
+            ),
+            (
+                format!("{}.{}.AAAA", id, now),
+                "GET",
+                "homename=home",
+                false,
+                "truncated signature",
+            ),
+        ];
+
+        for (presented, method, query, expected, description) in test_cases {
+            let credential = devices
+                .verify(&presented, method, "/", query, NOW)
+                .await
+                .unwrap();
+            assert_eq!(credential.is_some(), expected, "Failed: {}", description);
+        }
+    }
+
+    #[test]
+    fn canonical_query_sorts_parameters() {
+        let test_cases = vec![
+            ("https://w.example/?homename=home", "homename=home"),
+            (
+                "https://w.example/?myip=192.0.2.1&homename=home&b=x%20y",
+                "b=x+y&homename=home&myip=192.0.2.1",
+            ),
+            ("https://w.example/?ip=2&ip=1", "ip=1&ip=2"),
+            ("https://w.example/", ""),
+        ];
+
+        for (url, expected) in test_cases {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(DeviceStore::canonical_query(&url), expected, "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn revoked_devices_stay_revoked() {
+        let kv = MemoryStore::new();
+        let devices = DeviceStore::new(&kv);
+        let id = devices.enroll(router(), NOW).await.unwrap().unwrap().id;
+        let approve = ApproveRequest {
+            hosts: Some(vec!["home".to_string(), "cam-*".to_string()]),
+            scopes: None,
+        };
+        let approved = devices.approve(&id, approve, NOW).await.unwrap().unwrap();
+        assert_eq!(approved.record.hosts, vec!["home", "cam-*"]);
+
+        assert!(devices.revoke(&id).await.unwrap());
+        assert!(!devices.revoke("unknown").await.unwrap());
+        let presented = sign(&id, "GET", "homename=home", NOW / 1000);
+        let verify = devices.verify(&presented, "GET", "/", "homename=home", NOW);
+        assert_eq!(verify.await.unwrap(), None);
+
+        // Neither approval nor enrolling the key again brings it back
+        let approve = devices.approve(&id, ApproveRequest::default(), NOW);
+        assert!(approve.await.unwrap().is_none());
+        let enrolled = devices.enroll(router(), NOW).await.unwrap().unwrap();
+        assert_eq!(enrolled.record.status, DeviceStatus::Revoked);
+    }
+}
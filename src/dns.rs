@@ -1,26 +1,55 @@
+use crate::store::KeyValueStore;
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use worker::*;
 
+mod cloudflare;
+mod memory;
+mod powerdns;
+mod rfc2136;
+mod route53;
+mod sigv4;
+mod tsig;
+mod wire;
+
+pub use cloudflare::CloudflareProvider;
+pub use memory::{MemoryProvider, MemoryRecord};
+pub use powerdns::PowerDnsProvider;
+pub use rfc2136::{DnsTransport, Rfc2136Provider, WorkerTcpTransport};
+pub use route53::Route53Provider;
+pub use sigv4::SigV4Signer;
+pub use tsig::TsigKey;
+
 // Constants for better maintainability
-const CLOUDFLARE_API_BASE: &str = "https://api.cloudflare.com/client/v4";
-const DNS_TTL: u32 = 60;
-const CONTENT_TYPE_JSON: &str = "application/json";
+pub const DEFAULT_DNS_TTL: u32 = 60;
+const NO_ADDRESS_REASON: &str = "no address provided";
+const NOT_PUBLISHED_REASON: &str = "no address published yet";
+const DNS_RECORD_KEY_SUFFIX: &str = "_dns_record_id";
+const WRITTEN_KEY_PREFIX: &str = "written:";
+const MANAGED_KEY_PREFIX: &str = "managed:";
+/// Marks that homenames cached before the managed index existed were indexed
+const MANAGED_BACKFILL_KEY: &str = "backfill:managed";
+const CACHED_RECORD_TYPES: [RecordType; 3] = [RecordType::A, RecordType::AAAA, RecordType::TXT];
 
 /// DNS record types supported by this implementation
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum RecordType {
     /// IPv4 address record
     A,
     /// IPv6 address record  
     AAAA,
+    /// Text record
+    TXT,
 }
 
 impl RecordType {
-    fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             RecordType::A => "A",
             RecordType::AAAA => "AAAA",
+            RecordType::TXT => "TXT",
         }
     }
 }
@@ -32,27 +61,293 @@ pub enum DnsError {
     SerializationError(String),
     NotFound,
     InvalidInput(String),
+    /// A record exists that this worker neither created nor was asked to adopt
+    Unmanaged(String),
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::ApiError(msg) => write!(f, "DNS API error: {}", msg),
+            DnsError::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
+            DnsError::NotFound => write!(f, "DNS record not found"),
+            DnsError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            DnsError::Unmanaged(record) => write!(
+                f,
+                "{} exists and is not managed by this worker, set adopt to take it over",
+                record
+            ),
+        }
+    }
 }
 
 impl From<DnsError> for Error {
     fn from(err: DnsError) -> Self {
-        match err {
-            DnsError::ApiError(msg) => Error::RustError(format!("DNS API error: {}", msg)),
-            DnsError::SerializationError(msg) => {
-                Error::RustError(format!("Serialization error: {}", msg))
-            }
-            DnsError::NotFound => Error::RustError("DNS record not found".to_string()),
-            DnsError::InvalidInput(msg) => Error::RustError(format!("Invalid input: {}", msg)),
+        Error::RustError(err.to_string())
+    }
+}
+
+impl From<Error> for DnsError {
+    fn from(err: Error) -> Self {
+        DnsError::ApiError(err.to_string())
+    }
+}
+
+/// Result type returned by DNS providers
+pub type DnsResult<T> = std::result::Result<T, DnsError>;
+
+/// A DNS record as seen by a provider
+#[derive(Clone, Debug, PartialEq)]
+pub struct DnsRecord {
+    /// Provider-specific record identifier
+    pub id: String,
+    /// Published record content
+    pub content: String,
+    /// Whether the record carries this worker's marker
+    ///
+    /// Providers that cannot store a marker report `false`; their records are
+    /// recognised by the ids cached when this worker created them.
+    pub managed: bool,
+}
+
+/// Backend capable of managing records in a DNS zone
+///
+/// `DnsManager` drives a provider; implementations only translate each call to their API.
+#[async_trait(?Send)]
+pub trait DnsProvider {
+    /// Find an existing record by fully-qualified name and type
+    async fn find_record(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<DnsRecord>>;
+
+    /// Create a record and return its identifier
+    async fn create_record(
+        &self,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<String>;
+
+    /// Replace the content of an existing record
+    ///
+    /// Returns `DnsError::NotFound` if the record no longer exists.
+    async fn update_record(
+        &self,
+        record_id: &str,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<()>;
+
+    /// Delete an existing record
+    async fn delete_record(
+        &self,
+        record_id: &str,
+        record_type: RecordType,
+        name: &str,
+    ) -> DnsResult<()>;
+}
+
+/// Status of a single record after an update attempt
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordStatus {
+    /// Record did not exist and was created
+    Created,
+    /// Record existed and was changed
+    Updated,
+    /// Record already held the requested content
+    Unchanged,
+    /// No content was provided for this record type
+    Skipped,
+    /// The record was written too recently, the new content was not applied
+    Throttled,
+    /// The change was held for an admin to approve, the new content was not applied
+    Pending,
+    /// A record this worker does not manage exists and was left untouched
+    Refused,
+    /// The update failed, see the reason
+    Error,
+}
+
+impl RecordStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordStatus::Created => "created",
+            RecordStatus::Updated => "updated",
+            RecordStatus::Unchanged => "unchanged",
+            RecordStatus::Skipped => "skipped",
+            RecordStatus::Throttled => "throttled",
+            RecordStatus::Pending => "pending",
+            RecordStatus::Refused => "refused",
+            RecordStatus::Error => "error",
         }
     }
 }
 
-/// Cloudflare DNS record identifiers stored in KV
+/// Outcome of updating a single DNS record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordOutcome {
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    pub status: RecordStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl RecordOutcome {
+    fn new(record_type: RecordType, status: RecordStatus) -> Self {
+        Self {
+            record_type,
+            status,
+            record_id: None,
+            previous_ip: None,
+            reason: None,
+        }
+    }
+
+    fn skipped(record_type: RecordType, reason: &str) -> Self {
+        Self {
+            reason: Some(reason.to_string()),
+            ..Self::new(record_type, RecordStatus::Skipped)
+        }
+    }
+
+    fn throttled(record_type: RecordType, wait_secs: u64) -> Self {
+        Self {
+            reason: Some(format!("record written recently, retry in {}s", wait_secs)),
+            ..Self::new(record_type, RecordStatus::Throttled)
+        }
+    }
+
+    fn error(record_type: RecordType, record_id: Option<String>, reason: String) -> Self {
+        Self {
+            record_id,
+            reason: Some(reason),
+            ..Self::new(record_type, RecordStatus::Error)
+        }
+    }
+
+    /// Failed record, refused rather than errored when it is not this worker's to change
+    fn failure(record_type: RecordType, record_id: Option<String>, err: &DnsError) -> Self {
+        let status = match err {
+            DnsError::Unmanaged(_) => RecordStatus::Refused,
+            _ => RecordStatus::Error,
+        };
+        Self {
+            status,
+            ..Self::error(record_type, record_id, err.to_string())
+        }
+    }
+
+    /// Whether the record was written
+    pub fn changed(&self) -> bool {
+        matches!(self.status, RecordStatus::Created | RecordStatus::Updated)
+    }
+}
+
+/// Outcome of a DNS update across all record types
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsUpdateOutcome {
+    pub records: Vec<RecordOutcome>,
+}
+
+impl DnsUpdateOutcome {
+    /// Builds an outcome where every record failed for the same reason
+    pub fn failed(record_types: &[RecordType], reason: &str) -> Self {
+        Self {
+            records: record_types
+                .iter()
+                .map(|&t| RecordOutcome::error(t, None, reason.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Builds an outcome where every requested address waits for approval
+    ///
+    /// `contents` maps each record type to the content that was requested.
+    pub fn held(contents: &[(RecordType, &str)], reason: &str) -> Self {
+        Self {
+            records: contents
+                .iter()
+                .map(|&(record_type, content)| match content {
+                    "" => RecordOutcome::skipped(record_type, NO_ADDRESS_REASON),
+                    _ => RecordOutcome {
+                        reason: Some(reason.to_string()),
+                        ..RecordOutcome::new(record_type, RecordStatus::Pending)
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether at least one record was written
+    pub fn changed(&self) -> bool {
+        self.records.iter().any(RecordOutcome::changed)
+    }
+
+    /// Whether the change was held for approval
+    pub fn is_held(&self) -> bool {
+        self.records
+            .iter()
+            .any(|r| r.status == RecordStatus::Pending)
+    }
+
+    /// Whether at least one record failed to update or was refused
+    pub fn has_errors(&self) -> bool {
+        self.records
+            .iter()
+            .any(|r| matches!(r.status, RecordStatus::Error | RecordStatus::Refused))
+    }
+
+    /// Whether a record was refused because this worker does not manage it
+    ///
+    /// Retrying cannot help, so protocol clients are told the host is not theirs.
+    pub fn is_refused(&self) -> bool {
+        self.records
+            .iter()
+            .any(|r| r.status == RecordStatus::Refused)
+    }
+
+    /// Collapses the outcome into whether anything changed, or the first failure
+    ///
+    /// Throttled records count as failures so protocol clients retry later. Held
+    /// records count as unchanged: retrying cannot release them, an admin must.
+    pub fn into_result(self) -> Result<bool> {
+        match self.records.iter().find(|r| {
+            matches!(
+                r.status,
+                RecordStatus::Error | RecordStatus::Refused | RecordStatus::Throttled
+            )
+        }) {
+            Some(record) => Err(DnsError::ApiError(format!(
+                "{} record: {}",
+                record.record_type.as_str(),
+                record.reason.as_deref().unwrap_or_default()
+            ))
+            .into()),
+            None => Ok(self.changed()),
+        }
+    }
+}
+
+/// DNS record identifiers stored in KV
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DnsRecordInfo {
     pub record_name: String,
     pub a_id: Option<String>,
     pub aaaa_id: Option<String>,
+    #[serde(default)]
+    pub txt_id: Option<String>,
+    /// When the cached ids and contents were last checked against the provider (ms)
+    #[serde(default)]
+    pub validated_at: Option<u64>,
 }
 
 impl DnsRecordInfo {
@@ -61,6 +356,8 @@ impl DnsRecordInfo {
             record_name,
             a_id: None,
             aaaa_id: None,
+            txt_id: None,
+            validated_at: None,
         }
     }
 
@@ -68,327 +365,494 @@ impl DnsRecordInfo {
         match record_type {
             RecordType::A => self.a_id.as_ref(),
             RecordType::AAAA => self.aaaa_id.as_ref(),
+            RecordType::TXT => self.txt_id.as_ref(),
         }
     }
 
     fn set_id(&mut self, record_type: RecordType, id: String) {
-        match record_type {
-            RecordType::A => self.a_id = Some(id),
-            RecordType::AAAA => self.aaaa_id = Some(id),
-        }
+        *self.id_mut(record_type) = Some(id);
     }
-}
-
-/// Cloudflare API response for DNS record creation
-#[derive(Deserialize)]
-struct CreateDnsResponse {
-    success: bool,
-    result: Option<DnsRecord>,
-    errors: Option<Vec<ApiError>>,
-}
 
-/// Cloudflare API response for listing DNS records
-#[derive(Deserialize)]
-struct ListDnsResponse {
-    success: bool,
-    result: Option<Vec<DnsRecord>>,
-    errors: Option<Vec<ApiError>>,
-}
+    fn take_id(&mut self, record_type: RecordType) -> Option<String> {
+        self.id_mut(record_type).take()
+    }
 
-/// Cloudflare API response for updating DNS records
-#[derive(Deserialize)]
-struct UpdateDnsResponse {
-    success: bool,
-    errors: Option<Vec<ApiError>>,
+    fn id_mut(&mut self, record_type: RecordType) -> &mut Option<String> {
+        match record_type {
+            RecordType::A => &mut self.a_id,
+            RecordType::AAAA => &mut self.aaaa_id,
+            RecordType::TXT => &mut self.txt_id,
+        }
+    }
 }
 
-/// Cloudflare API error structure
-#[derive(Deserialize, Debug)]
-struct ApiError {
-    #[allow(dead_code)]
-    code: u32,
-    #[allow(dead_code)]
-    message: String,
+/// Periodic check of cached record state against the provider
+#[derive(Debug, Clone, Copy)]
+struct Revalidation {
+    interval_ms: u64,
+    now_ms: u64,
 }
 
-/// Cloudflare DNS record structure
-#[derive(Deserialize)]
-struct DnsRecord {
-    id: String,
-    #[serde(rename = "type")]
-    record_type: String,
-    name: String,
-    #[allow(dead_code)]
-    content: String,
+/// Minimum time between two writes of the same record
+#[derive(Debug, Clone, Copy)]
+struct WriteInterval {
+    interval_ms: u64,
+    now_ms: u64,
 }
 
-/// DNS manager for handling Cloudflare DNS operations
+/// DNS manager tracking record state in KV and applying changes through a provider
 pub struct DnsManager<'a> {
-    zone_id: String,
-    token: String,
-    kv: &'a kv::KvStore,
+    provider: &'a dyn DnsProvider,
+    kv: &'a dyn KeyValueStore,
+    revalidation: Option<Revalidation>,
+    write_interval: Option<WriteInterval>,
+    adopt: bool,
 }
 
 impl<'a> DnsManager<'a> {
     /// Create a new DNS manager instance
-    pub fn new(zone_id: String, token: String, kv: &'a kv::KvStore) -> Self {
-        Self { zone_id, token, kv }
+    pub fn new(provider: &'a dyn DnsProvider, kv: &'a dyn KeyValueStore) -> Self {
+        Self {
+            provider,
+            kv,
+            revalidation: None,
+            write_interval: None,
+            adopt: false,
+        }
+    }
+
+    /// Re-read live records from the provider when the cache is older than `interval_secs`
+    ///
+    /// `now_ms` is the current Unix time in milliseconds.
+    pub fn with_revalidation(mut self, interval_secs: u64, now_ms: u64) -> Self {
+        self.revalidation = Some(Revalidation {
+            interval_ms: interval_secs.saturating_mul(1000),
+            now_ms,
+        });
+        self
+    }
+
+    /// Write each record at most once per `interval_secs`, leaving further changes for later
+    ///
+    /// Protects the provider's API quota from clients flapping between addresses.
+    /// `now_ms` is the current Unix time in milliseconds.
+    pub fn with_write_interval(mut self, interval_secs: u64, now_ms: u64) -> Self {
+        self.write_interval = Some(WriteInterval {
+            interval_ms: interval_secs.saturating_mul(1000),
+            now_ms,
+        });
+        self
+    }
+
+    /// Take over existing records that do not carry this worker's marker
+    pub fn with_adoption(mut self, adopt: bool) -> Self {
+        self.adopt = adopt;
+        self
+    }
+
+    /// Refuse to touch a live record this worker neither created nor was asked to adopt
+    fn check_adoptable(
+        &self,
+        dns_info: &DnsRecordInfo,
+        record_type: RecordType,
+        record: &DnsRecord,
+    ) -> DnsResult<()> {
+        if record.managed || self.adopt || dns_info.get_id(record_type) == Some(&record.id) {
+            return Ok(());
+        }
+        Err(DnsError::Unmanaged(format!(
+            "{} record {}",
+            record_type.as_str(),
+            dns_info.record_name
+        )))
     }
 
     /// Generate KV key for DNS record info
-    fn dns_record_key(&self, homename: &str) -> String {
-        format!("{}_dns_record_id", homename)
+    fn dns_record_key(homename: &str) -> String {
+        format!("{}{}", homename, DNS_RECORD_KEY_SUFFIX)
+    }
+
+    /// KV key marking a homename as managed, written alongside its record info
+    fn managed_key(homename: &str) -> String {
+        format!("{}{}", MANAGED_KEY_PREFIX, homename)
+    }
+
+    /// Homenames in the managed index
+    pub async fn managed_homenames(kv: &dyn KeyValueStore) -> Result<Vec<String>> {
+        Ok(kv
+            .list_keys(MANAGED_KEY_PREFIX)
+            .await?
+            .iter()
+            .filter_map(|key| key.strip_prefix(MANAGED_KEY_PREFIX))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Index homenames cached before the managed index existed, returning how many were added
+    ///
+    /// Lists the whole namespace once: their record info and per-record keys
+    /// are the only unprefixed keys. A marker key keeps later runs from
+    /// repeating the scan.
+    pub async fn backfill_managed_index(kv: &dyn KeyValueStore) -> Result<usize> {
+        if kv.get(MANAGED_BACKFILL_KEY).await?.is_some() {
+            return Ok(0);
+        }
+
+        let suffixes: Vec<String> = std::iter::once(DNS_RECORD_KEY_SUFFIX.to_string())
+            .chain(CACHED_RECORD_TYPES.map(|t| Self::ip_key("", t)))
+            .collect();
+        let mut homenames: Vec<String> = kv
+            .list_keys("")
+            .await?
+            .iter()
+            .filter(|key| !key.contains(':'))
+            .filter_map(|key| suffixes.iter().find_map(|s| key.strip_suffix(s.as_str())))
+            .filter(|homename| !homename.is_empty())
+            .map(str::to_string)
+            .collect();
+        homenames.sort();
+        homenames.dedup();
+
+        for homename in &homenames {
+            kv.put(&Self::managed_key(homename), "").await?;
+        }
+        kv.put(MANAGED_BACKFILL_KEY, "done").await?;
+        Ok(homenames.len())
+    }
+
+    /// Drop an indexed homename whose record info is gone, with its per-record keys
+    ///
+    /// Returns the per-record keys removed, or `None` when the homename still
+    /// has cached record info and was left alone.
+    pub async fn remove_if_orphaned(
+        kv: &dyn KeyValueStore,
+        homename: &str,
+    ) -> Result<Option<Vec<String>>> {
+        if kv.get(&Self::dns_record_key(homename)).await?.is_some() {
+            return Ok(None);
+        }
+
+        let mut removed = Vec::new();
+        for record_type in CACHED_RECORD_TYPES {
+            let key = Self::ip_key(homename, record_type);
+            if kv.get(&key).await?.is_some() {
+                kv.delete(&key).await?;
+                removed.push(key);
+            }
+        }
+        kv.delete(&Self::managed_key(homename)).await?;
+        Ok(Some(removed))
     }
 
     /// Generate KV key for IP address storage
-    fn ip_key(&self, homename: &str, record_type: RecordType) -> String {
+    fn ip_key(homename: &str, record_type: RecordType) -> String {
         match record_type {
             RecordType::A => format!("{}_v4", homename),
             RecordType::AAAA => format!("{}_v6", homename),
+            RecordType::TXT => format!("{}_txt", homename),
         }
     }
 
     /// Get or create DNS record IDs for a hostname
+    ///
+    /// `record_types` are the records the caller is about to write; only they
+    /// fail discovery when they exist without this worker's marker.
     pub async fn get_or_create_record_ids(
         &self,
         homename: &str,
         record_name: &str,
-    ) -> Result<DnsRecordInfo> {
-        let dns_key: String = self.dns_record_key(homename);
+        record_types: &[RecordType],
+    ) -> DnsResult<DnsRecordInfo> {
+        let dns_key: String = Self::dns_record_key(homename);
 
         // First, check KV for existing record info
-        if let Some(dns_info_value) = self.kv.get(&dns_key).text().await? {
-            if let Ok(dns_info) = serde_json::from_str::<DnsRecordInfo>(&dns_info_value) {
-                return Ok(dns_info);
-            }
+        if let Some(dns_info_value) = self.kv.get(&dns_key).await?
+            && let Ok(dns_info) = serde_json::from_str::<DnsRecordInfo>(&dns_info_value)
+        {
+            return Ok(dns_info);
         }
 
-        // Not found in KV, check Cloudflare for existing records
+        // Not found in KV, check the provider for existing records
         let mut dns_info = DnsRecordInfo::new(record_name.to_string());
+        self.sync_live_records(homename, &mut dns_info, &CACHED_RECORD_TYPES, record_types)
+            .await?;
+        Ok(dns_info)
+    }
 
-        // Check for existing records
-        for record_type in [RecordType::A, RecordType::AAAA] {
-            if let Some(record) = self.find_existing_record(record_name, record_type).await? {
-                dns_info.set_id(record_type, record.id);
+    /// Cached record ids, re-read from the provider first when revalidation is due
+    async fn load_record_ids(
+        &self,
+        homename: &str,
+        record_name: &str,
+        record_types: &[RecordType],
+    ) -> DnsResult<DnsRecordInfo> {
+        let mut dns_info = self
+            .get_or_create_record_ids(homename, record_name, record_types)
+            .await?;
+
+        let due = self.revalidation.is_some_and(|r| {
+            dns_info
+                .validated_at
+                .is_none_or(|at| r.now_ms.saturating_sub(at) >= r.interval_ms)
+        });
+        if due {
+            self.sync_live_records(homename, &mut dns_info, record_types, record_types)
+                .await?;
+        }
+        Ok(dns_info)
+    }
+
+    /// Replace cached ids and contents with what the provider currently publishes
+    ///
+    /// The published content becomes the value later requests compare against,
+    /// so a matching record is never rewritten and a diverging one is corrected.
+    /// Unmanaged records among `record_types` fail the sync when they are in
+    /// `written` and are otherwise left uncached, so an unrelated SPF or
+    /// verification TXT record never blocks an address update.
+    async fn sync_live_records(
+        &self,
+        homename: &str,
+        dns_info: &mut DnsRecordInfo,
+        record_types: &[RecordType],
+        written: &[RecordType],
+    ) -> DnsResult<()> {
+        for &record_type in record_types {
+            match self
+                .provider
+                .find_record(&dns_info.record_name, record_type)
+                .await?
+            {
+                Some(record) => {
+                    if let Err(e) = self.check_adoptable(dns_info, record_type, &record) {
+                        if written.contains(&record_type) {
+                            return Err(e);
+                        }
+                        continue;
+                    }
+                    dns_info.set_id(record_type, record.id);
+                    self.store_ip(homename, record_type, &record.content)
+                        .await?;
+                }
+                None => {
+                    dns_info.take_id(record_type);
+                    self.kv.delete(&Self::ip_key(homename, record_type)).await?;
+                }
             }
         }
 
-        // Store the record info in KV for future use
-        self.store_dns_info(homename, &dns_info).await?;
-        Ok(dns_info)
+        dns_info.validated_at = self.revalidation.map(|r| r.now_ms);
+        self.store_dns_info(homename, dns_info).await
     }
 
-    /// Store DNS record info in KV
-    async fn store_dns_info(&self, homename: &str, dns_info: &DnsRecordInfo) -> Result<()> {
-        let dns_key = self.dns_record_key(homename);
+    /// Store DNS record info in KV and index the homename as managed
+    async fn store_dns_info(&self, homename: &str, dns_info: &DnsRecordInfo) -> DnsResult<()> {
+        let dns_key = Self::dns_record_key(homename);
         let dns_info_json = serde_json::to_string(dns_info)
             .map_err(|e| DnsError::SerializationError(e.to_string()))?;
-        self.kv.put(&dns_key, &dns_info_json)?.execute().await?;
+        self.kv.put(&dns_key, &dns_info_json).await?;
+        self.kv
+            .put(&Self::managed_key(homename), &dns_info.record_name)
+            .await?;
         Ok(())
     }
 
-    /// Find an existing DNS record in Cloudflare
-    async fn find_existing_record(
+    /// Ensure DNS record exists and update it with new content
+    async fn ensure_and_update_record(
         &self,
-        name: &str,
+        dns_info: &mut DnsRecordInfo,
         record_type: RecordType,
-    ) -> Result<Option<DnsRecord>> {
-        let url = format!(
-            "{}/zones/{}/dns_records?name={}&type={}",
-            CLOUDFLARE_API_BASE,
-            self.zone_id,
-            name,
-            record_type.as_str()
-        );
-
-        let response: ListDnsResponse = self.make_api_request(&url, Method::Get, None).await?;
-
-        if !response.success {
-            return Err(DnsError::ApiError(format!(
-                "Failed to list DNS records: {:?}",
-                response.errors
-            ))
-            .into());
-        }
+        content: &str,
+        homename: &str,
+    ) -> DnsResult<RecordStatus> {
+        // Without a cached id, look first: a record refused earlier may still exist
+        let Some(id) = dns_info.get_id(record_type).cloned() else {
+            return self
+                .rediscover_record(dns_info, record_type, content, homename)
+                .await;
+        };
 
-        if let Some(records) = response.result {
-            return Ok(records
-                .into_iter()
-                .find(|r| r.name == name && r.record_type == record_type.as_str()));
+        match self
+            .provider
+            .update_record(&id, record_type, &dns_info.record_name, content)
+            .await
+        {
+            Ok(()) => Ok(RecordStatus::Updated),
+            Err(DnsError::NotFound) => {
+                // The record was deleted outside this worker: forget the stale id
+                // right away so a failure below cannot leave it cached
+                dns_info.take_id(record_type);
+                self.store_dns_info(homename, dns_info).await?;
+                self.rediscover_record(dns_info, record_type, content, homename)
+                    .await
+            }
+            Err(e) => Err(e),
         }
-
-        Ok(None)
     }
 
-    /// Create a new DNS record in Cloudflare
-    async fn create_dns_record(
+    /// Adopt a record that was recreated under a new id, or create a fresh one
+    ///
+    /// Records without this worker's marker are only adopted when asked to.
+    async fn rediscover_record(
         &self,
+        dns_info: &mut DnsRecordInfo,
         record_type: RecordType,
-        name: &str,
         content: &str,
-    ) -> Result<Option<String>> {
-        let url = format!("{}/zones/{}/dns_records", CLOUDFLARE_API_BASE, self.zone_id);
-
-        let body = serde_json::json!({
-            "type": record_type.as_str(),
-            "name": name,
-            "content": content,
-            "ttl": DNS_TTL,
-            "proxied": false
-        });
-
-        let response: CreateDnsResponse = self
-            .make_api_request(&url, Method::Post, Some(body))
-            .await?;
-
-        if !response.success {
-            return Err(DnsError::ApiError(format!(
-                "Failed to create DNS record: {:?}",
-                response.errors
-            ))
-            .into());
+        homename: &str,
+    ) -> DnsResult<RecordStatus> {
+        match self
+            .provider
+            .find_record(&dns_info.record_name, record_type)
+            .await?
+        {
+            Some(record) => {
+                self.check_adoptable(dns_info, record_type, &record)?;
+                self.provider
+                    .update_record(&record.id, record_type, &dns_info.record_name, content)
+                    .await?;
+                dns_info.set_id(record_type, record.id);
+                self.store_dns_info(homename, dns_info).await?;
+                Ok(RecordStatus::Updated)
+            }
+            None => {
+                self.create_and_store_record(dns_info, record_type, content, homename)
+                    .await
+            }
         }
-
-        Ok(response.result.map(|record| record.id))
     }
 
-    /// Update an existing DNS record in Cloudflare
-    async fn update_dns_record(
+    /// Create a record with the given content and cache its id
+    async fn create_and_store_record(
         &self,
-        record_id: &str,
+        dns_info: &mut DnsRecordInfo,
         record_type: RecordType,
-        name: &str,
         content: &str,
-    ) -> Result<bool> {
-        let url = format!(
-            "{}/zones/{}/dns_records/{}",
-            CLOUDFLARE_API_BASE, self.zone_id, record_id
-        );
-
-        let body = serde_json::json!({
-            "type": record_type.as_str(),
-            "name": name,
-            "content": content,
-            "ttl": DNS_TTL,
-            "proxied": false
-        });
+        homename: &str,
+    ) -> DnsResult<RecordStatus> {
+        let new_id = self
+            .provider
+            .create_record(record_type, &dns_info.record_name, content)
+            .await?;
 
-        let response: UpdateDnsResponse =
-            self.make_api_request(&url, Method::Put, Some(body)).await?;
+        // Update the dns_info with the new record ID
+        dns_info.set_id(record_type, new_id);
 
-        if !response.success {
-            return Err(DnsError::ApiError(format!(
-                "Failed to update DNS record: {:?}",
-                response.errors
-            ))
-            .into());
-        }
+        // Update KV with the new record info
+        self.store_dns_info(homename, dns_info).await?;
 
-        Ok(response.success)
+        // Record created successfully, no need to update again
+        Ok(RecordStatus::Created)
     }
 
-    /// Make an authenticated API request to Cloudflare
-    async fn make_api_request<T>(
+    /// Load the last IP address (or TXT content) written for a record
+    pub async fn stored_ip(
         &self,
-        url: &str,
-        method: Method,
-        body: Option<serde_json::Value>,
-    ) -> Result<T>
-    where
-        T: for<'de> Deserialize<'de>,
-    {
-        let mut init = RequestInit::new();
-        init.with_method(method);
-
-        if let Some(body_data) = body {
-            init.with_body(Some(body_data.to_string().into()));
-        }
-
-        let mut req = Request::new_with_init(url, &init)?;
-        req.headers_mut()?
-            .set("Authorization", &format!("Bearer {}", self.token))?;
-        req.headers_mut()?.set("Content-Type", CONTENT_TYPE_JSON)?;
+        homename: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<String>> {
+        let key = Self::ip_key(homename, record_type);
+        Ok(self.kv.get(&key).await?.filter(|ip| !ip.is_empty()))
+    }
 
-        let mut resp = Fetch::Request(req).send().await?;
-        let response: T = resp.json().await?;
-        Ok(response)
+    /// Store the new IP address (or TXT content) in KV
+    async fn store_ip(&self, homename: &str, record_type: RecordType, ip: &str) -> DnsResult<()> {
+        let key = Self::ip_key(homename, record_type);
+        self.kv.put(&key, ip).await?;
+        Ok(())
     }
 
-    /// Ensure DNS record exists and update it with new content
-    async fn ensure_and_update_record(
+    /// Update a single DNS record if the IP has changed, capturing any failure
+    async fn update_record_if_changed(
         &self,
         dns_info: &mut DnsRecordInfo,
         record_type: RecordType,
-        content: &str,
+        ip: &str,
         homename: &str,
-    ) -> Result<bool> {
-        match dns_info.get_id(record_type) {
-            Some(id) => {
-                // Record exists, update it
-                self.update_dns_record(id, record_type, &dns_info.record_name, content)
-                    .await
-            }
-            None => {
-                // Record doesn't exist, create it with the correct content
-                match self
-                    .create_dns_record(record_type, &dns_info.record_name, content)
-                    .await?
-                {
-                    Some(new_id) => {
-                        // Update the dns_info with the new record ID
-                        dns_info.set_id(record_type, new_id);
-
-                        // Update KV with the new record info
-                        self.store_dns_info(homename, dns_info).await?;
+    ) -> RecordOutcome {
+        if ip.is_empty() {
+            return RecordOutcome::skipped(record_type, NO_ADDRESS_REASON);
+        }
 
-                        // Record created successfully, no need to update again
-                        Ok(true)
-                    }
-                    None => Ok(false),
-                }
+        match self
+            .try_update_record(dns_info, record_type, ip, homename)
+            .await
+        {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                RecordOutcome::failure(record_type, dns_info.get_id(record_type).cloned(), &e)
             }
         }
     }
 
-    /// Check if IP has changed and needs updating
-    async fn should_update_ip(
-        &self,
-        homename: &str,
-        record_type: RecordType,
-        new_ip: &str,
-    ) -> Result<bool> {
-        if new_ip.is_empty() {
-            return Ok(false);
-        }
+    fn written_key(homename: &str, record_type: RecordType) -> String {
+        format!(
+            "{}{}:{}",
+            WRITTEN_KEY_PREFIX,
+            homename,
+            record_type.as_str()
+        )
+    }
 
-        let key = self.ip_key(homename, record_type);
-        let prev_ip = self.kv.get(&key).text().await?.unwrap_or_default();
-        Ok(prev_ip != new_ip)
+    /// Seconds until the record may be written again, `None` when it may be written now
+    async fn write_wait(&self, homename: &str, record_type: RecordType) -> DnsResult<Option<u64>> {
+        let Some(limit) = self.write_interval else {
+            return Ok(None);
+        };
+        let key = Self::written_key(homename, record_type);
+        let written_at = self.kv.get(&key).await?.and_then(|v| v.parse::<u64>().ok());
+        Ok(written_at
+            .map(|at| at.saturating_add(limit.interval_ms))
+            .filter(|&next| next > limit.now_ms)
+            .map(|next| (next - limit.now_ms).div_ceil(1000)))
     }
 
-    /// Store the new IP address in KV
-    async fn store_ip(&self, homename: &str, record_type: RecordType, ip: &str) -> Result<()> {
-        let key = self.ip_key(homename, record_type);
-        self.kv.put(&key, ip)?.execute().await?;
+    /// Remember when the record was written for the write interval
+    async fn mark_written(&self, homename: &str, record_type: RecordType) -> DnsResult<()> {
+        let Some(limit) = self.write_interval else {
+            return Ok(());
+        };
+        let key = Self::written_key(homename, record_type);
+        self.kv
+            .put_with_ttl(
+                &key,
+                &limit.now_ms.to_string(),
+                limit.interval_ms.div_ceil(1000),
+            )
+            .await?;
         Ok(())
     }
 
-    /// Update a single DNS record if the IP has changed
-    async fn update_record_if_changed(
+    /// Compare against the stored IP and write the record when it differs
+    async fn try_update_record(
         &self,
         dns_info: &mut DnsRecordInfo,
         record_type: RecordType,
         ip: &str,
         homename: &str,
-    ) -> Result<()> {
-        if self.should_update_ip(homename, record_type, ip).await?
-            && self
+    ) -> DnsResult<RecordOutcome> {
+        let previous_ip = self.stored_ip(homename, record_type).await?;
+        let status = if previous_ip.as_deref() == Some(ip) {
+            RecordStatus::Unchanged
+        } else {
+            if let Some(wait) = self.write_wait(homename, record_type).await? {
+                return Ok(RecordOutcome {
+                    record_id: dns_info.get_id(record_type).cloned(),
+                    previous_ip,
+                    ..RecordOutcome::throttled(record_type, wait)
+                });
+            }
+            let status = self
                 .ensure_and_update_record(dns_info, record_type, ip, homename)
-                .await?
-        {
+                .await?;
             self.store_ip(homename, record_type, ip).await?;
-        }
-        Ok(())
+            self.mark_written(homename, record_type).await?;
+            status
+        };
+
+        Ok(RecordOutcome {
+            record_id: dns_info.get_id(record_type).cloned(),
+            previous_ip,
+            ..RecordOutcome::new(record_type, status)
+        })
     }
 
     /// Main method to update DNS records, handling both IPv4 and IPv6
@@ -398,18 +862,789 @@ impl<'a> DnsManager<'a> {
         record_name: &str,
         ipv4: &str,
         ipv6: &str,
-    ) -> Result<()> {
+    ) -> DnsUpdateOutcome {
+        let addresses = [(RecordType::A, ipv4), (RecordType::AAAA, ipv6)];
+
         // Get or create DNS record info
-        let mut dns_info = self.get_or_create_record_ids(homename, record_name).await?;
+        let record_types: Vec<RecordType> = addresses
+            .iter()
+            .filter(|(_, ip)| !ip.is_empty())
+            .map(|&(record_type, _)| record_type)
+            .collect();
+        let mut dns_info = match self
+            .load_record_ids(homename, record_name, &record_types)
+            .await
+        {
+            Ok(dns_info) => dns_info,
+            Err(e) => {
+                let records = addresses
+                    .iter()
+                    .map(|&(record_type, ip)| {
+                        if ip.is_empty() {
+                            RecordOutcome::skipped(record_type, NO_ADDRESS_REASON)
+                        } else {
+                            RecordOutcome::failure(record_type, None, &e)
+                        }
+                    })
+                    .collect();
+                return DnsUpdateOutcome { records };
+            }
+        };
+
+        // Update IPv4 and IPv6 records if provided
+        let mut records = Vec::with_capacity(addresses.len());
+        for (record_type, ip) in addresses {
+            records.push(
+                self.update_record_if_changed(&mut dns_info, record_type, ip, homename)
+                    .await,
+            );
+        }
+
+        DnsUpdateOutcome { records }
+    }
 
-        // Update IPv4 record if provided
-        self.update_record_if_changed(&mut dns_info, RecordType::A, ipv4, homename)
+    /// Set the TXT record for a hostname, creating it if necessary
+    pub async fn maybe_update_txt(
+        &self,
+        homename: &str,
+        record_name: &str,
+        content: &str,
+    ) -> DnsUpdateOutcome {
+        match self.try_update_txt(homename, record_name, content).await {
+            Ok(record) => DnsUpdateOutcome {
+                records: vec![record],
+            },
+            Err(e) => DnsUpdateOutcome {
+                records: vec![RecordOutcome::failure(RecordType::TXT, None, &e)],
+            },
+        }
+    }
+
+    /// Discover any TXT record not yet cached, then write the new content
+    async fn try_update_txt(
+        &self,
+        homename: &str,
+        record_name: &str,
+        content: &str,
+    ) -> DnsResult<RecordOutcome> {
+        let mut dns_info = self
+            .load_record_ids(homename, record_name, &[RecordType::TXT])
             .await?;
 
-        // Update IPv6 record if provided
-        self.update_record_if_changed(&mut dns_info, RecordType::AAAA, ipv6, homename)
+        // Record info cached before TXT support was added never looked for TXT records
+        if dns_info.get_id(RecordType::TXT).is_none()
+            && let Some(record) = self
+                .provider
+                .find_record(record_name, RecordType::TXT)
+                .await?
+        {
+            self.check_adoptable(&dns_info, RecordType::TXT, &record)?;
+            self.store_ip(homename, RecordType::TXT, &record.content)
+                .await?;
+            dns_info.set_id(RecordType::TXT, record.id);
+            self.store_dns_info(homename, &dns_info).await?;
+        }
+
+        Ok(self
+            .update_record_if_changed(&mut dns_info, RecordType::TXT, content, homename)
+            .await)
+    }
+
+    /// Bring the live A/AAAA records back in line with the last published addresses
+    ///
+    /// Used by the scheduled reconciliation: records edited or deleted outside
+    /// this worker are rewritten with the address stored in KV.
+    pub async fn reconcile(&self, homename: &str, record_name: &str) -> DnsUpdateOutcome {
+        let record_types = [RecordType::A, RecordType::AAAA];
+        match self
+            .try_reconcile(homename, record_name, &record_types)
+            .await
+        {
+            Ok(outcome) => outcome,
+            Err(e) => DnsUpdateOutcome {
+                records: record_types
+                    .iter()
+                    .map(|&t| RecordOutcome::failure(t, None, &e))
+                    .collect(),
+            },
+        }
+    }
+
+    async fn try_reconcile(
+        &self,
+        homename: &str,
+        record_name: &str,
+        record_types: &[RecordType],
+    ) -> DnsResult<DnsUpdateOutcome> {
+        let mut dns_info = self
+            .get_or_create_record_ids(homename, record_name, record_types)
             .await?;
+        let mut records = Vec::with_capacity(record_types.len());
 
-        Ok(())
+        for &record_type in record_types {
+            let outcome = match self
+                .reconcile_record(homename, &mut dns_info, record_type)
+                .await
+            {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    RecordOutcome::failure(record_type, dns_info.get_id(record_type).cloned(), &e)
+                }
+            };
+            records.push(outcome);
+        }
+
+        if let Some(revalidation) = self.revalidation {
+            dns_info.validated_at = Some(revalidation.now_ms);
+        }
+        self.store_dns_info(homename, &dns_info).await?;
+        Ok(DnsUpdateOutcome { records })
+    }
+
+    /// Compare one live record with the stored address and repair it if needed
+    async fn reconcile_record(
+        &self,
+        homename: &str,
+        dns_info: &mut DnsRecordInfo,
+        record_type: RecordType,
+    ) -> DnsResult<RecordOutcome> {
+        let Some(desired) = self.stored_ip(homename, record_type).await? else {
+            return Ok(RecordOutcome::skipped(record_type, NOT_PUBLISHED_REASON));
+        };
+
+        let live = self
+            .provider
+            .find_record(&dns_info.record_name, record_type)
+            .await?;
+        let previous_ip = live.as_ref().map(|record| record.content.clone());
+        if let Some(record) = &live {
+            self.check_adoptable(dns_info, record_type, record)?;
+        }
+        let status = match live {
+            Some(record) if record.content == desired => {
+                dns_info.set_id(record_type, record.id);
+                RecordStatus::Unchanged
+            }
+            Some(record) => {
+                self.provider
+                    .update_record(&record.id, record_type, &dns_info.record_name, &desired)
+                    .await?;
+                dns_info.set_id(record_type, record.id);
+                RecordStatus::Updated
+            }
+            None => {
+                let id = self
+                    .provider
+                    .create_record(record_type, &dns_info.record_name, &desired)
+                    .await?;
+                dns_info.set_id(record_type, id);
+                RecordStatus::Created
+            }
+        };
+
+        Ok(RecordOutcome {
+            record_id: dns_info.get_id(record_type).cloned(),
+            previous_ip,
+            ..RecordOutcome::new(record_type, status)
+        })
+    }
+
+    /// Delete the given records for a hostname and forget their cached state
+    ///
+    /// Returns `true` when at least one record was deleted.
+    pub async fn clear_records(
+        &self,
+        homename: &str,
+        record_name: &str,
+        record_types: &[RecordType],
+    ) -> DnsResult<bool> {
+        let mut dns_info = self
+            .get_or_create_record_ids(homename, record_name, record_types)
+            .await?;
+        let mut deleted = false;
+
+        for &record_type in record_types {
+            if let Some(id) = dns_info.take_id(record_type) {
+                match self
+                    .provider
+                    .delete_record(&id, record_type, record_name)
+                    .await
+                {
+                    Ok(()) => deleted = true,
+                    // Already deleted outside this worker, nothing left to clear
+                    Err(DnsError::NotFound) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            self.kv.delete(&Self::ip_key(homename, record_type)).await?;
+        }
+
+        self.store_dns_info(homename, &dns_info).await?;
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    const HOME: &str = "home";
+    const RECORD: &str = "home.example.com";
+
+    fn statuses(outcome: &DnsUpdateOutcome) -> Vec<(RecordType, RecordStatus)> {
+        outcome
+            .records
+            .iter()
+            .map(|r| (r.record_type, r.status))
+            .collect()
+    }
+
+    #[test]
+    fn held_changes_are_not_retried() {
+        let outcome = DnsUpdateOutcome::held(
+            &[(RecordType::A, "198.51.100.1"), (RecordType::AAAA, "")],
+            "held for approval: new country FR",
+        );
+        assert_eq!(
+            statuses(&outcome),
+            vec![
+                (RecordType::A, RecordStatus::Pending),
+                (RecordType::AAAA, RecordStatus::Skipped)
+            ]
+        );
+        assert!(outcome.is_held() && !outcome.has_errors());
+        assert!(!outcome.into_result().unwrap());
+    }
+
+    #[tokio::test]
+    async fn creates_records_on_first_update() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        let outcome = manager
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "2001:db8::1")
+            .await;
+
+        assert_eq!(
+            statuses(&outcome),
+            vec![
+                (RecordType::A, RecordStatus::Created),
+                (RecordType::AAAA, RecordStatus::Created)
+            ]
+        );
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("1.2.3.4")
+        );
+        assert_eq!(
+            provider.content(RECORD, RecordType::AAAA).as_deref(),
+            Some("2001:db8::1")
+        );
+        assert_eq!(kv.get("home_v4").await.unwrap().as_deref(), Some("1.2.3.4"));
+        let info = manager
+            .get_or_create_record_ids(HOME, RECORD, &[RecordType::A, RecordType::AAAA])
+            .await
+            .unwrap();
+        assert_eq!(info.a_id, outcome.records[0].record_id);
+        assert_eq!(info.aaaa_id, outcome.records[1].record_id);
+    }
+
+    #[tokio::test]
+    async fn unchanged_ip_does_not_write() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        let writes = provider.writes();
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+
+        assert_eq!(
+            statuses(&outcome),
+            vec![
+                (RecordType::A, RecordStatus::Unchanged),
+                (RecordType::AAAA, RecordStatus::Skipped)
+            ]
+        );
+        assert_eq!(outcome.records[0].previous_ip.as_deref(), Some("1.2.3.4"));
+        assert!(!outcome.changed());
+        assert_eq!(provider.writes(), writes);
+    }
+
+    #[tokio::test]
+    async fn changed_ip_updates_record() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "5.6.7.8", "").await;
+
+        let record = &outcome.records[0];
+        assert_eq!(record.status, RecordStatus::Updated);
+        assert_eq!(record.previous_ip.as_deref(), Some("1.2.3.4"));
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("5.6.7.8")
+        );
+        assert_eq!(provider.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn write_interval_defers_frequent_changes() {
+        const START: u64 = 1_700_000_000_000;
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let at = |now_ms| DnsManager::new(&provider, &kv).with_write_interval(300, now_ms);
+
+        at(START)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+        let outcome = at(START + 60_000)
+            .maybe_update_dns(HOME, RECORD, "5.6.7.8", "")
+            .await;
+        assert_eq!(
+            statuses(&outcome)[0],
+            (RecordType::A, RecordStatus::Throttled)
+        );
+        assert_eq!(
+            outcome.records[0].reason.as_deref(),
+            Some("record written recently, retry in 240s")
+        );
+        assert!(outcome.clone().into_result().is_err());
+        assert_eq!(provider.writes(), 1);
+
+        // Once the interval has passed the pending change goes through
+        let outcome = at(START + 300_000)
+            .maybe_update_dns(HOME, RECORD, "5.6.7.8", "")
+            .await;
+        assert_eq!(
+            statuses(&outcome)[0],
+            (RecordType::A, RecordStatus::Updated)
+        );
+        assert_eq!(outcome.records[0].previous_ip.as_deref(), Some("1.2.3.4"));
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("5.6.7.8")
+        );
+    }
+
+    #[tokio::test]
+    async fn adopts_existing_record_only_when_asked() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let existing = provider.insert(RecordType::A, RECORD, "9.9.9.9");
+
+        let outcome = DnsManager::new(&provider, &kv)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Refused);
+        assert!(outcome.is_refused() && outcome.has_errors());
+        assert!(
+            outcome.records[0]
+                .reason
+                .as_deref()
+                .is_some_and(|reason| reason.contains("not managed by this worker"))
+        );
+        assert_eq!(provider.writes(), 0);
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("9.9.9.9")
+        );
+
+        let manager = DnsManager::new(&provider, &kv).with_adoption(true);
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+
+        assert_eq!(outcome.records[0].status, RecordStatus::Updated);
+        assert_eq!(
+            outcome.records[0].record_id.as_deref(),
+            Some(existing.as_str())
+        );
+        assert_eq!(provider.len(), 1);
+        assert!(provider.get(&existing).unwrap().managed);
+
+        // Once adopted, the record is updated without the flag
+        let outcome = DnsManager::new(&provider, &kv)
+            .maybe_update_dns(HOME, RECORD, "5.6.7.8", "")
+            .await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Updated);
+    }
+
+    #[tokio::test]
+    async fn unmanaged_records_of_other_types_are_left_alone() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        provider.insert(RecordType::TXT, RECORD, "v=spf1 -all");
+        let manager = DnsManager::new(&provider, &kv);
+
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Created);
+        assert!(
+            manager
+                .clear_records(HOME, RECORD, &[RecordType::A])
+                .await
+                .unwrap()
+        );
+
+        // The TXT record was never cached, so writing it still needs adoption
+        let outcome = manager.maybe_update_txt(HOME, RECORD, "token").await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Refused);
+        assert_eq!(
+            provider.content(RECORD, RecordType::TXT).as_deref(),
+            Some("v=spf1 -all")
+        );
+    }
+
+    #[tokio::test]
+    async fn provider_failure_is_reported() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        provider.set_failure(Some("zone is locked"));
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "5.6.7.8", "").await;
+
+        assert!(outcome.has_errors());
+        assert_eq!(outcome.records[0].status, RecordStatus::Error);
+        assert!(outcome.into_result().is_err());
+        // The failed write must not be recorded as published
+        assert_eq!(kv.get("home_v4").await.unwrap().as_deref(), Some("1.2.3.4"));
+    }
+
+    #[tokio::test]
+    async fn stale_record_id_is_recreated() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        let stale_id = outcome.records[0].record_id.clone().unwrap();
+        provider.remove(&stale_id);
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "5.6.7.8", "").await;
+
+        let record = &outcome.records[0];
+        assert_eq!(record.status, RecordStatus::Created);
+        assert_ne!(record.record_id.as_deref(), Some(stale_id.as_str()));
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("5.6.7.8")
+        );
+        assert_eq!(kv.get("home_v4").await.unwrap().as_deref(), Some("5.6.7.8"));
+        let info = manager
+            .get_or_create_record_ids(HOME, RECORD, &[RecordType::A, RecordType::AAAA])
+            .await
+            .unwrap();
+        assert_eq!(info.a_id, record.record_id);
+
+        // The fresh id is used from now on
+        let writes = provider.writes();
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "9.9.9.9", "").await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Updated);
+        assert_eq!(provider.writes(), writes + 1);
+    }
+
+    #[tokio::test]
+    async fn stale_record_id_adopts_replacement_record() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        provider.remove(outcome.records[0].record_id.as_deref().unwrap());
+        let replacement = provider.insert(RecordType::A, RECORD, "1.1.1.1");
+
+        // The replacement was created outside this worker and is left alone
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "5.6.7.8", "").await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Refused);
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("1.1.1.1")
+        );
+
+        let outcome = DnsManager::new(&provider, &kv)
+            .with_adoption(true)
+            .maybe_update_dns(HOME, RECORD, "5.6.7.8", "")
+            .await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Updated);
+        assert_eq!(
+            outcome.records[0].record_id.as_deref(),
+            Some(replacement.as_str())
+        );
+        assert_eq!(provider.len(), 1);
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("5.6.7.8")
+        );
+    }
+
+    #[tokio::test]
+    async fn clearing_externally_deleted_record_succeeds() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        provider.remove(outcome.records[0].record_id.as_deref().unwrap());
+
+        let deleted = manager
+            .clear_records(HOME, RECORD, &[RecordType::A])
+            .await
+            .unwrap();
+        assert!(!deleted);
+        let info = manager
+            .get_or_create_record_ids(HOME, RECORD, &[RecordType::A, RecordType::AAAA])
+            .await
+            .unwrap();
+        assert_eq!(info.a_id, None);
+    }
+
+    #[tokio::test]
+    async fn wiped_cache_adopts_live_content() {
+        let provider = MemoryProvider::new();
+        let kv = MemoryStore::new();
+        DnsManager::new(&provider, &kv)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+
+        // A fresh KV namespace knows nothing, but the published record already matches
+        let wiped = MemoryStore::new();
+        let writes = provider.writes();
+        let outcome = DnsManager::new(&provider, &wiped)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+
+        assert_eq!(outcome.records[0].status, RecordStatus::Unchanged);
+        assert_eq!(provider.writes(), writes);
+        assert_eq!(
+            wiped.get("home_v4").await.unwrap().as_deref(),
+            Some("1.2.3.4")
+        );
+    }
+
+    #[tokio::test]
+    async fn revalidation_corrects_manual_edits() {
+        const START: u64 = 1_700_000_000_000;
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let at = |now_ms| DnsManager::new(&provider, &kv).with_revalidation(60, now_ms);
+
+        let outcome = at(START)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+        let id = outcome.records[0].record_id.clone().unwrap();
+        provider
+            .update_record(&id, RecordType::A, RECORD, "6.6.6.6")
+            .await
+            .unwrap();
+
+        // Within the interval the cache is trusted
+        let outcome = at(START + 30_000)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Unchanged);
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("6.6.6.6")
+        );
+
+        // Once it expires the live value is compared and corrected
+        let outcome = at(START + 60_000)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Updated);
+        assert_eq!(outcome.records[0].previous_ip.as_deref(), Some("6.6.6.6"));
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("1.2.3.4")
+        );
+
+        // The check is not repeated until the next interval
+        let writes = provider.writes();
+        let outcome = at(START + 90_000)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Unchanged);
+        assert_eq!(provider.writes(), writes);
+    }
+
+    #[tokio::test]
+    async fn revalidation_recreates_deleted_record() {
+        const START: u64 = 1_700_000_000_000;
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let at = |now_ms| DnsManager::new(&provider, &kv).with_revalidation(60, now_ms);
+
+        let outcome = at(START)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+        provider.remove(outcome.records[0].record_id.as_deref().unwrap());
+
+        let outcome = at(START + 120_000)
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "")
+            .await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Created);
+        assert_eq!(outcome.records[0].previous_ip, None);
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("1.2.3.4")
+        );
+    }
+
+    #[tokio::test]
+    async fn reconcile_repairs_drift() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        let outcome = manager
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "2001:db8::1")
+            .await;
+        let a_id = outcome.records[0].record_id.clone().unwrap();
+        let aaaa_id = outcome.records[1].record_id.clone().unwrap();
+        provider
+            .update_record(&a_id, RecordType::A, RECORD, "6.6.6.6")
+            .await
+            .unwrap();
+        provider.remove(&aaaa_id);
+
+        let outcome = manager.reconcile(HOME, RECORD).await;
+        assert_eq!(
+            statuses(&outcome),
+            vec![
+                (RecordType::A, RecordStatus::Updated),
+                (RecordType::AAAA, RecordStatus::Created)
+            ]
+        );
+        assert_eq!(outcome.records[0].previous_ip.as_deref(), Some("6.6.6.6"));
+        assert_eq!(
+            provider.content(RECORD, RecordType::A).as_deref(),
+            Some("1.2.3.4")
+        );
+        assert_eq!(
+            provider.content(RECORD, RecordType::AAAA).as_deref(),
+            Some("2001:db8::1")
+        );
+
+        // A second pass finds nothing to do and the new AAAA id is cached
+        let writes = provider.writes();
+        let outcome = manager.reconcile(HOME, RECORD).await;
+        assert!(!outcome.changed());
+        assert_eq!(provider.writes(), writes);
+        let info = manager
+            .get_or_create_record_ids(HOME, RECORD, &[RecordType::A, RecordType::AAAA])
+            .await
+            .unwrap();
+        assert_eq!(info.aaaa_id, outcome.records[1].record_id);
+    }
+
+    #[tokio::test]
+    async fn reconcile_skips_unpublished_records() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        let outcome = manager.reconcile(HOME, RECORD).await;
+
+        assert_eq!(
+            statuses(&outcome),
+            vec![
+                (RecordType::A, RecordStatus::Unchanged),
+                (RecordType::AAAA, RecordStatus::Skipped)
+            ]
+        );
+        assert_eq!(provider.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn orphans_are_found_through_the_managed_index() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+        manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        // Record info deleted out of band, per-record keys left behind
+        kv.put("old_v4", "5.6.7.8").await.unwrap();
+        kv.put("old_txt", "challenge").await.unwrap();
+        kv.put("managed:old", "old.example.com").await.unwrap();
+        kv.put("stray_v4", "9.9.9.9").await.unwrap();
+
+        assert_eq!(
+            DnsManager::managed_homenames(&kv).await.unwrap(),
+            vec!["home", "old"]
+        );
+        assert_eq!(
+            DnsManager::remove_if_orphaned(&kv, HOME).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            DnsManager::remove_if_orphaned(&kv, "old").await.unwrap(),
+            Some(vec!["old_v4".to_string(), "old_txt".to_string()])
+        );
+        assert_eq!(kv.get("old_v4").await.unwrap(), None);
+        assert_eq!(
+            DnsManager::managed_homenames(&kv).await.unwrap(),
+            vec!["home"]
+        );
+        // Keys outside the index are never touched
+        assert!(kv.get("stray_v4").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn legacy_homenames_are_backfilled_once() {
+        let kv = MemoryStore::new();
+        for key in [
+            "home_dns_record_id",
+            "home_v4",
+            "my_v4_dns_record_id",
+            "my_v4_v4",
+            "old_v6",
+            "token:abc_v4",
+        ] {
+            kv.put(key, "x").await.unwrap();
+        }
+
+        assert_eq!(DnsManager::backfill_managed_index(&kv).await.unwrap(), 3);
+        assert_eq!(
+            DnsManager::managed_homenames(&kv).await.unwrap(),
+            vec!["home", "my_v4", "old"]
+        );
+        assert_eq!(
+            DnsManager::remove_if_orphaned(&kv, "old").await.unwrap(),
+            Some(vec!["old_v6".to_string()])
+        );
+
+        // Later runs trust the index instead of scanning again
+        kv.put("new_dns_record_id", "x").await.unwrap();
+        assert_eq!(DnsManager::backfill_managed_index(&kv).await.unwrap(), 0);
+        assert_eq!(
+            DnsManager::managed_homenames(&kv).await.unwrap(),
+            vec!["home", "my_v4"]
+        );
+    }
+
+    #[tokio::test]
+    async fn txt_records_are_set_and_cleared() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        let outcome = manager.maybe_update_txt(HOME, RECORD, "challenge").await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Created);
+        let outcome = manager.maybe_update_txt(HOME, RECORD, "challenge").await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Unchanged);
+
+        let deleted = manager
+            .clear_records(HOME, RECORD, &[RecordType::TXT])
+            .await
+            .unwrap();
+        assert!(deleted);
+        assert!(provider.is_empty());
+        assert_eq!(kv.get("home_txt").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn clear_removes_address_records() {
+        let (provider, kv) = (MemoryProvider::new(), MemoryStore::new());
+        let manager = DnsManager::new(&provider, &kv);
+
+        manager
+            .maybe_update_dns(HOME, RECORD, "1.2.3.4", "2001:db8::1")
+            .await;
+        let deleted = manager
+            .clear_records(HOME, RECORD, &[RecordType::A, RecordType::AAAA])
+            .await
+            .unwrap();
+
+        assert!(deleted);
+        assert!(provider.is_empty());
+        let outcome = manager.maybe_update_dns(HOME, RECORD, "1.2.3.4", "").await;
+        assert_eq!(outcome.records[0].status, RecordStatus::Created);
     }
 }
@@ -0,0 +1,280 @@
+use super::{DnsError, DnsProvider, DnsRecord, DnsResult, RecordType};
+use async_trait::async_trait;
+use serde::Deserialize;
+use worker::*;
+
+// Constants for better maintainability
+const CLOUDFLARE_API_BASE: &str = "https://api.cloudflare.com/client/v4";
+const CONTENT_TYPE_JSON: &str = "application/json";
+/// Error code returned when a record id no longer exists
+const RECORD_NOT_FOUND_CODE: u32 = 81044;
+/// Comment marking the records this worker created or adopted
+const MANAGED_RECORD_COMMENT: &str = "managed by whatismyip";
+
+/// Cloudflare API response for DNS record creation
+#[derive(Deserialize)]
+struct CreateDnsResponse {
+    success: bool,
+    result: Option<CloudflareRecord>,
+    errors: Option<Vec<ApiError>>,
+}
+
+/// Cloudflare API response for listing DNS records
+#[derive(Deserialize)]
+struct ListDnsResponse {
+    success: bool,
+    result: Option<Vec<CloudflareRecord>>,
+    errors: Option<Vec<ApiError>>,
+}
+
+/// Cloudflare API response for updating DNS records
+#[derive(Deserialize)]
+struct UpdateDnsResponse {
+    success: bool,
+    errors: Option<Vec<ApiError>>,
+}
+
+/// Cloudflare API response for deleting DNS records
+#[derive(Deserialize)]
+struct DeleteDnsResponse {
+    success: bool,
+    errors: Option<Vec<ApiError>>,
+}
+
+/// Cloudflare API error structure
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    code: u32,
+    #[allow(dead_code)]
+    message: String,
+}
+
+/// Cloudflare DNS record structure
+#[derive(Deserialize)]
+struct CloudflareRecord {
+    id: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    name: String,
+    content: String,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+/// DNS provider backed by the Cloudflare API
+pub struct CloudflareProvider {
+    zone_id: String,
+    token: String,
+    ttl: u32,
+}
+
+impl CloudflareProvider {
+    /// Create a new Cloudflare provider for the given zone
+    pub fn new(zone_id: String, token: String, ttl: u32) -> Self {
+        Self {
+            zone_id,
+            token,
+            ttl,
+        }
+    }
+
+    /// Build the JSON body for creating or updating a record
+    fn record_body(&self, record_type: RecordType, name: &str, content: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": record_type.as_str(),
+            "name": name,
+            "content": content,
+            "ttl": self.ttl,
+            "proxied": false,
+            "comment": MANAGED_RECORD_COMMENT
+        })
+    }
+
+    /// Map a failed write on an existing record id to a `DnsError`
+    ///
+    /// Records deleted outside this worker are reported as `NotFound` so the
+    /// manager can recreate them.
+    fn record_error(action: &str, errors: Option<Vec<ApiError>>) -> DnsError {
+        let not_found = errors
+            .iter()
+            .flatten()
+            .any(|e| e.code == RECORD_NOT_FOUND_CODE);
+        if not_found {
+            return DnsError::NotFound;
+        }
+        DnsError::ApiError(format!("Failed to {} DNS record: {:?}", action, errors))
+    }
+
+    /// Make an authenticated API request to Cloudflare
+    async fn make_api_request<T>(
+        &self,
+        url: &str,
+        method: Method,
+        body: Option<serde_json::Value>,
+    ) -> DnsResult<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let mut init = RequestInit::new();
+        init.with_method(method);
+
+        if let Some(body_data) = body {
+            init.with_body(Some(body_data.to_string().into()));
+        }
+
+        let mut req = Request::new_with_init(url, &init)?;
+        req.headers_mut()?
+            .set("Authorization", &format!("Bearer {}", self.token))?;
+        req.headers_mut()?.set("Content-Type", CONTENT_TYPE_JSON)?;
+
+        let mut resp = Fetch::Request(req).send().await?;
+        let response: T = resp.json().await?;
+        Ok(response)
+    }
+}
+
+#[async_trait(?Send)]
+impl DnsProvider for CloudflareProvider {
+    async fn find_record(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<DnsRecord>> {
+        let url = format!(
+            "{}/zones/{}/dns_records?name={}&type={}",
+            CLOUDFLARE_API_BASE,
+            self.zone_id,
+            name,
+            record_type.as_str()
+        );
+
+        let response: ListDnsResponse = self.make_api_request(&url, Method::Get, None).await?;
+
+        if !response.success {
+            return Err(DnsError::ApiError(format!(
+                "Failed to list DNS records: {:?}",
+                response.errors
+            )));
+        }
+
+        Ok(response.result.and_then(|records| {
+            records
+                .into_iter()
+                .find(|r| r.name == name && r.record_type == record_type.as_str())
+                .map(|r| DnsRecord {
+                    id: r.id,
+                    content: r.content,
+                    managed: r.comment.as_deref() == Some(MANAGED_RECORD_COMMENT),
+                })
+        }))
+    }
+
+    async fn create_record(
+        &self,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<String> {
+        let url = format!("{}/zones/{}/dns_records", CLOUDFLARE_API_BASE, self.zone_id);
+        let body = self.record_body(record_type, name, content);
+
+        let response: CreateDnsResponse = self
+            .make_api_request(&url, Method::Post, Some(body))
+            .await?;
+
+        if !response.success {
+            return Err(DnsError::ApiError(format!(
+                "Failed to create DNS record: {:?}",
+                response.errors
+            )));
+        }
+
+        response
+            .result
+            .map(|record| record.id)
+            .ok_or_else(|| DnsError::ApiError("record created without an id".to_string()))
+    }
+
+    async fn update_record(
+        &self,
+        record_id: &str,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<()> {
+        let url = format!(
+            "{}/zones/{}/dns_records/{}",
+            CLOUDFLARE_API_BASE, self.zone_id, record_id
+        );
+        let body = self.record_body(record_type, name, content);
+
+        let response: UpdateDnsResponse =
+            self.make_api_request(&url, Method::Put, Some(body)).await?;
+
+        if !response.success {
+            return Err(Self::record_error("update", response.errors));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_record(
+        &self,
+        record_id: &str,
+        _record_type: RecordType,
+        _name: &str,
+    ) -> DnsResult<()> {
+        let url = format!(
+            "{}/zones/{}/dns_records/{}",
+            CLOUDFLARE_API_BASE, self.zone_id, record_id
+        );
+
+        let response: DeleteDnsResponse = self.make_api_request(&url, Method::Delete, None).await?;
+
+        if !response.success {
+            return Err(Self::record_error("delete", response.errors));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_records_carry_the_marker() {
+        let provider = CloudflareProvider::new("zone".to_string(), "token".to_string(), 60);
+        let body = provider.record_body(RecordType::A, "home.example.com", "1.2.3.4");
+        assert_eq!(body["comment"], MANAGED_RECORD_COMMENT);
+
+        let records: Vec<CloudflareRecord> = serde_json::from_str(
+            r#"[{"id": "1", "type": "A", "name": "www.example.com", "content": "1.2.3.4"}]"#,
+        )
+        .unwrap();
+        assert_eq!(records[0].comment, None);
+    }
+
+    #[test]
+    fn missing_record_maps_to_not_found() {
+        let errors: Vec<ApiError> =
+            serde_json::from_str(r#"[{"code": 81044, "message": "Record does not exist."}]"#)
+                .unwrap();
+        assert!(matches!(
+            CloudflareProvider::record_error("update", Some(errors)),
+            DnsError::NotFound
+        ));
+
+        let errors: Vec<ApiError> =
+            serde_json::from_str(r#"[{"code": 9109, "message": "Invalid access token"}]"#).unwrap();
+        assert!(matches!(
+            CloudflareProvider::record_error("update", Some(errors)),
+            DnsError::ApiError(_)
+        ));
+        assert!(matches!(
+            CloudflareProvider::record_error("delete", None),
+            DnsError::ApiError(_)
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+use super::{DnsError, DnsProvider, DnsRecord, DnsResult, RecordType};
+use async_trait::async_trait;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+/// Record held by the in-memory provider
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryRecord {
+    pub record_type: RecordType,
+    pub name: String,
+    pub content: String,
+    /// Whether the record carries this worker's marker
+    pub managed: bool,
+}
+
+/// In-memory DNS provider, used to exercise `DnsManager` without a real zone
+#[derive(Default)]
+pub struct MemoryProvider {
+    records: RefCell<BTreeMap<String, MemoryRecord>>,
+    next_id: Cell<u32>,
+    writes: Cell<u32>,
+    failure: RefCell<Option<String>>,
+}
+
+impl MemoryProvider {
+    /// Create an empty zone
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seed a record as if it had been created outside this worker
+    pub fn insert(&self, record_type: RecordType, name: &str, content: &str) -> String {
+        self.store(record_type, name, content, false)
+    }
+
+    fn store(&self, record_type: RecordType, name: &str, content: &str, managed: bool) -> String {
+        let id = self.allocate_id();
+        self.records.borrow_mut().insert(
+            id.clone(),
+            MemoryRecord {
+                record_type,
+                name: name.to_string(),
+                content: content.to_string(),
+                managed,
+            },
+        );
+        id
+    }
+
+    /// Remove a record as if it had been deleted outside this worker
+    pub fn remove(&self, record_id: &str) -> Option<MemoryRecord> {
+        self.records.borrow_mut().remove(record_id)
+    }
+
+    /// Look up a record by identifier
+    pub fn get(&self, record_id: &str) -> Option<MemoryRecord> {
+        self.records.borrow().get(record_id).cloned()
+    }
+
+    /// Content of the record with the given name and type, if any
+    pub fn content(&self, name: &str, record_type: RecordType) -> Option<String> {
+        self.records
+            .borrow()
+            .values()
+            .find(|r| r.name == name && r.record_type == record_type)
+            .map(|r| r.content.clone())
+    }
+
+    /// Number of records in the zone
+    pub fn len(&self) -> usize {
+        self.records.borrow().len()
+    }
+
+    /// Whether the zone holds no records
+    pub fn is_empty(&self) -> bool {
+        self.records.borrow().is_empty()
+    }
+
+    /// Number of create, update and delete calls made so far
+    pub fn writes(&self) -> u32 {
+        self.writes.get()
+    }
+
+    /// Make every subsequent call fail with the given API error, or succeed again with `None`
+    pub fn set_failure(&self, reason: Option<&str>) {
+        *self.failure.borrow_mut() = reason.map(str::to_string);
+    }
+
+    fn check_failure(&self) -> DnsResult<()> {
+        match self.failure.borrow().as_ref() {
+            Some(reason) => Err(DnsError::ApiError(reason.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn allocate_id(&self) -> String {
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        format!("mem-{}", id)
+    }
+
+    fn record_write(&self) {
+        self.writes.set(self.writes.get() + 1);
+    }
+}
+
+#[async_trait(?Send)]
+impl DnsProvider for MemoryProvider {
+    async fn find_record(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<DnsRecord>> {
+        self.check_failure()?;
+        Ok(self
+            .records
+            .borrow()
+            .iter()
+            .find(|(_, r)| r.name == name && r.record_type == record_type)
+            .map(|(id, r)| DnsRecord {
+                id: id.clone(),
+                content: r.content.clone(),
+                managed: r.managed,
+            }))
+    }
+
+    async fn create_record(
+        &self,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<String> {
+        self.check_failure()?;
+        self.record_write();
+        Ok(self.store(record_type, name, content, true))
+    }
+
+    async fn update_record(
+        &self,
+        record_id: &str,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<()> {
+        self.check_failure()?;
+        self.record_write();
+        let mut records = self.records.borrow_mut();
+        let record = records.get_mut(record_id).ok_or(DnsError::NotFound)?;
+        *record = MemoryRecord {
+            record_type,
+            name: name.to_string(),
+            content: content.to_string(),
+            managed: true,
+        };
+        Ok(())
+    }
+
+    async fn delete_record(
+        &self,
+        record_id: &str,
+        _record_type: RecordType,
+        _name: &str,
+    ) -> DnsResult<()> {
+        self.check_failure()?;
+        self.record_write();
+        self.remove(record_id).map(|_| ()).ok_or(DnsError::NotFound)
+    }
+}
@@ -0,0 +1,344 @@
+use super::{DnsError, DnsProvider, DnsRecord, DnsResult, RecordType};
+use async_trait::async_trait;
+use serde::Deserialize;
+use worker::*;
+
+// Constants
+const HEADER_API_KEY: &str = "X-API-Key";
+const CONTENT_TYPE_JSON: &str = "application/json";
+const CHANGETYPE_REPLACE: &str = "REPLACE";
+const CHANGETYPE_DELETE: &str = "DELETE";
+
+/// PowerDNS zone as returned by `GET /zones/{zone}`
+#[derive(Deserialize)]
+struct ZoneResponse {
+    #[serde(default)]
+    rrsets: Vec<RrSet>,
+}
+
+/// PowerDNS resource record set
+#[derive(Deserialize)]
+struct RrSet {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    #[serde(default)]
+    records: Vec<RrRecord>,
+}
+
+/// Single record within an RRset
+#[derive(Deserialize)]
+struct RrRecord {
+    content: String,
+    #[serde(default)]
+    disabled: bool,
+}
+
+/// PowerDNS API error body
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// DNS provider backed by the PowerDNS Authoritative HTTP API
+///
+/// PowerDNS addresses records by RRset rather than by id, so the canonical
+/// record name doubles as the record identifier.
+pub struct PowerDnsProvider {
+    api_url: String,
+    api_key: String,
+    server_id: String,
+    zone: String,
+    ttl: u32,
+}
+
+impl PowerDnsProvider {
+    /// Create a new PowerDNS provider for the given zone
+    pub fn new(
+        api_url: String,
+        api_key: String,
+        server_id: String,
+        zone: String,
+        ttl: u32,
+    ) -> Self {
+        Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_key,
+            server_id,
+            zone: Self::canonical_name(&zone),
+            ttl,
+        }
+    }
+
+    /// Zone endpoint URL
+    fn zone_url(&self) -> String {
+        format!(
+            "{}/api/v1/servers/{}/zones/{}",
+            self.api_url, self.server_id, self.zone
+        )
+    }
+
+    /// Names in the PowerDNS API are fully-qualified with a trailing dot
+    pub fn canonical_name(name: &str) -> String {
+        format!("{}.", name.trim_end_matches('.'))
+    }
+
+    /// TXT content must be sent as a quoted character-string
+    fn encode_content(record_type: RecordType, content: &str) -> String {
+        match record_type {
+            RecordType::TXT => {
+                format!("\"{}\"", content.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            _ => content.to_string(),
+        }
+    }
+
+    /// Reverse of `encode_content` for records read back from the API
+    fn decode_content(record_type: RecordType, content: &str) -> String {
+        match record_type {
+            RecordType::TXT => content
+                .strip_prefix('"')
+                .and_then(|c| c.strip_suffix('"'))
+                .map(|c| c.replace("\\\"", "\"").replace("\\\\", "\\"))
+                .unwrap_or_else(|| content.to_string()),
+            _ => content.to_string(),
+        }
+    }
+
+    /// Build the PATCH body replacing or deleting a single RRset
+    pub fn rrset_patch(
+        record_type: RecordType,
+        ttl: u32,
+        name: &str,
+        content: Option<&str>,
+    ) -> serde_json::Value {
+        let name = Self::canonical_name(name);
+        match content {
+            Some(content) => serde_json::json!({
+                "rrsets": [{
+                    "name": name,
+                    "type": record_type.as_str(),
+                    "ttl": ttl,
+                    "changetype": CHANGETYPE_REPLACE,
+                    "records": [{
+                        "content": Self::encode_content(record_type, content),
+                        "disabled": false
+                    }]
+                }]
+            }),
+            None => serde_json::json!({
+                "rrsets": [{
+                    "name": name,
+                    "type": record_type.as_str(),
+                    "changetype": CHANGETYPE_DELETE
+                }]
+            }),
+        }
+    }
+
+    /// Find the first enabled record with the given name and type in a zone listing
+    fn find_in_zone(
+        body: &str,
+        name: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<DnsRecord>> {
+        let zone: ZoneResponse =
+            serde_json::from_str(body).map_err(|e| DnsError::SerializationError(e.to_string()))?;
+        let name = Self::canonical_name(name);
+
+        Ok(zone
+            .rrsets
+            .into_iter()
+            .find(|rrset| {
+                rrset.name.eq_ignore_ascii_case(&name) && rrset.record_type == record_type.as_str()
+            })
+            .and_then(|rrset| rrset.records.into_iter().find(|r| !r.disabled))
+            .map(|record| DnsRecord {
+                id: name.clone(),
+                content: Self::decode_content(record_type, &record.content),
+                managed: false,
+            }))
+    }
+
+    /// Make an authenticated API request to PowerDNS, returning the response body
+    async fn make_api_request(
+        &self,
+        url: &str,
+        method: Method,
+        body: Option<serde_json::Value>,
+    ) -> DnsResult<String> {
+        let mut init = RequestInit::new();
+        init.with_method(method);
+
+        if let Some(body_data) = body {
+            init.with_body(Some(body_data.to_string().into()));
+        }
+
+        let mut req = Request::new_with_init(url, &init)?;
+        req.headers_mut()?.set(HEADER_API_KEY, &self.api_key)?;
+        req.headers_mut()?.set("Content-Type", CONTENT_TYPE_JSON)?;
+
+        let mut resp = Fetch::Request(req).send().await?;
+        let status = resp.status_code();
+        let text = resp.text().await?;
+
+        match status {
+            200..=299 => Ok(text),
+            404 => Err(DnsError::NotFound),
+            _ => {
+                let message = serde_json::from_str::<ErrorResponse>(&text)
+                    .map(|e| e.error)
+                    .unwrap_or(text);
+                Err(DnsError::ApiError(format!(
+                    "PowerDNS returned {}: {}",
+                    status, message
+                )))
+            }
+        }
+    }
+
+    /// Replace or delete a single RRset
+    async fn patch_rrset(
+        &self,
+        record_type: RecordType,
+        name: &str,
+        content: Option<&str>,
+    ) -> DnsResult<()> {
+        let body = Self::rrset_patch(record_type, self.ttl, name, content);
+        self.make_api_request(&self.zone_url(), Method::Patch, Some(body))
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl DnsProvider for PowerDnsProvider {
+    async fn find_record(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<DnsRecord>> {
+        let url = format!(
+            "{}?rrset_name={}&rrset_type={}",
+            self.zone_url(),
+            Self::canonical_name(name),
+            record_type.as_str()
+        );
+
+        let body = self.make_api_request(&url, Method::Get, None).await?;
+        Self::find_in_zone(&body, name, record_type)
+    }
+
+    async fn create_record(
+        &self,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<String> {
+        self.patch_rrset(record_type, name, Some(content)).await?;
+        Ok(Self::canonical_name(name))
+    }
+
+    async fn update_record(
+        &self,
+        _record_id: &str,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<()> {
+        self.patch_rrset(record_type, name, Some(content)).await
+    }
+
+    async fn delete_record(
+        &self,
+        _record_id: &str,
+        record_type: RecordType,
+        name: &str,
+    ) -> DnsResult<()> {
+        self.patch_rrset(record_type, name, None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_names() {
+        assert_eq!(
+            PowerDnsProvider::canonical_name("home.example.com"),
+            "home.example.com."
+        );
+        assert_eq!(
+            PowerDnsProvider::canonical_name("home.example.com."),
+            "home.example.com."
+        );
+    }
+
+    #[test]
+    fn replace_patch_body() {
+        let body =
+            PowerDnsProvider::rrset_patch(RecordType::A, 300, "home.example.com", Some("1.2.3.4"));
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "rrsets": [{
+                    "name": "home.example.com.",
+                    "type": "A",
+                    "ttl": 300,
+                    "changetype": "REPLACE",
+                    "records": [{"content": "1.2.3.4", "disabled": false}]
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn delete_patch_body() {
+        let body = PowerDnsProvider::rrset_patch(RecordType::AAAA, 300, "home.example.com.", None);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "rrsets": [{"name": "home.example.com.", "type": "AAAA", "changetype": "DELETE"}]
+            })
+        );
+    }
+
+    #[test]
+    fn txt_content_is_quoted() {
+        let body =
+            PowerDnsProvider::rrset_patch(RecordType::TXT, 60, "home.example.com", Some("a \"b\""));
+        assert_eq!(
+            body["rrsets"][0]["records"][0]["content"],
+            "\"a \\\"b\\\"\""
+        );
+        assert_eq!(
+            PowerDnsProvider::decode_content(RecordType::TXT, "\"a \\\"b\\\"\""),
+            "a \"b\""
+        );
+    }
+
+    #[test]
+    fn finds_record_in_zone_listing() {
+        let zone = r#"{
+            "name": "example.com.",
+            "rrsets": [
+                {"name": "other.example.com.", "type": "A", "records": [{"content": "5.5.5.5", "disabled": false}]},
+                {"name": "home.example.com.", "type": "AAAA", "records": [{"content": "2001:db8::1", "disabled": false}]},
+                {"name": "home.example.com.", "type": "A", "records": [
+                    {"content": "9.9.9.9", "disabled": true},
+                    {"content": "1.2.3.4", "disabled": false}
+                ]}
+            ]
+        }"#;
+
+        let record = PowerDnsProvider::find_in_zone(zone, "home.example.com", RecordType::A)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.id, "home.example.com.");
+        assert_eq!(record.content, "1.2.3.4");
+
+        let missing = PowerDnsProvider::find_in_zone(zone, "home.example.com", RecordType::TXT);
+        assert!(missing.unwrap().is_none());
+    }
+}
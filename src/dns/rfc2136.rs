@@ -0,0 +1,471 @@
+use super::wire::{
+    CLASS_ANY, CLASS_IN, MessageBuilder, OPCODE_QUERY, OPCODE_UPDATE, ParsedMessage, RCODE_NOERROR,
+    RCODE_NXDOMAIN, Section, TYPE_SOA, decode_rdata, encode_rdata, rcode_name, type_code,
+};
+use super::{DnsError, DnsProvider, DnsRecord, DnsResult, RecordType, TsigKey};
+use async_trait::async_trait;
+use std::cell::Cell;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use worker::*;
+
+/// Carries one DNS message to the server and returns its reply
+#[async_trait(?Send)]
+pub trait DnsTransport {
+    async fn exchange(&self, message: &[u8]) -> DnsResult<Vec<u8>>;
+}
+
+/// DNS over TCP using the Workers `connect()` socket API
+pub struct WorkerTcpTransport {
+    host: String,
+    port: u16,
+}
+
+impl WorkerTcpTransport {
+    pub fn new(host: String, port: u16) -> Self {
+        Self { host, port }
+    }
+}
+
+#[async_trait(?Send)]
+impl DnsTransport for WorkerTcpTransport {
+    async fn exchange(&self, message: &[u8]) -> DnsResult<Vec<u8>> {
+        let mut socket = Socket::builder().connect(self.host.clone(), self.port)?;
+        let reply = exchange_stream(&mut socket, message).await;
+        // The reply is complete at this point, so a failing close is not worth reporting
+        let _ = socket.close().await;
+        reply
+    }
+}
+
+/// Send a length-prefixed message over a TCP stream and read the length-prefixed reply
+pub async fn exchange_stream<S>(stream: &mut S, message: &[u8]) -> DnsResult<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let io_error = |e: std::io::Error| DnsError::ApiError(format!("DNS connection failed: {}", e));
+    let len = u16::try_from(message.len())
+        .map_err(|_| DnsError::InvalidInput("DNS message too long".to_string()))?;
+
+    let mut framed = Vec::with_capacity(message.len() + 2);
+    framed.extend_from_slice(&len.to_be_bytes());
+    framed.extend_from_slice(message);
+    stream.write_all(&framed).await.map_err(io_error)?;
+    stream.flush().await.map_err(io_error)?;
+
+    let reply_len = stream.read_u16().await.map_err(io_error)?;
+    let mut reply = vec![0; usize::from(reply_len)];
+    stream.read_exact(&mut reply).await.map_err(io_error)?;
+    Ok(reply)
+}
+
+/// DNS provider speaking RFC 2136 dynamic update, signed with TSIG
+///
+/// Works with any standards-compliant primary such as BIND or Knot. Records
+/// are addressed by name and type, so the canonical record name doubles as
+/// the record identifier; lookups are regular TSIG-signed queries.
+pub struct Rfc2136Provider {
+    transport: Box<dyn DnsTransport>,
+    zone: String,
+    key: TsigKey,
+    ttl: u32,
+    clock: fn() -> u64,
+    next_id: Cell<u16>,
+}
+
+impl Rfc2136Provider {
+    /// Create a provider for the given zone, sending messages over `transport`
+    pub fn new(transport: Box<dyn DnsTransport>, zone: String, key: TsigKey, ttl: u32) -> Self {
+        let clock: fn() -> u64 = || Date::now().as_millis() / 1000;
+        Self {
+            transport,
+            zone: Self::canonical_name(&zone),
+            key,
+            ttl,
+            clock,
+            next_id: Cell::new(0),
+        }
+    }
+
+    /// Replace the source of Unix time used for TSIG timestamps
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Names are handled fully-qualified with a trailing dot
+    fn canonical_name(name: &str) -> String {
+        format!("{}.", name.trim_end_matches('.'))
+    }
+
+    /// Message id for the next request
+    fn message_id(&self, now: u64) -> u16 {
+        let id = self.next_id.get().wrapping_add(1);
+        self.next_id.set(id);
+        id ^ (now as u16)
+    }
+
+    /// Build the UPDATE message replacing (or, without content, deleting) one RRset
+    fn update_message(
+        &self,
+        id: u16,
+        record_type: RecordType,
+        name: &str,
+        content: Option<&str>,
+    ) -> DnsResult<Vec<u8>> {
+        let rtype = type_code(record_type);
+        let mut message = MessageBuilder::new(id, OPCODE_UPDATE)
+            .question(&self.zone, TYPE_SOA, CLASS_IN)?
+            // Delete the whole RRset first so the add below replaces it
+            .record(Section::Authority, name, rtype, CLASS_ANY, 0, &[])?;
+
+        if let Some(content) = content {
+            let rdata = encode_rdata(record_type, content)?;
+            message =
+                message.record(Section::Authority, name, rtype, CLASS_IN, self.ttl, &rdata)?;
+        }
+        Ok(message.finish())
+    }
+
+    /// Sign a message, send it and verify the signed reply
+    async fn send(&self, mut message: Vec<u8>, now: u64) -> DnsResult<ParsedMessage> {
+        let request = ParsedMessage::parse(&message)?;
+        let request_mac = self.key.sign(&mut message, None, now)?;
+        let reply = self.transport.exchange(&message).await?;
+
+        let parsed = ParsedMessage::parse(&reply)?;
+        if parsed.id != request.id
+            || parsed.opcode() != request.opcode()
+            || parsed.questions != request.questions
+        {
+            return Err(DnsError::ApiError(
+                "DNS reply does not match the request".to_string(),
+            ));
+        }
+        self.key
+            .verify(&reply, Some(&request_mac), (self.clock)())
+            .map_err(|e| match parsed.rcode() {
+                RCODE_NOERROR => e,
+                rcode => DnsError::ApiError(format!("DNS server returned {}", rcode_name(rcode))),
+            })?;
+        Ok(parsed)
+    }
+
+    /// Replace or delete a single RRset
+    async fn update_rrset(
+        &self,
+        record_type: RecordType,
+        name: &str,
+        content: Option<&str>,
+    ) -> DnsResult<()> {
+        let now = (self.clock)();
+        let message = self.update_message(self.message_id(now), record_type, name, content)?;
+        let reply = self.send(message, now).await?;
+
+        match reply.rcode() {
+            RCODE_NOERROR => Ok(()),
+            rcode => Err(DnsError::ApiError(format!(
+                "DNS update rejected with {}",
+                rcode_name(rcode)
+            ))),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl DnsProvider for Rfc2136Provider {
+    async fn find_record(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<DnsRecord>> {
+        let name = Self::canonical_name(name);
+        let rtype = type_code(record_type);
+        let now = (self.clock)();
+        let message = MessageBuilder::new(self.message_id(now), OPCODE_QUERY)
+            .question(&name, rtype, CLASS_IN)?
+            .finish();
+        let reply = self.send(message, now).await?;
+
+        match reply.rcode() {
+            RCODE_NOERROR | RCODE_NXDOMAIN => {}
+            rcode => {
+                return Err(DnsError::ApiError(format!(
+                    "DNS query failed with {}",
+                    rcode_name(rcode)
+                )));
+            }
+        }
+
+        let answer = reply
+            .section(Section::Answer)
+            .find(|r| r.rtype == rtype && r.name.eq_ignore_ascii_case(&name));
+
+        answer
+            .map(|r| {
+                Ok(DnsRecord {
+                    id: name.clone(),
+                    content: decode_rdata(record_type, &r.rdata)?,
+                    managed: false,
+                })
+            })
+            .transpose()
+    }
+
+    async fn create_record(
+        &self,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<String> {
+        self.update_rrset(record_type, name, Some(content)).await?;
+        Ok(Self::canonical_name(name))
+    }
+
+    async fn update_record(
+        &self,
+        _record_id: &str,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<()> {
+        self.update_rrset(record_type, name, Some(content)).await
+    }
+
+    async fn delete_record(
+        &self,
+        _record_id: &str,
+        record_type: RecordType,
+        name: &str,
+    ) -> DnsResult<()> {
+        self.update_rrset(record_type, name, None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::wire::{CLASS_NONE, TYPE_A, TYPE_AAAA};
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    const NOW: u64 = 1_700_000_000;
+    const KEY_NAME: &str = "ddns-key";
+    const KEY_SECRET: &str = "c2VjcmV0LWtleS1mb3ItdGVzdHM=";
+    const ZONE: &str = "example.com";
+    const RECORD: &str = "home.example.com";
+
+    type Zone = Rc<RefCell<BTreeMap<(String, u16), Vec<Vec<u8>>>>>;
+
+    /// Plain TCP transport for native tests
+    struct TokioTcpTransport(SocketAddr);
+
+    #[async_trait(?Send)]
+    impl DnsTransport for TokioTcpTransport {
+        async fn exchange(&self, message: &[u8]) -> DnsResult<Vec<u8>> {
+            let mut stream = TcpStream::connect(self.0)
+                .await
+                .map_err(|e| DnsError::ApiError(e.to_string()))?;
+            exchange_stream(&mut stream, message).await
+        }
+    }
+
+    fn key() -> TsigKey {
+        TsigKey::from_base64(KEY_NAME, KEY_SECRET).unwrap()
+    }
+
+    /// Minimal primary server: answers queries and applies updates to `zone`
+    async fn serve(listener: TcpListener, zone: Zone, key: TsigKey, connections: usize) {
+        for _ in 0..connections {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let len = stream.read_u16().await.unwrap();
+            let mut request = vec![0; usize::from(len)];
+            stream.read_exact(&mut request).await.unwrap();
+
+            let mut reply = match key.verify(&request, None, NOW) {
+                Ok(request_mac) => {
+                    let mut reply = handle(&request, &zone);
+                    key.sign(&mut reply, Some(&request_mac), NOW).unwrap();
+                    reply
+                }
+                Err(_) => {
+                    let parsed = ParsedMessage::parse(&request).unwrap();
+                    let (qname, qtype, qclass) = parsed.questions[0].clone();
+                    MessageBuilder::response(parsed.id, parsed.opcode(), 9)
+                        .question(&qname, qtype, qclass)
+                        .unwrap()
+                        .finish()
+                }
+            };
+
+            let mut framed = (reply.len() as u16).to_be_bytes().to_vec();
+            framed.append(&mut reply);
+            stream.write_all(&framed).await.unwrap();
+        }
+    }
+
+    fn handle(request: &[u8], zone: &Zone) -> Vec<u8> {
+        let request = ParsedMessage::parse(request).unwrap();
+        let (qname, qtype, qclass) = request.questions[0].clone();
+
+        if request.opcode() == OPCODE_UPDATE {
+            let mut zone = zone.borrow_mut();
+            for update in request.section(Section::Authority) {
+                let key = (update.name.clone(), update.rtype);
+                match update.class {
+                    CLASS_ANY => {
+                        zone.remove(&key);
+                    }
+                    CLASS_NONE => zone.entry(key).or_default().retain(|r| *r != update.rdata),
+                    _ => zone.entry(key).or_default().push(update.rdata.clone()),
+                }
+            }
+            return MessageBuilder::response(request.id, OPCODE_UPDATE, RCODE_NOERROR)
+                .question(&qname, qtype, qclass)
+                .unwrap()
+                .finish();
+        }
+
+        let answers = zone
+            .borrow()
+            .get(&(qname.clone(), qtype))
+            .cloned()
+            .unwrap_or_default();
+        let rcode = if answers.is_empty() {
+            RCODE_NXDOMAIN
+        } else {
+            RCODE_NOERROR
+        };
+        let mut reply = MessageBuilder::response(request.id, OPCODE_QUERY, rcode)
+            .question(&qname, qtype, qclass)
+            .unwrap();
+        for rdata in answers {
+            reply = reply
+                .record(Section::Answer, &qname, qtype, CLASS_IN, 60, &rdata)
+                .unwrap();
+        }
+        reply.finish()
+    }
+
+    async fn start(zone: Zone, key: TsigKey, connections: usize) -> Rfc2136Provider {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::task::spawn_local(serve(listener, zone, key, connections));
+        Rfc2136Provider::new(
+            Box::new(TokioTcpTransport(addr)),
+            ZONE.to_string(),
+            TsigKey::from_base64(KEY_NAME, KEY_SECRET).unwrap(),
+            300,
+        )
+        .with_clock(|| NOW)
+    }
+
+    #[test]
+    fn update_message_replaces_rrset() {
+        let provider = Rfc2136Provider::new(
+            Box::new(TokioTcpTransport(([127, 0, 0, 1], 53).into())),
+            ZONE.to_string(),
+            key(),
+            300,
+        );
+        let message = provider
+            .update_message(1, RecordType::AAAA, RECORD, Some("2001:db8::1"))
+            .unwrap();
+        let parsed = ParsedMessage::parse(&message).unwrap();
+
+        assert_eq!(parsed.opcode(), OPCODE_UPDATE);
+        assert_eq!(
+            parsed.questions,
+            vec![("example.com.".to_string(), TYPE_SOA, CLASS_IN)]
+        );
+        let updates: Vec<_> = parsed.section(Section::Authority).collect();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(
+            (updates[0].rtype, updates[0].class, updates[0].ttl),
+            (TYPE_AAAA, CLASS_ANY, 0)
+        );
+        assert!(updates[0].rdata.is_empty());
+        assert_eq!(
+            (updates[1].rtype, updates[1].class, updates[1].ttl),
+            (TYPE_AAAA, CLASS_IN, 300)
+        );
+        assert_eq!(
+            decode_rdata(RecordType::AAAA, &updates[1].rdata).unwrap(),
+            "2001:db8::1"
+        );
+
+        let delete = provider
+            .update_message(2, RecordType::A, RECORD, None)
+            .unwrap();
+        assert_eq!(ParsedMessage::parse(&delete).unwrap().records.len(), 1);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn updates_and_queries_over_tcp() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let zone = Zone::default();
+                let provider = start(zone.clone(), key(), 5).await;
+
+                assert!(
+                    provider
+                        .find_record(RECORD, RecordType::A)
+                        .await
+                        .unwrap()
+                        .is_none()
+                );
+
+                let id = provider
+                    .create_record(RecordType::A, RECORD, "1.2.3.4")
+                    .await
+                    .unwrap();
+                assert_eq!(id, "home.example.com.");
+                provider
+                    .update_record(&id, RecordType::A, RECORD, "5.6.7.8")
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    zone.borrow().get(&(id.clone(), TYPE_A)),
+                    Some(&vec![vec![5, 6, 7, 8]])
+                );
+
+                let record = provider
+                    .find_record(RECORD, RecordType::A)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(record.id, id);
+                assert_eq!(record.content, "5.6.7.8");
+
+                provider
+                    .delete_record(&id, RecordType::A, RECORD)
+                    .await
+                    .unwrap();
+                assert!(zone.borrow().is_empty());
+            })
+            .await;
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejected_signature_is_reported() {
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let zone = Zone::default();
+                let server_key = TsigKey::new(KEY_NAME, b"a different secret".to_vec());
+                let provider = start(zone.clone(), server_key, 1).await;
+
+                let err = provider
+                    .create_record(RecordType::A, RECORD, "1.2.3.4")
+                    .await
+                    .unwrap_err();
+                assert_eq!(
+                    err.to_string(),
+                    "DNS API error: DNS server returned NOTAUTH"
+                );
+                assert!(zone.borrow().is_empty());
+            })
+            .await;
+    }
+}
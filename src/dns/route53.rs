@@ -0,0 +1,515 @@
+use super::{DnsError, DnsProvider, DnsRecord, DnsResult, RecordType, SigV4Signer};
+use async_trait::async_trait;
+use worker::*;
+
+// Constants
+const ROUTE53_API_VERSION: &str = "2013-04-01";
+const ROUTE53_SERVICE: &str = "route53";
+const ROUTE53_XMLNS: &str = "https://route53.amazonaws.com/doc/2013-04-01/";
+const CONTENT_TYPE_XML: &str = "text/xml";
+const ACTION_UPSERT: &str = "UPSERT";
+const ACTION_DELETE: &str = "DELETE";
+
+/// Resource record set as listed by Route 53
+#[derive(Debug, PartialEq)]
+struct ResourceRecordSet {
+    ttl: u32,
+    value: String,
+}
+
+/// DNS provider backed by the Amazon Route 53 API
+///
+/// Requests are signed with SigV4 directly, so no AWS SDK is needed on wasm.
+/// Route 53 addresses records by name and type, so the canonical record name
+/// doubles as the record identifier.
+pub struct Route53Provider {
+    api_url: String,
+    host: String,
+    base_path: String,
+    hosted_zone_id: String,
+    signer: SigV4Signer,
+    ttl: u32,
+}
+
+impl Route53Provider {
+    /// Create a new Route 53 provider for the given hosted zone
+    ///
+    /// `api_url` is normally `https://route53.amazonaws.com` but may point at a
+    /// mock endpoint, including a path prefix.
+    pub fn new(
+        api_url: String,
+        access_key_id: String,
+        secret_access_key: String,
+        region: String,
+        hosted_zone_id: String,
+        ttl: u32,
+    ) -> Self {
+        let api_url = api_url.trim_end_matches('/').to_string();
+        let authority = api_url
+            .split_once("://")
+            .map_or(api_url.as_str(), |(_, rest)| rest);
+        let (host, base_path) = match authority.find('/') {
+            Some(index) => (&authority[..index], &authority[index..]),
+            None => (authority, ""),
+        };
+
+        Self {
+            host: host.to_string(),
+            base_path: base_path.to_string(),
+            api_url,
+            hosted_zone_id: hosted_zone_id
+                .trim_start_matches("/hostedzone/")
+                .to_string(),
+            signer: SigV4Signer::new(
+                access_key_id,
+                secret_access_key,
+                region,
+                ROUTE53_SERVICE.to_string(),
+            ),
+            ttl,
+        }
+    }
+
+    /// Path of the hosted zone's `rrset` resource, relative to the API host
+    fn rrset_path(&self) -> String {
+        format!(
+            "{}/{}/hostedzone/{}/rrset",
+            self.base_path, ROUTE53_API_VERSION, self.hosted_zone_id
+        )
+    }
+
+    /// Names in the Route 53 API are fully-qualified with a trailing dot
+    fn canonical_name(name: &str) -> String {
+        format!("{}.", name.trim_end_matches('.'))
+    }
+
+    /// TXT values must be sent as a quoted character-string
+    fn encode_value(record_type: RecordType, content: &str) -> String {
+        match record_type {
+            RecordType::TXT => {
+                format!("\"{}\"", content.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            _ => content.to_string(),
+        }
+    }
+
+    /// Reverse of `encode_value` for records read back from the API
+    fn decode_value(record_type: RecordType, value: &str) -> String {
+        match record_type {
+            RecordType::TXT => value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .map(|v| v.replace("\\\"", "\"").replace("\\\\", "\\"))
+                .unwrap_or_else(|| value.to_string()),
+            _ => value.to_string(),
+        }
+    }
+
+    /// Build a `ChangeResourceRecordSets` request body with a single change
+    fn change_batch(
+        action: &str,
+        record_type: RecordType,
+        name: &str,
+        ttl: u32,
+        content: &str,
+    ) -> String {
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<ChangeResourceRecordSetsRequest xmlns="{}">"#,
+                "<ChangeBatch><Changes><Change>",
+                "<Action>{}</Action>",
+                "<ResourceRecordSet>",
+                "<Name>{}</Name>",
+                "<Type>{}</Type>",
+                "<TTL>{}</TTL>",
+                "<ResourceRecords><ResourceRecord><Value>{}</Value></ResourceRecord></ResourceRecords>",
+                "</ResourceRecordSet>",
+                "</Change></Changes></ChangeBatch>",
+                "</ChangeResourceRecordSetsRequest>"
+            ),
+            ROUTE53_XMLNS,
+            action,
+            Self::escape_xml(&Self::canonical_name(name)),
+            record_type.as_str(),
+            ttl,
+            Self::escape_xml(&Self::encode_value(record_type, content))
+        )
+    }
+
+    /// Find the record set with the given name and type in a `ListResourceRecordSets` response
+    ///
+    /// Listing starts at the requested name and type but continues with the
+    /// following sets, so the first result still has to be checked.
+    fn find_in_listing(
+        body: &str,
+        name: &str,
+        record_type: RecordType,
+    ) -> Option<ResourceRecordSet> {
+        let name = Self::canonical_name(name);
+
+        Self::xml_elements(body, "ResourceRecordSet")
+            .into_iter()
+            .find(|set| {
+                Self::xml_text(set, "Name").is_some_and(|n| n.eq_ignore_ascii_case(&name))
+                    && Self::xml_text(set, "Type").as_deref() == Some(record_type.as_str())
+            })
+            .and_then(|set| {
+                Some(ResourceRecordSet {
+                    ttl: Self::xml_text(set, "TTL")?.parse().ok()?,
+                    value: Self::decode_value(record_type, &Self::xml_text(set, "Value")?),
+                })
+            })
+    }
+
+    /// Contents of every `<tag>...</tag>` element in a document
+    fn xml_elements<'a>(body: &'a str, tag: &str) -> Vec<&'a str> {
+        let open = format!("<{}>", tag);
+        let close = format!("</{}>", tag);
+        let mut elements = Vec::new();
+        let mut rest = body;
+
+        while let Some(start) = rest.find(&open) {
+            let after_open = &rest[start + open.len()..];
+            let Some(end) = after_open.find(&close) else {
+                break;
+            };
+            elements.push(&after_open[..end]);
+            rest = &after_open[end + close.len()..];
+        }
+        elements
+    }
+
+    /// Unescaped text of the first `<tag>` element in a document
+    fn xml_text(body: &str, tag: &str) -> Option<String> {
+        Self::xml_elements(body, tag)
+            .first()
+            .map(|text| Self::unescape_xml(text))
+    }
+
+    fn escape_xml(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&apos;")
+    }
+
+    fn unescape_xml(text: &str) -> String {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+
+    /// Format a Unix timestamp in milliseconds as an `x-amz-date` value
+    fn amz_date(millis: u64) -> String {
+        let secs = millis / 1000;
+        let (days, day_secs) = (secs / 86_400, secs % 86_400);
+
+        // Civil date from days since the epoch (Howard Hinnant's algorithm)
+        let z = days as i64 + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            year,
+            month,
+            day,
+            day_secs / 3600,
+            day_secs % 3600 / 60,
+            day_secs % 60
+        )
+    }
+
+    /// Make a signed API request to Route 53, returning the response body
+    async fn make_api_request(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Option<String>,
+    ) -> DnsResult<String> {
+        let amz_date = Self::amz_date(Date::now().as_millis());
+        let payload = body.clone().unwrap_or_default();
+        let method_name = method.to_string();
+
+        let mut headers = vec![("host", self.host.as_str()), ("x-amz-date", &amz_date)];
+        if body.is_some() {
+            headers.push(("content-type", CONTENT_TYPE_XML));
+        }
+        let authorization = self.signer.authorization(
+            &method_name,
+            path,
+            query,
+            &headers,
+            payload.as_bytes(),
+            &amz_date,
+        );
+
+        let mut url = format!("{}{}", self.api_url, &path[self.base_path.len()..]);
+        if !query.is_empty() {
+            let query = query
+                .iter()
+                .map(|(k, v)| {
+                    format!(
+                        "{}={}",
+                        SigV4Signer::uri_encode(k),
+                        SigV4Signer::uri_encode(v)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("&");
+            url = format!("{}?{}", url, query);
+        }
+
+        let mut init = RequestInit::new();
+        init.with_method(method);
+        if let Some(body) = body {
+            init.with_body(Some(body.into()));
+        }
+
+        let mut req = Request::new_with_init(&url, &init)?;
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            req.headers_mut()?.set(name, value)?;
+        }
+        req.headers_mut()?.set("Authorization", &authorization)?;
+
+        let mut resp = Fetch::Request(req).send().await?;
+        let status = resp.status_code();
+        let text = resp.text().await?;
+
+        match status {
+            200..=299 => Ok(text),
+            _ => {
+                let message = Self::xml_text(&text, "Message").unwrap_or(text);
+                Err(DnsError::ApiError(format!(
+                    "Route 53 returned {}: {}",
+                    status, message
+                )))
+            }
+        }
+    }
+
+    /// Look up the record set with the given name and type
+    async fn get_record_set(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<ResourceRecordSet>> {
+        let name = Self::canonical_name(name);
+        let query = [
+            ("maxitems", "1"),
+            ("name", name.as_str()),
+            ("type", record_type.as_str()),
+        ];
+
+        let body = self
+            .make_api_request(Method::Get, &self.rrset_path(), &query, None)
+            .await?;
+        Ok(Self::find_in_listing(&body, &name, record_type))
+    }
+
+    /// Submit a single-change batch
+    async fn change_record_set(
+        &self,
+        action: &str,
+        record_type: RecordType,
+        name: &str,
+        ttl: u32,
+        content: &str,
+    ) -> DnsResult<()> {
+        let body = Self::change_batch(action, record_type, name, ttl, content);
+        let path = format!("{}/", self.rrset_path());
+        self.make_api_request(Method::Post, &path, &[], Some(body))
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl DnsProvider for Route53Provider {
+    async fn find_record(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> DnsResult<Option<DnsRecord>> {
+        Ok(self
+            .get_record_set(name, record_type)
+            .await?
+            .map(|set| DnsRecord {
+                id: Self::canonical_name(name),
+                content: set.value,
+                managed: false,
+            }))
+    }
+
+    async fn create_record(
+        &self,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<String> {
+        self.change_record_set(ACTION_UPSERT, record_type, name, self.ttl, content)
+            .await?;
+        Ok(Self::canonical_name(name))
+    }
+
+    async fn update_record(
+        &self,
+        _record_id: &str,
+        record_type: RecordType,
+        name: &str,
+        content: &str,
+    ) -> DnsResult<()> {
+        self.change_record_set(ACTION_UPSERT, record_type, name, self.ttl, content)
+            .await
+    }
+
+    async fn delete_record(
+        &self,
+        _record_id: &str,
+        record_type: RecordType,
+        name: &str,
+    ) -> DnsResult<()> {
+        // Route 53 only deletes a record set when given its exact current values
+        let set = self
+            .get_record_set(name, record_type)
+            .await?
+            .ok_or(DnsError::NotFound)?;
+        let content = set.value.clone();
+        self.change_record_set(ACTION_DELETE, record_type, name, set.ttl, &content)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(api_url: &str) -> Route53Provider {
+        Route53Provider::new(
+            api_url.to_string(),
+            "AKIDEXAMPLE".to_string(),
+            "secret".to_string(),
+            "us-east-1".to_string(),
+            "/hostedzone/Z0123456789".to_string(),
+            300,
+        )
+    }
+
+    #[test]
+    fn endpoint_parsing() {
+        let aws = provider("https://route53.amazonaws.com/");
+        assert_eq!(aws.host, "route53.amazonaws.com");
+        assert_eq!(aws.rrset_path(), "/2013-04-01/hostedzone/Z0123456789/rrset");
+
+        let mock = provider("http://127.0.0.1:4566/route53");
+        assert_eq!(mock.host, "127.0.0.1:4566");
+        assert_eq!(
+            mock.rrset_path(),
+            "/route53/2013-04-01/hostedzone/Z0123456789/rrset"
+        );
+    }
+
+    #[test]
+    fn upsert_change_batch() {
+        let body = Route53Provider::change_batch(
+            ACTION_UPSERT,
+            RecordType::A,
+            "home.example.com",
+            300,
+            "1.2.3.4",
+        );
+
+        assert!(body.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+        assert!(body.contains(
+            "<Change><Action>UPSERT</Action><ResourceRecordSet>\
+             <Name>home.example.com.</Name><Type>A</Type><TTL>300</TTL>\
+             <ResourceRecords><ResourceRecord><Value>1.2.3.4</Value></ResourceRecord></ResourceRecords>\
+             </ResourceRecordSet></Change>"
+        ));
+    }
+
+    #[test]
+    fn txt_values_are_quoted_and_escaped() {
+        let body = Route53Provider::change_batch(
+            ACTION_UPSERT,
+            RecordType::TXT,
+            "home.example.com",
+            60,
+            "a \"b\" <c>",
+        );
+        assert!(body.contains("<Value>&quot;a \\&quot;b\\&quot; &lt;c&gt;&quot;</Value>"));
+        assert_eq!(
+            Route53Provider::decode_value(RecordType::TXT, "\"a \\\"b\\\"\""),
+            "a \"b\""
+        );
+    }
+
+    #[test]
+    fn finds_record_in_listing() {
+        let listing = r#"<?xml version="1.0"?>
+            <ListResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+              <ResourceRecordSets>
+                <ResourceRecordSet>
+                  <Name>home.example.com.</Name>
+                  <Type>A</Type>
+                  <TTL>120</TTL>
+                  <ResourceRecords><ResourceRecord><Value>1.2.3.4</Value></ResourceRecord></ResourceRecords>
+                </ResourceRecordSet>
+              </ResourceRecordSets>
+              <IsTruncated>false</IsTruncated>
+              <MaxItems>1</MaxItems>
+            </ListResourceRecordSetsResponse>"#;
+
+        assert_eq!(
+            Route53Provider::find_in_listing(listing, "home.example.com", RecordType::A),
+            Some(ResourceRecordSet {
+                ttl: 120,
+                value: "1.2.3.4".to_string()
+            })
+        );
+        // Listing continues past the requested set, which must not be mistaken for a match
+        assert_eq!(
+            Route53Provider::find_in_listing(listing, "home.example.com", RecordType::AAAA),
+            None
+        );
+        assert_eq!(
+            Route53Provider::find_in_listing(listing, "away.example.com", RecordType::A),
+            None
+        );
+    }
+
+    #[test]
+    fn error_message_extraction() {
+        let error = r#"<ErrorResponse><Error><Type>Sender</Type><Code>InvalidChangeBatch</Code>
+            <Message>Tried to delete resource record set but it was not found</Message></Error></ErrorResponse>"#;
+        assert_eq!(
+            Route53Provider::xml_text(error, "Message").as_deref(),
+            Some("Tried to delete resource record set but it was not found")
+        );
+    }
+
+    #[test]
+    fn amz_date_formatting() {
+        let test_cases = vec![
+            (0, "19700101T000000Z"),
+            (1_440_938_160_000, "20150830T123600Z"),
+            (1_709_164_799_999, "20240228T235959Z"),
+            (1_709_164_800_000, "20240229T000000Z"),
+        ];
+
+        for (millis, expected) in test_cases {
+            assert_eq!(Route53Provider::amz_date(millis), expected);
+        }
+    }
+}
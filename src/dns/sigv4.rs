@@ -0,0 +1,224 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+// Constants
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const TERMINATOR: &str = "aws4_request";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// AWS Signature Version 4 request signer
+///
+/// Only what the Route 53 API needs: header-based signing of a single request.
+pub struct SigV4Signer {
+    access_key_id: String,
+    secret_access_key: String,
+    region: String,
+    service: String,
+}
+
+impl SigV4Signer {
+    /// Create a signer for the given credentials, region and service
+    pub fn new(
+        access_key_id: String,
+        secret_access_key: String,
+        region: String,
+        service: String,
+    ) -> Self {
+        Self {
+            access_key_id,
+            secret_access_key,
+            region,
+            service,
+        }
+    }
+
+    /// Compute the `Authorization` header value for a request
+    ///
+    /// `headers` must include `host` and `x-amz-date`; `amz_date` is the same
+    /// `YYYYMMDD'T'HHMMSS'Z'` timestamp sent in `x-amz-date`.
+    pub fn authorization(
+        &self,
+        method: &str,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        payload: &[u8],
+        amz_date: &str,
+    ) -> String {
+        let date = &amz_date[..amz_date.len().min(8)];
+        let scope = format!("{}/{}/{}/{}", date, self.region, self.service, TERMINATOR);
+        let (canonical_request, signed_headers) =
+            Self::canonical_request(method, path, query, headers, payload);
+
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signature = hex::encode(Self::hmac(
+            &self.signing_key(date),
+            string_to_sign.as_bytes(),
+        ));
+
+        format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, self.access_key_id, scope, signed_headers, signature
+        )
+    }
+
+    /// Build the canonical request and the signed header list
+    fn canonical_request(
+        method: &str,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        payload: &[u8],
+    ) -> (String, String) {
+        let canonical_uri = path
+            .split('/')
+            .map(Self::uri_encode)
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (Self::uri_encode(k), Self::uri_encode(v)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut headers: Vec<(String, String)> = headers
+            .iter()
+            .map(|(k, v)| {
+                let value = v.split_whitespace().collect::<Vec<_>>().join(" ");
+                (k.to_ascii_lowercase(), value)
+            })
+            .collect();
+        headers.sort();
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            canonical_uri,
+            canonical_query,
+            canonical_headers,
+            signed_headers,
+            hex::encode(Sha256::digest(payload))
+        );
+        (canonical_request, signed_headers)
+    }
+
+    /// Derive the date-scoped signing key
+    fn signing_key(&self, date: &str) -> Vec<u8> {
+        let secret = format!("AWS4{}", self.secret_access_key);
+        let k_date = Self::hmac(secret.as_bytes(), date.as_bytes());
+        let k_region = Self::hmac(&k_date, self.region.as_bytes());
+        let k_service = Self::hmac(&k_region, self.service.as_bytes());
+        Self::hmac(&k_service, TERMINATOR.as_bytes())
+    }
+
+    fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Percent-encode everything except RFC 3986 unreserved characters
+    pub fn uri_encode(value: &str) -> String {
+        value
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_signer(service: &str) -> SigV4Signer {
+        SigV4Signer::new(
+            "AKIDEXAMPLE".to_string(),
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            "us-east-1".to_string(),
+            service.to_string(),
+        )
+    }
+
+    #[test]
+    fn aws_test_suite_get_vanilla() {
+        let authorization = example_signer("service").authorization(
+            "GET",
+            "/",
+            &[],
+            &[
+                ("Host", "example.amazonaws.com"),
+                ("X-Amz-Date", "20150830T123600Z"),
+            ],
+            b"",
+            "20150830T123600Z",
+        );
+
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn aws_documentation_iam_example() {
+        let authorization = example_signer("iam").authorization(
+            "GET",
+            "/",
+            &[("Version", "2010-05-08"), ("Action", "ListUsers")],
+            &[
+                (
+                    "Content-Type",
+                    "application/x-www-form-urlencoded; charset=utf-8",
+                ),
+                ("Host", "iam.amazonaws.com"),
+                ("X-Amz-Date", "20150830T123600Z"),
+            ],
+            b"",
+            "20150830T123600Z",
+        );
+
+        assert!(authorization.ends_with(
+            "Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        ));
+    }
+
+    #[test]
+    fn uri_encoding() {
+        assert_eq!(SigV4Signer::uri_encode("abc-_.~09"), "abc-_.~09");
+        assert_eq!(SigV4Signer::uri_encode("a b/c="), "a%20b%2Fc%3D");
+        assert_eq!(
+            SigV4Signer::uri_encode("home.example.com."),
+            "home.example.com."
+        );
+    }
+}
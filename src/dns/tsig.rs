@@ -0,0 +1,275 @@
+use super::wire::{
+    CLASS_ANY, HEADER_LEN, ParsedMessage, Reader, Section, TYPE_TSIG, push_name, rcode_name,
+};
+use super::{DnsError, DnsResult};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// Constants
+const ALGORITHM_HMAC_SHA256: &str = "hmac-sha256.";
+const FUDGE_SECS: u16 = 300;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// TSIG (RFC 8945) key using HMAC-SHA256
+pub struct TsigKey {
+    name: String,
+    secret: Vec<u8>,
+}
+
+impl TsigKey {
+    /// Create a key from its name and raw secret
+    pub fn new(name: &str, secret: Vec<u8>) -> Self {
+        Self {
+            name: format!("{}.", name.trim_end_matches('.').to_ascii_lowercase()),
+            secret,
+        }
+    }
+
+    /// Create a key from its name and base64 secret, as found in BIND/Knot key files
+    pub fn from_base64(name: &str, secret: &str) -> DnsResult<Self> {
+        let secret = STANDARD
+            .decode(secret.trim())
+            .map_err(|e| DnsError::InvalidInput(format!("invalid TSIG secret: {}", e)))?;
+        Ok(Self::new(name, secret))
+    }
+
+    /// Sign a message by appending a TSIG record, returning the MAC
+    ///
+    /// `request_mac` is the MAC of the request when signing a response.
+    pub fn sign(
+        &self,
+        message: &mut Vec<u8>,
+        request_mac: Option<&[u8]>,
+        time_signed: u64,
+    ) -> DnsResult<Vec<u8>> {
+        let original_id = u16::from_be_bytes([message[0], message[1]]);
+        let mac = self.compute_mac(message, request_mac, time_signed, FUDGE_SECS, 0)?;
+
+        let mut rdata = Vec::new();
+        push_name(&mut rdata, ALGORITHM_HMAC_SHA256)?;
+        rdata.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+        rdata.extend_from_slice(&FUDGE_SECS.to_be_bytes());
+        rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+        rdata.extend_from_slice(&mac);
+        rdata.extend_from_slice(&original_id.to_be_bytes());
+        rdata.extend_from_slice(&0u16.to_be_bytes());
+        rdata.extend_from_slice(&0u16.to_be_bytes());
+
+        push_name(message, &self.name)?;
+        message.extend_from_slice(&TYPE_TSIG.to_be_bytes());
+        message.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        message.extend_from_slice(&0u32.to_be_bytes());
+        message.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        message.extend_from_slice(&rdata);
+
+        let arcount = u16::from_be_bytes([message[10], message[11]]) + 1;
+        message[10..HEADER_LEN].copy_from_slice(&arcount.to_be_bytes());
+        Ok(mac)
+    }
+
+    /// Verify the TSIG record closing a message, returning its MAC
+    ///
+    /// `request_mac` is the MAC of our request when verifying a response;
+    /// `now` is the current Unix time used for the fudge window check.
+    pub fn verify(
+        &self,
+        message: &[u8],
+        request_mac: Option<&[u8]>,
+        now: u64,
+    ) -> DnsResult<Vec<u8>> {
+        let parsed = ParsedMessage::parse(message)?;
+        let tsig = parsed
+            .records
+            .last()
+            .filter(|r| r.section == Section::Additional && r.rtype == TYPE_TSIG)
+            .ok_or_else(|| DnsError::ApiError("message is not TSIG signed".to_string()))?;
+
+        if !tsig.name.eq_ignore_ascii_case(&self.name) {
+            return Err(DnsError::ApiError(format!(
+                "message signed with unexpected TSIG key {}",
+                tsig.name
+            )));
+        }
+
+        let mut reader = Reader::at(message, tsig.rdata_offset);
+        let algorithm = reader.name()?;
+        let time_signed = reader.u48()?;
+        let fudge = reader.u16()?;
+        let mac_len = reader.u16()? as usize;
+        let mac = reader.bytes(mac_len)?.to_vec();
+        let original_id = reader.u16()?;
+        let error = reader.u16()?;
+
+        if error != 0 {
+            return Err(DnsError::ApiError(format!(
+                "TSIG error {}",
+                rcode_name(error)
+            )));
+        }
+        if !algorithm.eq_ignore_ascii_case(ALGORITHM_HMAC_SHA256) {
+            return Err(DnsError::ApiError(format!(
+                "unsupported TSIG algorithm {}",
+                algorithm
+            )));
+        }
+
+        // The MAC covers the message as it was before the TSIG record was added
+        let mut unsigned = message[..tsig.offset].to_vec();
+        unsigned[..2].copy_from_slice(&original_id.to_be_bytes());
+        let arcount = u16::from_be_bytes([unsigned[10], unsigned[11]]) - 1;
+        unsigned[10..HEADER_LEN].copy_from_slice(&arcount.to_be_bytes());
+
+        let hmac = self.hmac(&unsigned, request_mac, time_signed, fudge, error)?;
+        hmac.verify_slice(&mac)
+            .map_err(|_| DnsError::ApiError("TSIG signature mismatch".to_string()))?;
+
+        if now.abs_diff(time_signed) > u64::from(fudge) {
+            return Err(DnsError::ApiError(
+                "TSIG time outside the allowed window".to_string(),
+            ));
+        }
+        Ok(mac)
+    }
+
+    fn compute_mac(
+        &self,
+        message: &[u8],
+        request_mac: Option<&[u8]>,
+        time_signed: u64,
+        fudge: u16,
+        error: u16,
+    ) -> DnsResult<Vec<u8>> {
+        let hmac = self.hmac(message, request_mac, time_signed, fudge, error)?;
+        Ok(hmac.finalize().into_bytes().to_vec())
+    }
+
+    /// HMAC state fed with the request MAC, the message and the TSIG variables
+    fn hmac(
+        &self,
+        message: &[u8],
+        request_mac: Option<&[u8]>,
+        time_signed: u64,
+        fudge: u16,
+        error: u16,
+    ) -> DnsResult<HmacSha256> {
+        let mut hmac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+
+        if let Some(request_mac) = request_mac {
+            hmac.update(&(request_mac.len() as u16).to_be_bytes());
+            hmac.update(request_mac);
+        }
+        hmac.update(message);
+
+        let mut variables = Vec::new();
+        push_name(&mut variables, &self.name)?;
+        variables.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        variables.extend_from_slice(&0u32.to_be_bytes());
+        push_name(&mut variables, ALGORITHM_HMAC_SHA256)?;
+        variables.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+        variables.extend_from_slice(&fudge.to_be_bytes());
+        variables.extend_from_slice(&error.to_be_bytes());
+        variables.extend_from_slice(&0u16.to_be_bytes());
+        hmac.update(&variables);
+
+        Ok(hmac)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::wire::{CLASS_IN, MessageBuilder, OPCODE_QUERY, RCODE_NOERROR, TYPE_A};
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn key() -> TsigKey {
+        TsigKey::from_base64("ddns-key.", "c2VjcmV0LWtleS1mb3ItdGVzdHM=").unwrap()
+    }
+
+    /// UPDATE of example.com deleting and re-adding `home A 192.0.2.1`, signed at
+    /// `NOW` by dnspython 1.16 (`dns.update.Update` with `HMAC_SHA256`)
+    const DNSPYTHON_UPDATE: &str = concat!(
+        "002a28000001000000020001076578616d706c6503636f6d000006000104686f",
+        "6d65c00c000100ff000000000000c01d000100010000003c0004c00002010864",
+        "646e732d6b65790000fa00ff00000000003d0b686d61632d7368613235360000",
+        "006553f100012c0020392c2590467dc1ad62779242fd4b7a44764b0431ba5dab",
+        "aff7d06bb05cafa172002a00000000",
+    );
+    const DNSPYTHON_MAC: &str = "392c2590467dc1ad62779242fd4b7a44764b0431ba5dabaff7d06bb05cafa172";
+
+    fn query() -> Vec<u8> {
+        MessageBuilder::new(42, OPCODE_QUERY)
+            .question("home.example.com", TYPE_A, CLASS_IN)
+            .unwrap()
+            .finish()
+    }
+
+    #[test]
+    fn signed_request_verifies() {
+        let mut message = query();
+        let unsigned_len = message.len();
+        let mac = key().sign(&mut message, None, NOW).unwrap();
+
+        assert_eq!(mac.len(), 32);
+        assert_eq!(&message[10..12], &[0, 1]);
+        let parsed = ParsedMessage::parse(&message).unwrap();
+        let tsig = parsed.records.last().unwrap();
+        assert_eq!(tsig.name, "ddns-key.");
+        assert_eq!(tsig.offset, unsigned_len);
+
+        assert_eq!(key().verify(&message, None, NOW + 10).unwrap(), mac);
+    }
+
+    #[test]
+    fn matches_dnspython_signature() {
+        let signed = hex::decode(DNSPYTHON_UPDATE).unwrap();
+        let mac = hex::decode(DNSPYTHON_MAC).unwrap();
+        assert_eq!(key().verify(&signed, None, NOW).unwrap(), mac);
+
+        // Signing the unsigned message reproduces dnspython's output byte for byte
+        let parsed = ParsedMessage::parse(&signed).unwrap();
+        let mut message = signed[..parsed.records.last().unwrap().offset].to_vec();
+        message[10..HEADER_LEN].copy_from_slice(&0u16.to_be_bytes());
+        assert_eq!(key().sign(&mut message, None, NOW).unwrap(), mac);
+        assert_eq!(message, signed);
+    }
+
+    #[test]
+    fn response_mac_chains_request_mac() {
+        let mut request = query();
+        let request_mac = key().sign(&mut request, None, NOW).unwrap();
+
+        let mut response = MessageBuilder::response(42, OPCODE_QUERY, RCODE_NOERROR)
+            .question("home.example.com", TYPE_A, CLASS_IN)
+            .unwrap()
+            .finish();
+        key().sign(&mut response, Some(&request_mac), NOW).unwrap();
+
+        assert!(key().verify(&response, Some(&request_mac), NOW).is_ok());
+        assert!(key().verify(&response, None, NOW).is_err());
+        assert!(key().verify(&response, Some(&[0; 32]), NOW).is_err());
+    }
+
+    #[test]
+    fn tampering_and_wrong_keys_are_rejected() {
+        let mut message = query();
+        key().sign(&mut message, None, NOW).unwrap();
+
+        let mut tampered = message.clone();
+        tampered[HEADER_LEN + 1] = b'x';
+        assert!(key().verify(&tampered, None, NOW).is_err());
+
+        let other_secret = TsigKey::new("ddns-key", b"another secret".to_vec());
+        assert!(other_secret.verify(&message, None, NOW).is_err());
+
+        let other_name = TsigKey::from_base64("other-key", "c2VjcmV0LWtleS1mb3ItdGVzdHM=").unwrap();
+        assert!(other_name.verify(&message, None, NOW).is_err());
+
+        assert!(key().verify(&query(), None, NOW).is_err());
+        assert!(key().verify(&message, None, NOW + 301).is_err());
+        assert!(TsigKey::from_base64("ddns-key", "not base64!").is_err());
+    }
+}
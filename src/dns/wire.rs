@@ -0,0 +1,515 @@
+use super::{DnsError, DnsResult, RecordType};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// Header layout
+pub const HEADER_LEN: usize = 12;
+#[cfg(test)]
+const FLAG_QR: u16 = 0x8000;
+const FLAG_RD: u16 = 0x0100;
+const OPCODE_SHIFT: u16 = 11;
+const RCODE_MASK: u16 = 0x000f;
+const POINTER_MASK: u8 = 0xc0;
+
+// Opcodes, types and classes (RFC 1035, RFC 2136, RFC 8945)
+pub const OPCODE_QUERY: u16 = 0;
+pub const OPCODE_UPDATE: u16 = 5;
+pub const TYPE_A: u16 = 1;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_TSIG: u16 = 250;
+pub const CLASS_IN: u16 = 1;
+#[cfg(test)]
+pub const CLASS_NONE: u16 = 254;
+pub const CLASS_ANY: u16 = 255;
+
+// Response codes
+pub const RCODE_NOERROR: u16 = 0;
+pub const RCODE_NXDOMAIN: u16 = 3;
+
+/// Message sections in wire order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    /// Question section, the zone section in an UPDATE
+    Question,
+    /// Answer section, the prerequisite section in an UPDATE
+    Answer,
+    /// Authority section, the update section in an UPDATE
+    Authority,
+    /// Additional data section, where TSIG lives
+    Additional,
+}
+
+/// Builder for DNS messages
+///
+/// Sections must be filled in wire order: questions, then answers, authority
+/// and additional records.
+pub struct MessageBuilder {
+    buf: Vec<u8>,
+    counts: [u16; 4],
+}
+
+impl MessageBuilder {
+    /// Start a request with the given id and opcode
+    pub fn new(id: u16, opcode: u16) -> Self {
+        let mut flags = opcode << OPCODE_SHIFT;
+        if opcode == OPCODE_QUERY {
+            flags |= FLAG_RD;
+        }
+        Self::with_flags(id, flags)
+    }
+
+    /// Start a response to a request with the given id, opcode and response code
+    #[cfg(test)]
+    pub fn response(id: u16, opcode: u16, rcode: u16) -> Self {
+        Self::with_flags(
+            id,
+            FLAG_QR | (opcode << OPCODE_SHIFT) | (rcode & RCODE_MASK),
+        )
+    }
+
+    fn with_flags(id: u16, flags: u16) -> Self {
+        let mut buf = Vec::with_capacity(512);
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(&flags.to_be_bytes());
+        buf.extend_from_slice(&[0; 8]);
+        Self {
+            buf,
+            counts: [0; 4],
+        }
+    }
+
+    /// Append a question (or UPDATE zone) entry
+    pub fn question(mut self, name: &str, rtype: u16, class: u16) -> DnsResult<Self> {
+        push_name(&mut self.buf, name)?;
+        self.buf.extend_from_slice(&rtype.to_be_bytes());
+        self.buf.extend_from_slice(&class.to_be_bytes());
+        self.counts[Section::Question as usize] += 1;
+        Ok(self)
+    }
+
+    /// Append a resource record to one of the record sections
+    pub fn record(
+        mut self,
+        section: Section,
+        name: &str,
+        rtype: u16,
+        class: u16,
+        ttl: u32,
+        rdata: &[u8],
+    ) -> DnsResult<Self> {
+        let rdlen = u16::try_from(rdata.len())
+            .map_err(|_| DnsError::InvalidInput("record data too long".to_string()))?;
+        push_name(&mut self.buf, name)?;
+        self.buf.extend_from_slice(&rtype.to_be_bytes());
+        self.buf.extend_from_slice(&class.to_be_bytes());
+        self.buf.extend_from_slice(&ttl.to_be_bytes());
+        self.buf.extend_from_slice(&rdlen.to_be_bytes());
+        self.buf.extend_from_slice(rdata);
+        self.counts[section as usize] += 1;
+        Ok(self)
+    }
+
+    /// Finish the message, filling in the section counts
+    pub fn finish(mut self) -> Vec<u8> {
+        for (i, count) in self.counts.iter().enumerate() {
+            self.buf[4 + i * 2..6 + i * 2].copy_from_slice(&count.to_be_bytes());
+        }
+        self.buf
+    }
+}
+
+/// Resource record read from a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedRecord {
+    pub section: Section,
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub rdata: Vec<u8>,
+    /// Offset of the record's first byte within the message
+    pub offset: usize,
+    /// Offset of the record data within the message
+    pub rdata_offset: usize,
+}
+
+/// DNS message read from the wire
+#[derive(Debug)]
+pub struct ParsedMessage {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<(String, u16, u16)>,
+    pub records: Vec<ParsedRecord>,
+}
+
+impl ParsedMessage {
+    /// Parse a complete message
+    pub fn parse(message: &[u8]) -> DnsResult<Self> {
+        let mut reader = Reader::new(message);
+        let id = reader.u16()?;
+        let flags = reader.u16()?;
+        let counts = [reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?];
+
+        let mut questions = Vec::with_capacity(counts[0] as usize);
+        for _ in 0..counts[0] {
+            let name = reader.name()?;
+            questions.push((name, reader.u16()?, reader.u16()?));
+        }
+
+        let mut records = Vec::new();
+        for (section, count) in [Section::Answer, Section::Authority, Section::Additional]
+            .into_iter()
+            .zip(&counts[1..])
+        {
+            for _ in 0..*count {
+                let offset = reader.pos;
+                let name = reader.name()?;
+                let rtype = reader.u16()?;
+                let class = reader.u16()?;
+                let ttl = reader.u32()?;
+                let rdlen = reader.u16()? as usize;
+                let rdata_offset = reader.pos;
+                let rdata = reader.bytes(rdlen)?.to_vec();
+                records.push(ParsedRecord {
+                    section,
+                    name,
+                    rtype,
+                    class,
+                    ttl,
+                    rdata,
+                    offset,
+                    rdata_offset,
+                });
+            }
+        }
+
+        Ok(Self {
+            id,
+            flags,
+            questions,
+            records,
+        })
+    }
+
+    /// Opcode of the message
+    pub fn opcode(&self) -> u16 {
+        (self.flags >> OPCODE_SHIFT) & 0x0f
+    }
+
+    /// Response code of the message
+    pub fn rcode(&self) -> u16 {
+        self.flags & RCODE_MASK
+    }
+
+    /// Records in the given section
+    pub fn section(&self, section: Section) -> impl Iterator<Item = &ParsedRecord> {
+        self.records.iter().filter(move |r| r.section == section)
+    }
+}
+
+/// Cursor over a message, resolving compressed names against the whole message
+pub struct Reader<'a> {
+    message: &'a [u8],
+    pub pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Start reading at the beginning of a message
+    pub fn new(message: &'a [u8]) -> Self {
+        Self { message, pos: 0 }
+    }
+
+    /// Start reading at an offset within a message
+    pub fn at(message: &'a [u8], pos: usize) -> Self {
+        Self { message, pos }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> DnsResult<&'a [u8]> {
+        let bytes = self
+            .message
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| DnsError::SerializationError("truncated DNS message".to_string()))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u16(&mut self) -> DnsResult<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> DnsResult<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// 48-bit unsigned integer, as used for TSIG timestamps
+    pub fn u48(&mut self) -> DnsResult<u64> {
+        let bytes = self.bytes(6)?;
+        Ok(bytes.iter().fold(0, |acc, b| (acc << 8) | u64::from(*b)))
+    }
+
+    /// Read a possibly compressed domain name, returned with a trailing dot
+    pub fn name(&mut self) -> DnsResult<String> {
+        let mut labels: Vec<String> = Vec::new();
+        let mut pos = self.pos;
+        let mut resume = None;
+        // Every pointer must go backwards, which bounds the number of jumps
+        let mut limit = pos;
+
+        loop {
+            let len = *self.message.get(pos).ok_or_else(Self::truncated)?;
+            if len & POINTER_MASK == POINTER_MASK {
+                let low = *self.message.get(pos + 1).ok_or_else(Self::truncated)?;
+                let target = (usize::from(len & !POINTER_MASK) << 8) | usize::from(low);
+                if target >= limit {
+                    return Err(DnsError::SerializationError(
+                        "invalid name compression pointer".to_string(),
+                    ));
+                }
+                resume.get_or_insert(pos + 2);
+                limit = target;
+                pos = target;
+            } else if len == 0 {
+                pos += 1;
+                break;
+            } else {
+                let label = self
+                    .message
+                    .get(pos + 1..pos + 1 + usize::from(len))
+                    .ok_or_else(Self::truncated)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + usize::from(len);
+            }
+        }
+
+        self.pos = resume.unwrap_or(pos);
+        Ok(format!("{}.", labels.join(".")))
+    }
+
+    fn truncated() -> DnsError {
+        DnsError::SerializationError("truncated DNS name".to_string())
+    }
+}
+
+/// Append a domain name in uncompressed wire format
+pub fn push_name(buf: &mut Vec<u8>, name: &str) -> DnsResult<()> {
+    let name = name.trim_end_matches('.');
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(DnsError::InvalidInput(format!(
+                    "invalid DNS name: {}",
+                    name
+                )));
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+    Ok(())
+}
+
+/// Wire type code for a record type
+pub fn type_code(record_type: RecordType) -> u16 {
+    match record_type {
+        RecordType::A => TYPE_A,
+        RecordType::AAAA => TYPE_AAAA,
+        RecordType::TXT => TYPE_TXT,
+    }
+}
+
+/// Encode record content as RDATA
+pub fn encode_rdata(record_type: RecordType, content: &str) -> DnsResult<Vec<u8>> {
+    let invalid = || {
+        DnsError::InvalidInput(format!(
+            "invalid {} content: {}",
+            record_type.as_str(),
+            content
+        ))
+    };
+    match record_type {
+        RecordType::A => Ok(content
+            .parse::<Ipv4Addr>()
+            .map_err(|_| invalid())?
+            .octets()
+            .to_vec()),
+        RecordType::AAAA => Ok(content
+            .parse::<Ipv6Addr>()
+            .map_err(|_| invalid())?
+            .octets()
+            .to_vec()),
+        RecordType::TXT => {
+            // TXT data is a sequence of character-strings of at most 255 bytes
+            let mut rdata = Vec::with_capacity(content.len() + 1);
+            let chunks: Vec<&[u8]> = match content.is_empty() {
+                true => vec![&[]],
+                false => content.as_bytes().chunks(255).collect(),
+            };
+            for chunk in chunks {
+                rdata.push(chunk.len() as u8);
+                rdata.extend_from_slice(chunk);
+            }
+            Ok(rdata)
+        }
+    }
+}
+
+/// Decode RDATA back into record content
+pub fn decode_rdata(record_type: RecordType, rdata: &[u8]) -> DnsResult<String> {
+    let invalid =
+        || DnsError::SerializationError(format!("invalid {} record data", record_type.as_str()));
+    match record_type {
+        RecordType::A => {
+            let octets: [u8; 4] = rdata.try_into().map_err(|_| invalid())?;
+            Ok(Ipv4Addr::from(octets).to_string())
+        }
+        RecordType::AAAA => {
+            let octets: [u8; 16] = rdata.try_into().map_err(|_| invalid())?;
+            Ok(Ipv6Addr::from(octets).to_string())
+        }
+        RecordType::TXT => {
+            let mut content = Vec::with_capacity(rdata.len());
+            let mut rest = rdata;
+            while let Some((&len, tail)) = rest.split_first() {
+                let chunk = tail.get(..usize::from(len)).ok_or_else(invalid)?;
+                content.extend_from_slice(chunk);
+                rest = &tail[usize::from(len)..];
+            }
+            String::from_utf8(content).map_err(|_| invalid())
+        }
+    }
+}
+
+/// Mnemonic for a response code, including the TSIG error codes
+pub fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        6 => "YXDOMAIN".to_string(),
+        7 => "YXRRSET".to_string(),
+        8 => "NXRRSET".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        16 => "BADSIG".to_string(),
+        17 => "BADKEY".to_string(),
+        18 => "BADTIME".to_string(),
+        other => format!("RCODE{}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_round_trip() {
+        let message = MessageBuilder::new(0x1234, OPCODE_QUERY)
+            .question("home.example.com", TYPE_A, CLASS_IN)
+            .unwrap()
+            .finish();
+
+        assert_eq!(&message[..4], &[0x12, 0x34, 0x01, 0x00]);
+        let parsed = ParsedMessage::parse(&message).unwrap();
+        assert_eq!(parsed.id, 0x1234);
+        assert_eq!(parsed.opcode(), OPCODE_QUERY);
+        assert_eq!(
+            parsed.questions,
+            vec![("home.example.com.".to_string(), TYPE_A, CLASS_IN)]
+        );
+        assert!(parsed.records.is_empty());
+    }
+
+    #[test]
+    fn update_sections_are_counted() {
+        let message = MessageBuilder::new(7, OPCODE_UPDATE)
+            .question("example.com.", TYPE_SOA, CLASS_IN)
+            .unwrap()
+            .record(
+                Section::Authority,
+                "home.example.com",
+                TYPE_A,
+                CLASS_ANY,
+                0,
+                &[],
+            )
+            .unwrap()
+            .record(
+                Section::Authority,
+                "home.example.com",
+                TYPE_A,
+                CLASS_IN,
+                60,
+                &[1, 2, 3, 4],
+            )
+            .unwrap()
+            .finish();
+
+        assert_eq!(&message[4..HEADER_LEN], &[0, 1, 0, 0, 0, 2, 0, 0]);
+        let parsed = ParsedMessage::parse(&message).unwrap();
+        assert_eq!(parsed.opcode(), OPCODE_UPDATE);
+        let updates: Vec<_> = parsed.section(Section::Authority).collect();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].class, CLASS_ANY);
+        assert_eq!(updates[1].ttl, 60);
+        assert_eq!(updates[1].rdata, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn compressed_names_are_followed() {
+        let mut message = MessageBuilder::response(1, OPCODE_QUERY, RCODE_NOERROR)
+            .question("home.example.com", TYPE_A, CLASS_IN)
+            .unwrap()
+            .finish();
+        // Answer owner is a pointer to the question name at offset 12
+        message[7] = 1;
+        message.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 9, 8, 7, 6]);
+
+        let parsed = ParsedMessage::parse(&message).unwrap();
+        let answer = parsed.section(Section::Answer).next().unwrap();
+        assert_eq!(answer.name, "home.example.com.");
+        assert_eq!(answer.rdata, vec![9, 8, 7, 6]);
+
+        // A pointer that does not go backwards is rejected rather than looped on
+        let mut looping = message.clone();
+        looping[HEADER_LEN + 23] = 0x22;
+        assert!(ParsedMessage::parse(&looping).is_err());
+    }
+
+    #[test]
+    fn rdata_encoding() {
+        let test_cases = vec![
+            (RecordType::A, "1.2.3.4"),
+            (RecordType::AAAA, "2001:db8::1"),
+            (RecordType::TXT, "hello world"),
+            (RecordType::TXT, ""),
+        ];
+
+        for (record_type, content) in test_cases {
+            let rdata = encode_rdata(record_type, content).unwrap();
+            assert_eq!(decode_rdata(record_type, &rdata).unwrap(), content);
+        }
+
+        let long = "x".repeat(300);
+        let rdata = encode_rdata(RecordType::TXT, &long).unwrap();
+        assert_eq!(rdata[0], 255);
+        assert_eq!(rdata[256], 45);
+        assert_eq!(decode_rdata(RecordType::TXT, &rdata).unwrap(), long);
+
+        assert!(encode_rdata(RecordType::A, "2001:db8::1").is_err());
+        assert!(decode_rdata(RecordType::AAAA, &[1, 2, 3, 4]).is_err());
+    }
+
+    #[test]
+    fn invalid_names_rejected() {
+        let mut buf = Vec::new();
+        assert!(push_name(&mut buf, "a..b").is_err());
+        assert!(push_name(&mut buf, &"x".repeat(64)).is_err());
+    }
+}
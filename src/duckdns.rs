@@ -0,0 +1,304 @@
+use crate::dyndns::DynDnsUtils;
+use crate::ip::IpUtils;
+use crate::request::RequestContext;
+use std::net::IpAddr;
+use worker::*;
+
+// Constants
+pub const DUCKDNS_UPDATE_PATH: &str = "/update";
+const PARAM_DOMAINS: &str = "domains";
+const PARAM_TOKEN: &str = "token";
+const PARAM_IP: &str = "ip";
+const PARAM_IPV6: &str = "ipv6";
+const PARAM_CLEAR: &str = "clear";
+const PARAM_TXT: &str = "txt";
+const PARAM_VERBOSE: &str = "verbose";
+const REPLY_OK: &str = "OK";
+const REPLY_KO: &str = "KO";
+const STATUS_UPDATED: &str = "UPDATED";
+const STATUS_NOCHANGE: &str = "NOCHANGE";
+
+/// Operation requested by a DuckDNS client
+#[derive(Debug, PartialEq)]
+pub enum DuckDnsAction {
+    /// Publish A/AAAA records
+    Update { ipv4: String, ipv6: String },
+    /// Delete A/AAAA records
+    Clear,
+    /// Publish a TXT record
+    SetTxt(String),
+    /// Delete the TXT record
+    ClearTxt,
+}
+
+/// Parsed DuckDNS update request
+#[derive(Debug)]
+pub struct DuckDnsRequest {
+    /// Raw domain entries as sent by the client
+    pub domains: Vec<String>,
+    /// Token passed in the query string
+    pub token: Option<String>,
+    /// Operation to perform on every domain
+    pub action: DuckDnsAction,
+    /// Whether the client asked for the verbose reply
+    pub verbose: bool,
+}
+
+impl DuckDnsRequest {
+    /// Parse a DuckDNS update request from the incoming request
+    pub fn from_request(req: &Request) -> Result<Self> {
+        let client_ip = RequestContext::extract_client_ip(req)?;
+        Self::from_url(&req.url()?, &client_ip)
+    }
+
+    /// Parse a DuckDNS update request from the URL, using `client_ip` when no IP is given
+    pub fn from_url(url: &Url, client_ip: &str) -> Result<Self> {
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_string())
+        };
+        let flag = |name: &str| param(name).is_some_and(|v| v.eq_ignore_ascii_case("true"));
+
+        let domains = param(PARAM_DOMAINS)
+            .map(|d| DynDnsUtils::split_list(&d))
+            .unwrap_or_default();
+        let clear = flag(PARAM_CLEAR);
+
+        let action = match param(PARAM_TXT) {
+            Some(_) if clear => DuckDnsAction::ClearTxt,
+            Some(txt) => DuckDnsAction::SetTxt(txt),
+            None if clear => DuckDnsAction::Clear,
+            None => {
+                let (ipv4, ipv6) = DuckDnsUtils::parse_ips(
+                    param(PARAM_IP).as_deref(),
+                    param(PARAM_IPV6).as_deref(),
+                    client_ip,
+                )?;
+                DuckDnsAction::Update { ipv4, ipv6 }
+            }
+        };
+
+        Ok(Self {
+            domains,
+            token: param(PARAM_TOKEN),
+            action,
+            verbose: flag(PARAM_VERBOSE),
+        })
+    }
+
+    /// Renders the success reply for this request
+    pub fn reply(&self, changed: bool) -> String {
+        if !self.verbose {
+            return REPLY_OK.to_string();
+        }
+
+        let status = if changed {
+            STATUS_UPDATED
+        } else {
+            STATUS_NOCHANGE
+        };
+        match &self.action {
+            DuckDnsAction::Update { ipv4, ipv6 } => {
+                format!("{}\n{}\n{}\n{}", REPLY_OK, ipv4, ipv6, status)
+            }
+            DuckDnsAction::Clear => format!("{}\n\n\n{}", REPLY_OK, status),
+            DuckDnsAction::SetTxt(txt) => format!("{}\n{}\n{}", REPLY_OK, txt, status),
+            DuckDnsAction::ClearTxt => format!("{}\n\n{}", REPLY_OK, status),
+        }
+    }
+
+    /// Renders the failure reply
+    pub fn failure() -> String {
+        REPLY_KO.to_string()
+    }
+}
+
+/// DuckDNS protocol helpers
+pub struct DuckDnsUtils;
+
+impl DuckDnsUtils {
+    /// Resolves the addresses to publish from `ip`/`ipv6`, falling back to the client IP
+    ///
+    /// `ip` may carry either family; when it is absent the client's IPv4 is detected.
+    /// Unlike DynDNS2, invalid addresses are rejected.
+    pub fn parse_ips(
+        ip: Option<&str>,
+        ipv6: Option<&str>,
+        client_ip: &str,
+    ) -> Result<(String, String)> {
+        let ip = ip.map(str::trim).filter(|v| !v.is_empty());
+        let ipv6 = ipv6.map(str::trim).filter(|v| !v.is_empty());
+        let invalid = |value: &str| Error::RustError(format!("invalid ip: {}", value));
+
+        let (v4, mut v6) = match ip {
+            Some(value) => match value.parse::<IpAddr>().map_err(|_| invalid(value))? {
+                IpAddr::V4(addr) => (addr.to_string(), String::new()),
+                IpAddr::V6(addr) => (String::new(), addr.to_string()),
+            },
+            None if ipv6.is_none() => return Ok(IpUtils::split_ip(client_ip)),
+            None => (IpUtils::split_ip(client_ip).0, String::new()),
+        };
+
+        if let Some(value) = ipv6 {
+            match value.parse::<IpAddr>() {
+                Ok(IpAddr::V6(addr)) => v6 = addr.to_string(),
+                _ => return Err(invalid(value)),
+            }
+        }
+
+        Ok((v4, v6))
+    }
+
+    /// Maps a DuckDNS domain entry (bare name or FQDN under a domain) onto a homename
+    pub fn homename_from_domain(entry: &str, domains: &[&str]) -> Option<String> {
+        if let Ok(homename) = DynDnsUtils::homename_from_hostname_in(entry, domains) {
+            return Some(homename);
+        }
+        let entry = entry.to_ascii_lowercase();
+        (!entry.contains('.') && RequestContext::is_valid_homename(&entry)).then_some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Result<DuckDnsRequest> {
+        let url = Url::parse(&format!("https://worker.example/update?{}", query)).unwrap();
+        DuckDnsRequest::from_url(&url, "203.0.113.1")
+    }
+
+    #[test]
+    fn action_parsing() {
+        let update = |ipv4: &str, ipv6: &str| DuckDnsAction::Update {
+            ipv4: ipv4.to_string(),
+            ipv6: ipv6.to_string(),
+        };
+        let test_cases = vec![
+            (
+                "domains=a",
+                update("203.0.113.1", ""),
+                "defaults to client IP",
+            ),
+            (
+                "domains=a&ip=1.2.3.4",
+                update("1.2.3.4", ""),
+                "explicit IPv4",
+            ),
+            (
+                "domains=a&ip=&ipv6=",
+                update("203.0.113.1", ""),
+                "empty params",
+            ),
+            (
+                "domains=a&ip=2001:db8::1",
+                update("", "2001:db8::1"),
+                "IPv6 in ip",
+            ),
+            (
+                "domains=a&ipv6=2001:db8::1",
+                update("203.0.113.1", "2001:db8::1"),
+                "IPv6 only still detects IPv4",
+            ),
+            (
+                "domains=a&ip=1.2.3.4&ipv6=2001:db8::1",
+                update("1.2.3.4", "2001:db8::1"),
+                "both families",
+            ),
+            (
+                "domains=a&clear=true",
+                DuckDnsAction::Clear,
+                "clear addresses",
+            ),
+            (
+                "domains=a&clear=false",
+                update("203.0.113.1", ""),
+                "clear=false",
+            ),
+            (
+                "domains=a&txt=hello",
+                DuckDnsAction::SetTxt("hello".to_string()),
+                "set TXT",
+            ),
+            (
+                "domains=a&txt=x&clear=true",
+                DuckDnsAction::ClearTxt,
+                "clear TXT",
+            ),
+        ];
+
+        for (query, expected, description) in test_cases {
+            let request = parse(query).unwrap();
+            assert_eq!(request.action, expected, "Failed: {}", description);
+        }
+    }
+
+    #[test]
+    fn invalid_ips_rejected() {
+        for query in ["ip=nope", "ipv6=1.2.3.4", "ip=1.2.3.4&ipv6=bad"] {
+            assert!(parse(query).is_err(), "Failed: {}", query);
+        }
+    }
+
+    #[test]
+    fn domains_and_token() {
+        let request = parse("domains=a,b.example.com&token=t&verbose=true").unwrap();
+        assert_eq!(request.domains, vec!["a", "b.example.com"]);
+        assert_eq!(request.token.as_deref(), Some("t"));
+        assert!(request.verbose);
+    }
+
+    #[test]
+    fn replies() {
+        let test_cases = vec![
+            ("domains=a&ip=1.2.3.4", true, "OK", "terse reply"),
+            (
+                "domains=a&ip=1.2.3.4&ipv6=2001:db8::1&verbose=true",
+                true,
+                "OK\n1.2.3.4\n2001:db8::1\nUPDATED",
+                "verbose update",
+            ),
+            (
+                "domains=a&ip=1.2.3.4&verbose=true",
+                false,
+                "OK\n1.2.3.4\n\nNOCHANGE",
+                "verbose no change",
+            ),
+            (
+                "domains=a&clear=true&verbose=true",
+                true,
+                "OK\n\n\nUPDATED",
+                "verbose clear",
+            ),
+            (
+                "domains=a&txt=hi&verbose=true",
+                true,
+                "OK\nhi\nUPDATED",
+                "verbose TXT",
+            ),
+        ];
+
+        for (query, changed, expected, description) in test_cases {
+            let request = parse(query).unwrap();
+            assert_eq!(request.reply(changed), expected, "Failed: {}", description);
+        }
+        assert_eq!(DuckDnsRequest::failure(), "KO");
+    }
+
+    #[test]
+    fn domain_mapping() {
+        let test_cases = vec![
+            ("home", Some("home"), "bare name"),
+            ("Home", Some("home"), "bare name lowercased"),
+            ("home.example.com", Some("home"), "FQDN under domain"),
+            ("home.other.com", None, "foreign domain"),
+            ("bad@name", None, "invalid characters"),
+        ];
+
+        for (entry, expected, description) in test_cases {
+            let result = DuckDnsUtils::homename_from_domain(entry, &["example.com"]);
+            assert_eq!(result.as_deref(), expected, "Failed: {}", description);
+        }
+    }
+}
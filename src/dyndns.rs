@@ -0,0 +1,339 @@
+use crate::ip::IpUtils;
+use crate::request::RequestContext;
+use std::net::IpAddr;
+use worker::*;
+
+// Constants
+pub const DYNDNS_UPDATE_PATH: &str = "/nic/update";
+const PARAM_HOSTNAME: &str = "hostname";
+const PARAM_MYIP: &str = "myip";
+
+/// DynDNS2 protocol return codes
+#[derive(Debug, PartialEq)]
+pub enum DynDnsReply {
+    /// Update succeeded, carries the IP(s) now published
+    Good(String),
+    /// IP unchanged, carries the IP(s) already published
+    NoChg(String),
+    /// Authentication failed
+    BadAuth,
+    /// Hostname is not a fully-qualified domain name
+    NotFqdn,
+    /// Hostname is not managed by this worker
+    NoHost,
+    /// Caller is blocked from updating the hostname
+    Abuse,
+    /// Server-side failure, clients should back off
+    ServerError,
+}
+
+impl DynDnsReply {
+    /// Renders the reply as a single protocol line
+    pub fn as_line(&self) -> String {
+        match self {
+            DynDnsReply::Good(ip) => format!("good {}", ip),
+            DynDnsReply::NoChg(ip) => format!("nochg {}", ip),
+            DynDnsReply::BadAuth => "badauth".to_string(),
+            DynDnsReply::NotFqdn => "notfqdn".to_string(),
+            DynDnsReply::NoHost => "nohost".to_string(),
+            DynDnsReply::Abuse => "abuse".to_string(),
+            DynDnsReply::ServerError => "911".to_string(),
+        }
+    }
+}
+
+/// Parsed DynDNS2 update request
+pub struct DynDnsRequest {
+    /// Raw hostnames as sent by the client
+    pub hostnames: Vec<String>,
+    /// IPv4 address to publish, empty if none
+    pub ipv4: String,
+    /// IPv6 address to publish, empty if none
+    pub ipv6: String,
+}
+
+impl DynDnsRequest {
+    /// Parse a DynDNS2 update request from the incoming request
+    pub fn from_request(req: &Request) -> Result<Self> {
+        let url = req.url()?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_string())
+        };
+
+        let hostnames = param(PARAM_HOSTNAME)
+            .map(|h| DynDnsUtils::split_list(&h))
+            .unwrap_or_default();
+        let client_ip = RequestContext::extract_client_ip(req)?;
+        let (ipv4, ipv6) = DynDnsUtils::parse_myip(param(PARAM_MYIP).as_deref(), &client_ip);
+
+        Ok(Self {
+            hostnames,
+            ipv4,
+            ipv6,
+        })
+    }
+
+    /// IP addresses in reply form, comma-separated
+    pub fn reply_ips(&self) -> String {
+        [self.ipv4.as_str(), self.ipv6.as_str()]
+            .into_iter()
+            .filter(|ip| !ip.is_empty())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// DynDNS2 protocol helpers
+pub struct DynDnsUtils;
+
+impl DynDnsUtils {
+    /// Splits a comma-separated parameter into trimmed, non-empty entries
+    pub fn split_list(value: &str) -> Vec<String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Extracts IPv4 and IPv6 addresses from `myip`, falling back to the client IP
+    ///
+    /// Unparseable entries are ignored; if none remain the client IP is used.
+    pub fn parse_myip(myip: Option<&str>, client_ip: &str) -> (String, String) {
+        let mut ipv4 = String::new();
+        let mut ipv6 = String::new();
+
+        for entry in myip.map(Self::split_list).unwrap_or_default() {
+            match entry.parse::<IpAddr>() {
+                Ok(IpAddr::V4(ip)) if ipv4.is_empty() => ipv4 = ip.to_string(),
+                Ok(IpAddr::V6(ip)) if ipv6.is_empty() => ipv6 = ip.to_string(),
+                _ => {}
+            }
+        }
+
+        if ipv4.is_empty() && ipv6.is_empty() {
+            return IpUtils::split_ip(client_ip);
+        }
+        (ipv4, ipv6)
+    }
+
+    /// Maps a fully-qualified hostname onto a homename under the configured domain
+    pub fn homename_from_hostname(
+        hostname: &str,
+        domain: &str,
+    ) -> std::result::Result<String, DynDnsReply> {
+        let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+
+        if !hostname.contains('.') {
+            return Err(DynDnsReply::NotFqdn);
+        }
+
+        let homename = hostname
+            .strip_suffix(&domain)
+            .and_then(|prefix| prefix.strip_suffix('.'))
+            .ok_or(DynDnsReply::NoHost)?;
+
+        if !RequestContext::is_valid_homename(homename) {
+            return Err(DynDnsReply::NoHost);
+        }
+
+        Ok(homename.to_string())
+    }
+
+    /// Maps a hostname onto a homename under any of the configured domains
+    pub fn homename_from_hostname_in(
+        hostname: &str,
+        domains: &[&str],
+    ) -> std::result::Result<String, DynDnsReply> {
+        let mut first_error = DynDnsReply::NoHost;
+        for (i, domain) in domains.iter().enumerate() {
+            match Self::homename_from_hostname(hostname, domain) {
+                Ok(homename) => return Ok(homename),
+                Err(reply) if i == 0 => first_error = reply,
+                Err(_) => {}
+            }
+        }
+        Err(first_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply_lines() {
+        let test_cases = vec![
+            (DynDnsReply::Good("1.2.3.4".to_string()), "good 1.2.3.4"),
+            (
+                DynDnsReply::NoChg("1.2.3.4,2001:db8::1".to_string()),
+                "nochg 1.2.3.4,2001:db8::1",
+            ),
+            (DynDnsReply::BadAuth, "badauth"),
+            (DynDnsReply::NotFqdn, "notfqdn"),
+            (DynDnsReply::NoHost, "nohost"),
+            (DynDnsReply::Abuse, "abuse"),
+            (DynDnsReply::ServerError, "911"),
+        ];
+
+        for (reply, expected) in test_cases {
+            assert_eq!(reply.as_line(), expected, "Failed: {:?}", reply);
+        }
+    }
+
+    #[test]
+    fn myip_parsing() {
+        let test_cases = vec![
+            (
+                None,
+                "203.0.113.1",
+                "203.0.113.1",
+                "",
+                "falls back to client IPv4",
+            ),
+            (
+                None,
+                "2001:db8::2",
+                "",
+                "2001:db8::2",
+                "falls back to client IPv6",
+            ),
+            (
+                Some("1.2.3.4"),
+                "203.0.113.1",
+                "1.2.3.4",
+                "",
+                "explicit IPv4",
+            ),
+            (
+                Some("2001:db8::1"),
+                "203.0.113.1",
+                "",
+                "2001:db8::1",
+                "explicit IPv6",
+            ),
+            (
+                Some("1.2.3.4,2001:db8::1"),
+                "203.0.113.1",
+                "1.2.3.4",
+                "2001:db8::1",
+                "comma-separated IPv4 and IPv6",
+            ),
+            (
+                Some(" 2001:db8::1 , 1.2.3.4 "),
+                "",
+                "1.2.3.4",
+                "2001:db8::1",
+                "order and whitespace are ignored",
+            ),
+            (
+                Some("1.2.3.4,5.6.7.8"),
+                "",
+                "1.2.3.4",
+                "",
+                "first address of each family wins",
+            ),
+            (
+                Some("garbage"),
+                "203.0.113.1",
+                "203.0.113.1",
+                "",
+                "invalid myip falls back",
+            ),
+            (
+                Some(""),
+                "203.0.113.1",
+                "203.0.113.1",
+                "",
+                "empty myip falls back",
+            ),
+        ];
+
+        for (myip, client_ip, expected_v4, expected_v6, description) in test_cases {
+            let (ipv4, ipv6) = DynDnsUtils::parse_myip(myip, client_ip);
+            assert_eq!(ipv4, expected_v4, "IPv4 failed for: {}", description);
+            assert_eq!(ipv6, expected_v6, "IPv6 failed for: {}", description);
+        }
+    }
+
+    #[test]
+    fn hostname_mapping() {
+        let test_cases = vec![
+            ("home.example.com", Ok("home"), "simple host under domain"),
+            (
+                "HOME.Example.COM.",
+                Ok("home"),
+                "case and trailing dot normalised",
+            ),
+            (
+                "a.b.example.com",
+                Ok("a.b"),
+                "nested label kept as homename",
+            ),
+            (
+                "home",
+                Err(DynDnsReply::NotFqdn),
+                "bare name is not an FQDN",
+            ),
+            ("home.other.com", Err(DynDnsReply::NoHost), "foreign domain"),
+            (
+                "example.com",
+                Err(DynDnsReply::NoHost),
+                "zone apex is not a host",
+            ),
+            (
+                "homeexample.com",
+                Err(DynDnsReply::NoHost),
+                "suffix without dot",
+            ),
+            (
+                "bad@x.example.com",
+                Err(DynDnsReply::NoHost),
+                "invalid characters",
+            ),
+        ];
+
+        for (hostname, expected, description) in test_cases {
+            let result = DynDnsUtils::homename_from_hostname(hostname, "example.com");
+            assert_eq!(
+                result,
+                expected.map(str::to_string),
+                "Failed: {}",
+                description
+            );
+        }
+    }
+
+    #[test]
+    fn hostname_mapping_across_domains() {
+        let domains = ["example.com", "internal.example"];
+        let test_cases = vec![
+            ("home.example.com", Ok("home"), "first domain"),
+            ("nas.internal.example", Ok("nas"), "second domain"),
+            ("nas", Err(DynDnsReply::NotFqdn), "bare name"),
+            (
+                "nas.other.org",
+                Err(DynDnsReply::NoHost),
+                "no domain matches",
+            ),
+        ];
+
+        for (hostname, expected, description) in test_cases {
+            let result = DynDnsUtils::homename_from_hostname_in(hostname, &domains);
+            assert_eq!(
+                result,
+                expected.map(str::to_string),
+                "Failed: {}",
+                description
+            );
+        }
+        assert_eq!(
+            DynDnsUtils::homename_from_hostname_in("home.example.com", &[]),
+            Err(DynDnsReply::NoHost)
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use crate::config::Config;
+use crate::store::KeyValueStore;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+// Constants
+pub const STATUS_PATH: &str = "/status";
+const SEEN_KEY_PREFIX: &str = "seen:";
+/// Check-ins from unchanged addresses refresh `last_seen` this many times per threshold
+const SEEN_REFRESHES_PER_THRESHOLD: u64 = 4;
+
+/// Last check-in of a homename, stored in KV under `seen:{homename}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeartbeatRecord {
+    /// Unix time of the last check-in in milliseconds
+    pub last_seen: u64,
+    pub ipv4: String,
+    pub ipv6: String,
+    /// Whether a down alert has been sent and not yet followed by a recovery
+    #[serde(default)]
+    pub alerted: bool,
+}
+
+/// Liveness of a homename as judged against its threshold
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostState {
+    /// Checked in within its threshold
+    Online,
+    /// Silent for longer than its threshold
+    Silent,
+    /// No threshold configured for this homename
+    Unmonitored,
+}
+
+/// Status of one homename as exposed by the status API
+#[derive(Debug, Clone, Serialize)]
+pub struct HostStatus {
+    pub homename: String,
+    pub state: HostState,
+    pub last_seen: u64,
+    pub silent_for: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<u64>,
+    pub ipv4: String,
+    pub ipv6: String,
+}
+
+/// Kind of heartbeat alert
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// Host went silent for longer than its threshold
+    HostDown,
+    /// Previously silent host checked in again
+    HostRecovered,
+}
+
+/// Alert raised by a heartbeat check
+#[derive(Debug, Clone, Serialize)]
+pub struct HeartbeatAlert {
+    pub event: AlertKind,
+    pub homename: String,
+    pub last_seen: u64,
+    pub silent_for: u64,
+    pub threshold: u64,
+}
+
+/// Tracks host check-ins in KV and detects hosts that went silent
+pub struct HeartbeatMonitor<'a> {
+    kv: &'a dyn KeyValueStore,
+}
+
+impl<'a> HeartbeatMonitor<'a> {
+    /// Create a new monitor over the given store
+    pub fn new(kv: &'a dyn KeyValueStore) -> Self {
+        Self { kv }
+    }
+
+    fn seen_key(homename: &str) -> String {
+        format!("{}{}", SEEN_KEY_PREFIX, homename)
+    }
+
+    async fn load(&self, homename: &str) -> Result<Option<HeartbeatRecord>> {
+        let value = self.kv.get(&Self::seen_key(homename)).await?;
+        Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+    }
+
+    async fn store(&self, homename: &str, record: &HeartbeatRecord) -> Result<()> {
+        let value = serde_json::to_string(record)?;
+        self.kv.put(&Self::seen_key(homename), &value).await
+    }
+
+    /// Record a check-in; `now_ms` is the current Unix time in milliseconds
+    ///
+    /// KV writes are limited, so a check-in from the same addresses is only
+    /// stored once a quarter of `threshold` (in seconds) has passed since the
+    /// last one. A host that was reported down is always stored.
+    pub async fn record_seen(
+        &self,
+        homename: &str,
+        ipv4: &str,
+        ipv6: &str,
+        threshold: u64,
+        now_ms: u64,
+    ) -> Result<()> {
+        let known = self.load(homename).await?;
+        let resolution_ms = threshold.saturating_mul(1000) / SEEN_REFRESHES_PER_THRESHOLD;
+        if known.as_ref().is_some_and(|r| {
+            !r.alerted
+                && r.ipv4 == ipv4
+                && r.ipv6 == ipv6
+                && now_ms.saturating_sub(r.last_seen) < resolution_ms
+        }) {
+            return Ok(());
+        }
+
+        // Keep the alert flag so the next check can announce the recovery
+        let alerted = known.is_some_and(|r| r.alerted);
+        let record = HeartbeatRecord {
+            last_seen: now_ms,
+            ipv4: ipv4.to_string(),
+            ipv6: ipv6.to_string(),
+            alerted,
+        };
+        self.store(homename, &record).await
+    }
+
+    /// Judge a host from its last check-in and threshold (in seconds)
+    pub fn state(last_seen: u64, threshold: Option<u64>, now_ms: u64) -> HostState {
+        match threshold {
+            None => HostState::Unmonitored,
+            Some(threshold) if Self::silent_for(last_seen, now_ms) > threshold => HostState::Silent,
+            Some(_) => HostState::Online,
+        }
+    }
+
+    fn silent_for(last_seen: u64, now_ms: u64) -> u64 {
+        now_ms.saturating_sub(last_seen) / 1000
+    }
+
+    /// Every homename that has checked in, with its stored record
+    async fn records(&self) -> Result<Vec<(String, HeartbeatRecord)>> {
+        let mut records = Vec::new();
+        for key in self.kv.list_keys(SEEN_KEY_PREFIX).await? {
+            let homename = &key[SEEN_KEY_PREFIX.len()..];
+            if let Some(record) = self.load(homename).await? {
+                records.push((homename.to_string(), record));
+            }
+        }
+        Ok(records)
+    }
+
+    /// Current status of every homename that has checked in
+    pub async fn statuses(&self, config: &Config, now_ms: u64) -> Result<Vec<HostStatus>> {
+        Ok(self
+            .records()
+            .await?
+            .into_iter()
+            .map(|(homename, record)| {
+                let threshold = config.heartbeat_threshold(&homename);
+                HostStatus {
+                    state: Self::state(record.last_seen, threshold, now_ms),
+                    last_seen: record.last_seen,
+                    silent_for: Self::silent_for(record.last_seen, now_ms),
+                    threshold,
+                    ipv4: record.ipv4,
+                    ipv6: record.ipv6,
+                    homename,
+                }
+            })
+            .collect())
+    }
+
+    /// Find hosts that went silent or came back since the last check
+    ///
+    /// Each transition is reported once: the alert flag stored with the host
+    /// suppresses repeated down alerts until it checks in again.
+    pub async fn check(&self, config: &Config, now_ms: u64) -> Result<Vec<HeartbeatAlert>> {
+        let mut alerts = Vec::new();
+
+        for (homename, mut record) in self.records().await? {
+            let Some(threshold) = config.heartbeat_threshold(&homename) else {
+                continue;
+            };
+            let silent =
+                Self::state(record.last_seen, Some(threshold), now_ms) == HostState::Silent;
+            let event = match (silent, record.alerted) {
+                (true, false) => AlertKind::HostDown,
+                (false, true) => AlertKind::HostRecovered,
+                _ => continue,
+            };
+
+            record.alerted = silent;
+            self.store(&homename, &record).await?;
+            alerts.push(HeartbeatAlert {
+                event,
+                silent_for: Self::silent_for(record.last_seen, now_ms),
+                last_seen: record.last_seen,
+                threshold,
+                homename,
+            });
+        }
+
+        Ok(alerts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    const START: u64 = 1_700_000_000_000;
+    const MINUTE: u64 = 60_000;
+
+    fn config() -> Config {
+        Config {
+            heartbeat_timeout: Some(600),
+            heartbeat_thresholds: vec![("site-*".to_string(), 3600)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn host_states() {
+        let test_cases = vec![
+            (START, None, START + 100 * MINUTE, HostState::Unmonitored),
+            (START, Some(600), START + 10 * MINUTE, HostState::Online),
+            (START, Some(600), START + 11 * MINUTE, HostState::Silent),
+            (START, Some(600), START - MINUTE, HostState::Online),
+        ];
+
+        for (last_seen, threshold, now, expected) in test_cases {
+            assert_eq!(HeartbeatMonitor::state(last_seen, threshold, now), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn statuses_use_per_host_thresholds() {
+        let kv = MemoryStore::new();
+        let monitor = HeartbeatMonitor::new(&kv);
+        monitor
+            .record_seen("home", "1.2.3.4", "", 600, START)
+            .await
+            .unwrap();
+        monitor
+            .record_seen("site-berlin", "", "2001:db8::1", 3600, START)
+            .await
+            .unwrap();
+
+        let statuses = monitor
+            .statuses(&config(), START + 20 * MINUTE)
+            .await
+            .unwrap();
+        let states: Vec<_> = statuses
+            .iter()
+            .map(|s| (s.homename.as_str(), s.state, s.threshold))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("home", HostState::Silent, Some(600)),
+                ("site-berlin", HostState::Online, Some(3600)),
+            ]
+        );
+        assert_eq!(statuses[0].silent_for, 1200);
+        assert_eq!(statuses[0].ipv4, "1.2.3.4");
+    }
+
+    #[tokio::test]
+    async fn alerts_fire_once_per_transition() {
+        let kv = MemoryStore::new();
+        let monitor = HeartbeatMonitor::new(&kv);
+        let config = config();
+        monitor
+            .record_seen("home", "1.2.3.4", "", 600, START)
+            .await
+            .unwrap();
+
+        assert!(
+            monitor
+                .check(&config, START + 5 * MINUTE)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let alerts = monitor.check(&config, START + 15 * MINUTE).await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].event, AlertKind::HostDown);
+        assert_eq!(alerts[0].silent_for, 900);
+        assert!(
+            monitor
+                .check(&config, START + 30 * MINUTE)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Checking in keeps the flag until the next check announces the recovery
+        monitor
+            .record_seen("home", "1.2.3.4", "", 600, START + 40 * MINUTE)
+            .await
+            .unwrap();
+        let alerts = monitor.check(&config, START + 41 * MINUTE).await.unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].event, AlertKind::HostRecovered);
+        assert!(
+            monitor
+                .check(&config, START + 42 * MINUTE)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn unmonitored_hosts_never_alert() {
+        let kv = MemoryStore::new();
+        let monitor = HeartbeatMonitor::new(&kv);
+        let config = Config::default();
+        // Seen while a threshold was configured, which has since been removed
+        monitor
+            .record_seen("home", "1.2.3.4", "", 600, START)
+            .await
+            .unwrap();
+
+        assert!(
+            monitor
+                .check(&config, START + 1000 * MINUTE)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let statuses = monitor.statuses(&config, START).await.unwrap();
+        assert_eq!(statuses[0].state, HostState::Unmonitored);
+    }
+
+    #[tokio::test]
+    async fn unchanged_check_ins_are_stored_sparingly() {
+        let kv = MemoryStore::new();
+        let monitor = HeartbeatMonitor::new(&kv);
+        let last_seen = || async { monitor.load("home").await.unwrap().map(|r| r.last_seen) };
+
+        let test_cases = vec![
+            (START, "1.2.3.4", START, "first check-in"),
+            (START + MINUTE, "1.2.3.4", START, "same address, recent"),
+            (
+                START + 2 * MINUTE,
+                "5.6.7.8",
+                START + 2 * MINUTE,
+                "new address",
+            ),
+            (
+                START + 6 * MINUTE,
+                "5.6.7.8",
+                START + 2 * MINUTE,
+                "under a quarter",
+            ),
+            (
+                START + 7 * MINUTE,
+                "5.6.7.8",
+                START + 7 * MINUTE,
+                "a quarter passed",
+            ),
+        ];
+
+        for (now, ipv4, expected, description) in test_cases {
+            monitor
+                .record_seen("home", ipv4, "", 1200, now)
+                .await
+                .unwrap();
+            assert_eq!(last_seen().await, Some(expected), "Failed: {}", description);
+        }
+
+        // A host reported down is stored at once so its recovery is noticed
+        monitor.check(&config(), START + 30 * MINUTE).await.unwrap();
+        monitor
+            .record_seen("home", "5.6.7.8", "", 1_000_000, START + 31 * MINUTE)
+            .await
+            .unwrap();
+        assert_eq!(last_seen().await, Some(START + 31 * MINUTE));
+    }
+}
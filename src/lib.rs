@@ -1,35 +1,116 @@
 use worker::*;
 
 // Module declarations
+mod acl;
+mod audit;
 mod auth;
+mod claims;
 mod config;
+mod devices;
 mod dns;
+mod duckdns;
+mod dyndns;
+mod heartbeat;
 mod ip;
+mod notify;
+mod quarantine;
+mod ratelimit;
 mod request;
 mod response;
 mod service;
+mod store;
+mod tokens;
 
 // Re-export public APIs
-pub use auth::AuthUtils;
-pub use config::Config;
-pub use dns::DnsManager;
+pub use acl::{Cidr, IpAccessList, IpVerdict};
+pub use audit::{AuditLog, AuthFailure, FailureReason, Lockout, LockoutPolicy};
+pub use auth::{
+    AccessClaims, AccessVerifier, AuthUtils, FetchJwksSource, Jwk, Jwks, JwksSource, Scope,
+};
+pub use claims::{Claim, ClaimInfo, ClaimStore, TransferRequest};
+pub use config::{
+    AccessConfig, Config, PowerDnsConfig, ProviderKind, Rfc2136Config, Route53Config,
+};
+pub use devices::{
+    ApproveRequest, DeviceInfo, DeviceRecord, DeviceStatus, DeviceStore, EnrollRequest,
+};
+pub use dns::{
+    CloudflareProvider, DnsError, DnsManager, DnsProvider, DnsRecord, DnsTransport,
+    DnsUpdateOutcome, MemoryProvider, MemoryRecord, PowerDnsProvider, RecordOutcome, RecordStatus,
+    RecordType, Rfc2136Provider, Route53Provider, SigV4Signer, TsigKey,
+};
+pub use duckdns::{DuckDnsAction, DuckDnsRequest, DuckDnsUtils};
+pub use dyndns::{DynDnsReply, DynDnsRequest, DynDnsUtils};
+pub use heartbeat::{
+    AlertKind, HeartbeatAlert, HeartbeatMonitor, HeartbeatRecord, HostState, HostStatus,
+};
 pub use ip::IpUtils;
+pub use notify::{ChangeEvent, NotifyUtils, WebhookPreset, WebhookRequest, WebhookTarget};
+pub use quarantine::{
+    Caller, HeldAlert, HostProfile, PendingChange, Quarantine, QuarantinePolicy, RiskRule,
+};
+pub use ratelimit::{RateKind, RateLimit, RateLimiter, RateLimits};
 pub use request::{Format, RequestContext};
-pub use response::{IpPayload, ResponseUtils};
-pub use service::DnsUpdateService;
+pub use response::{ErrorPayload, IpPayload, ResponseUtils};
+pub use service::{DnsUpdateService, ReconcileSummary};
+pub use store::{KeyValueStore, MemoryStore};
+pub use tokens::{
+    CreateTokenRequest, CreatedToken, Credential, TokenInfo, TokenRecord, TokenStore,
+};
 
 // HTTP status codes
+const HTTP_CREATED: u16 = 201;
+const HTTP_ACCEPTED: u16 = 202;
+const HTTP_NO_CONTENT: u16 = 204;
 const HTTP_UNAUTHORIZED: u16 = 401;
+const HTTP_FORBIDDEN: u16 = 403;
+const HTTP_NOT_FOUND: u16 = 404;
+const HTTP_TOO_MANY_REQUESTS: u16 = 429;
 const HTTP_BAD_REQUEST: u16 = 400;
+const HTTP_BAD_GATEWAY: u16 = 502;
+
+// DynDNS2 Basic auth challenge
+const HEADER_WWW_AUTHENTICATE: &str = "WWW-Authenticate";
+const DYNDNS_AUTH_CHALLENGE: &str = "Basic realm=\"DynDNS\"";
+
+const HEADER_RETRY_AFTER: &str = "Retry-After";
+const LOCKED_OUT_MESSAGE: &str = "Too many failed authentication attempts";
 
 /// Main request handler
-pub async fn handler(req: Request, env: Env) -> Result<Response> {
+pub async fn handler(req: Request, env: Env, worker_ctx: Context) -> Result<Response> {
     // Extract configuration
     let config = Config::from_env(&env)?;
 
+    // Throttle runaway clients before any further KV or DNS work
+    let kv = env.kv("IP_STORE")?;
+    let limiter = RateLimiter::new(&kv);
+    let now_ms = Date::now().as_millis();
+    let client_ip = RequestContext::extract_client_ip(&req)?;
+    let ip_key = [(RateKind::Ip, client_ip.as_str())];
+    if let Some(wait) = limiter.check(&config.rate_limits, &ip_key, now_ms).await? {
+        let format = RequestContext::detect_format(&req);
+        return too_many_requests(format, "Too many requests", wait);
+    }
+
     // Check authentication
-    if !AuthUtils::check_auth(&req, &config) {
-        return Response::error("Unauthorized", HTTP_UNAUTHORIZED);
+    let credential = match authenticate_request(&req, &config, &kv, true).await? {
+        Ok(credential) => credential,
+        Err(resp) => return Ok(resp),
+    };
+
+    // Read-only credentials get the IP payload without touching DNS or KV
+    if !credential.has_scope(Scope::DnsUpdate) {
+        let format = RequestContext::detect_format(&req);
+        if !credential.has_scope(Scope::IpRead) {
+            let reason = format!(
+                "token lacks {} and {}",
+                Scope::IpRead.as_str(),
+                Scope::DnsUpdate.as_str()
+            );
+            return ResponseUtils::create_error(format, &reason, HTTP_FORBIDDEN);
+        }
+        let (ipv4, ipv6) = IpUtils::split_ip(&client_ip);
+        return ResponseUtils::create_response(format, ipv4, ipv6, None).await;
     }
 
     // Parse request context
@@ -38,23 +119,827 @@ pub async fn handler(req: Request, env: Env) -> Result<Response> {
         Err(e) => return Response::error(e.to_string(), HTTP_BAD_REQUEST),
     };
 
+    // Named tokens may only update the homenames they are bound to
+    if !credential.allows(&ctx.homename) {
+        let reason = format!("token may not update {}", ctx.homename);
+        return ResponseUtils::create_error(ctx.format, &reason, HTTP_FORBIDDEN);
+    }
+
+    // Reserved names hold production records no caller may overwrite
+    if config.is_reserved(&ctx.homename) {
+        let reason = format!("{} is a reserved name", ctx.homename);
+        return ResponseUtils::create_error(ctx.format, &reason, HTTP_FORBIDDEN);
+    }
+
+    // Taking over records this worker did not create is an admin decision
+    if ctx.adopt && !credential.has_scope(Scope::Admin) {
+        let reason = "adopting existing records requires the admin scope";
+        return ResponseUtils::create_error(ctx.format, reason, HTTP_FORBIDDEN);
+    }
+
+    // Callers outside the configured networks are refused even with a valid credential
+    let verdict = config.ip_access.check(&ctx.homename, &ctx.client_ip);
+    if verdict != IpVerdict::Allowed {
+        let reason = verdict.reason(&ctx.client_ip);
+        return ResponseUtils::create_error(ctx.format, &reason, HTTP_FORBIDDEN);
+    }
+
+    let keys = [
+        (RateKind::Token, credential.rate_key()),
+        (RateKind::Homename, ctx.homename.as_str()),
+    ];
+    if let Some(wait) = limiter.check(&config.rate_limits, &keys, now_ms).await? {
+        return too_many_requests(ctx.format, "Too many requests", wait);
+    }
+
+    // The first credential to update a homename owns it from then on
+    let claims = ClaimStore::new(&kv);
+    if claims
+        .claim(&ctx.homename, &credential, now_ms)
+        .await?
+        .is_some()
+    {
+        let reason = format!("{} is claimed by another credential", ctx.homename);
+        return ResponseUtils::create_error(ctx.format, &reason, HTTP_FORBIDDEN);
+    }
+
     // Split IP into IPv4 and IPv6 components
     let (ipv4, ipv6) = IpUtils::split_ip(&ctx.client_ip);
 
-    // Update DNS records if necessary
-    if let Err(e) =
-        DnsUpdateService::maybe_update_dns(&ctx.homename, &ipv4, &ipv6, &env, &config).await
+    // Record the check-in; liveness tracking never fails the request
+    if let Err(e) = DnsUpdateService::record_seen(&ctx.homename, &ipv4, &ipv6, &env, &config).await
+    {
+        console_log!("Recording check-in failed for {}: {}", ctx.homename, e);
+    }
+
+    // Update DNS records if necessary, holding risky changes for approval
+    let caller = Caller::from_request(&req, &client_ip, &credential);
+    let dns = match DnsUpdateService::screened_update(
+        &ctx.homename,
+        &ipv4,
+        &ipv6,
+        &caller,
+        ctx.adopt,
+        &env,
+        &worker_ctx,
+        &config,
+    )
+    .await
     {
-        // Log error but don't fail the request
-        console_log!("DNS update failed: {}", e);
+        Ok(outcome) => outcome,
+        Err(e) => DnsUpdateOutcome::failed(&[RecordType::A, RecordType::AAAA], &e.to_string()),
+    };
+    let held = dns.is_held();
+
+    // Failures are reported in the body; only strict mode fails the request
+    let failed = dns.has_errors();
+    if failed {
+        console_log!("DNS update failed for {}: {:?}", ctx.homename, dns.records);
     }
+    // Records this worker does not manage stay refused however often the client retries
+    let refused = dns.is_refused();
 
     // Create and return response
-    ResponseUtils::create_response(ctx.format, ipv4, ipv6).await
+    let resp = ResponseUtils::create_response(ctx.format, ipv4, ipv6, Some(dns)).await?;
+    if refused {
+        return Ok(resp.with_status(HTTP_FORBIDDEN));
+    }
+    if failed && (config.strict_dns_update || ctx.strict) {
+        return Ok(resp.with_status(HTTP_BAD_GATEWAY));
+    }
+    if held {
+        return Ok(resp.with_status(HTTP_ACCEPTED));
+    }
+    Ok(resp)
+}
+
+/// 429 response telling the client how many seconds to wait
+fn too_many_requests(format: Format, message: &str, retry_after: u64) -> Result<Response> {
+    let message = format!("{}, retry in {}s", message, retry_after);
+    let resp = ResponseUtils::create_error(format, &message, HTTP_TOO_MANY_REQUESTS)?;
+    with_retry_after(resp, retry_after)
+}
+
+/// Adds `Retry-After` telling the client how many seconds to wait
+fn with_retry_after(mut resp: Response, retry_after: u64) -> Result<Response> {
+    resp.headers_mut()
+        .set(HEADER_RETRY_AFTER, &retry_after.to_string())?;
+    Ok(resp)
+}
+
+/// Authenticates a request, refusing locked-out sources and auditing failures
+///
+/// `query_token` also accepts a non-admin `token` query parameter, for update
+/// endpoints only. When the request is refused, the response to send is
+/// returned instead of a credential.
+async fn authenticate_request(
+    req: &Request,
+    config: &Config,
+    kv: &kv::KvStore,
+    query_token: bool,
+) -> Result<std::result::Result<Credential, Response>> {
+    let audit = AuditLog::new(kv);
+    let now_ms = Date::now().as_millis();
+    let client_ip = RequestContext::extract_client_ip(req)?;
+    if let Some(wait) = audit.locked_for(&client_ip, now_ms).await? {
+        let format = RequestContext::detect_format(req);
+        return too_many_requests(format, LOCKED_OUT_MESSAGE, wait).map(Err);
+    }
+
+    let credential = match query_token {
+        true => AuthUtils::authenticate_update(req, config, kv).await?,
+        false => AuthUtils::authenticate(req, config, kv).await?,
+    };
+    if let Some(credential) = credential {
+        return Ok(Ok(credential));
+    }
+    let failure = AuthFailure {
+        client_ip,
+        homename: req
+            .url()?
+            .query_pairs()
+            .find(|(key, _)| key == "homename")
+            .map(|(_, value)| value.to_string()),
+        timestamp: now_ms,
+        reason: AuthUtils::failure_reason(req, config)?,
+    };
+    record_failure(&audit, &failure, req, config).await;
+    Response::error("Unauthorized", HTTP_UNAUTHORIZED).map(Err)
+}
+
+/// Authenticates a request for an admin endpoint, refusing credentials without the `admin` scope
+async fn require_admin(
+    req: &Request,
+    config: &Config,
+    kv: &kv::KvStore,
+) -> Result<std::result::Result<Credential, Response>> {
+    match authenticate_request(req, config, kv, false).await? {
+        Ok(credential) if credential.has_scope(Scope::Admin) => Ok(Ok(credential)),
+        Ok(_) => Response::error("Forbidden", HTTP_FORBIDDEN).map(Err),
+        Err(resp) => Ok(Err(resp)),
+    }
+}
+
+/// Audits a failed authentication; storage errors never change the response
+///
+/// The request URL is logged with query-string credentials redacted.
+async fn record_failure(
+    audit: &AuditLog<'_>,
+    failure: &AuthFailure,
+    req: &Request,
+    config: &Config,
+) {
+    if let Ok(url) = req.url() {
+        console_log!(
+            "Authentication failed for {} from {}: {:?}",
+            AuthUtils::redact_url(&url),
+            failure.client_ip,
+            failure.reason
+        );
+    }
+    match audit.record(failure, config.auth_lockout.as_ref()).await {
+        Ok(Some(secs)) => console_log!(
+            "Locked out {} for {}s after repeated authentication failures",
+            failure.client_ip,
+            secs
+        ),
+        Ok(None) => {}
+        Err(e) => console_log!("Auditing authentication failure failed: {}", e),
+    }
+}
+
+/// DynDNS2-compatible `/nic/update` handler for routers and ddclient
+pub async fn dyndns_handler(req: Request, env: Env, worker_ctx: Context) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let kv = env.kv("IP_STORE")?;
+    let limiter = RateLimiter::new(&kv);
+    let audit = AuditLog::new(&kv);
+    let now_ms = Date::now().as_millis();
+    let client_ip = RequestContext::extract_client_ip(&req)?;
+    let ip_key = [(RateKind::Ip, client_ip.as_str())];
+    let wait = match limiter.check(&config.rate_limits, &ip_key, now_ms).await? {
+        Some(wait) => Some(wait),
+        None => audit.locked_for(&client_ip, now_ms).await?,
+    };
+    // `abuse` makes clients stop until someone steps in, `911` asks them to retry later
+    if let Some(wait) = wait {
+        let resp = with_retry_after(Response::ok(DynDnsReply::ServerError.as_line())?, wait)?;
+        return Ok(resp.with_status(HTTP_TOO_MANY_REQUESTS));
+    }
+
+    let Some(credential) = AuthUtils::authenticate_header(&req, &config, &kv).await? else {
+        let url = req.url()?;
+        let failure = AuthFailure {
+            client_ip,
+            homename: url
+                .query_pairs()
+                .find(|(key, _)| key == "hostname")
+                .map(|(_, value)| value.to_string()),
+            timestamp: now_ms,
+            reason: AuthUtils::failure_reason(&req, &config)?,
+        };
+        record_failure(&audit, &failure, &req, &config).await;
+        let mut resp = Response::ok(DynDnsReply::BadAuth.as_line())?;
+        resp.headers_mut()
+            .set(HEADER_WWW_AUTHENTICATE, DYNDNS_AUTH_CHALLENGE)?;
+        return Ok(resp.with_status(HTTP_UNAUTHORIZED));
+    };
+    if !credential.has_scope(Scope::DnsUpdate) {
+        return Response::ok(DynDnsReply::BadAuth.as_line());
+    }
+
+    let update = DynDnsRequest::from_request(&req)?;
+    if update.hostnames.is_empty() {
+        return Response::ok(DynDnsReply::NotFqdn.as_line());
+    }
+
+    let domains = config.domains();
+    let claims = ClaimStore::new(&kv);
+    let mut lines = Vec::with_capacity(update.hostnames.len());
+    let mut retry_after = None;
+    for hostname in &update.hostnames {
+        let reply = match DynDnsUtils::homename_from_hostname_in(hostname, &domains) {
+            Ok(homename) if !credential.allows(&homename) || config.is_reserved(&homename) => {
+                DynDnsReply::NoHost
+            }
+            Ok(homename) if config.ip_access.check(&homename, &client_ip) != IpVerdict::Allowed => {
+                DynDnsReply::Abuse
+            }
+            Ok(homename)
+                if claims
+                    .claim(&homename, &credential, now_ms)
+                    .await?
+                    .is_some() =>
+            {
+                DynDnsReply::NoHost
+            }
+            Ok(homename)
+                if let Some(wait) = limiter
+                    .check(
+                        &config.rate_limits,
+                        &[
+                            (RateKind::Token, credential.rate_key()),
+                            (RateKind::Homename, homename.as_str()),
+                        ],
+                        now_ms,
+                    )
+                    .await? =>
+            {
+                retry_after = retry_after.max(Some(wait));
+                DynDnsReply::ServerError
+            }
+            Ok(homename) => {
+                if let Err(e) = DnsUpdateService::record_seen(
+                    &homename,
+                    &update.ipv4,
+                    &update.ipv6,
+                    &env,
+                    &config,
+                )
+                .await
+                {
+                    console_log!("Recording check-in failed for {}: {}", homename, e);
+                }
+                let caller = Caller::from_request(&req, &client_ip, &credential);
+                match DnsUpdateService::screened_update(
+                    &homename,
+                    &update.ipv4,
+                    &update.ipv6,
+                    &caller,
+                    false,
+                    &env,
+                    &worker_ctx,
+                    &config,
+                )
+                .await
+                {
+                    // Retrying cannot help against a record this worker does not manage
+                    Ok(dns) if dns.is_refused() => {
+                        console_log!("DNS update refused for {}: {:?}", hostname, dns.records);
+                        DynDnsReply::NoHost
+                    }
+                    result => match result.and_then(DnsUpdateOutcome::into_result) {
+                        Ok(true) => DynDnsReply::Good(update.reply_ips()),
+                        Ok(false) => DynDnsReply::NoChg(update.reply_ips()),
+                        Err(e) => {
+                            console_log!("DNS update failed for {}: {}", hostname, e);
+                            DynDnsReply::ServerError
+                        }
+                    },
+                }
+            }
+            Err(reply) => reply,
+        };
+        lines.push(reply.as_line());
+    }
+
+    let resp = Response::ok(lines.join("\n"))?;
+    match retry_after {
+        Some(wait) => with_retry_after(resp, wait),
+        None => Ok(resp),
+    }
+}
+
+/// DuckDNS-compatible `/update` handler for IoT devices
+pub async fn duckdns_handler(req: Request, env: Env, worker_ctx: Context) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let update = match DuckDnsRequest::from_request(&req) {
+        Ok(update) => update,
+        Err(_) => return Response::ok(DuckDnsRequest::failure()),
+    };
+
+    let kv = env.kv("IP_STORE")?;
+    let limiter = RateLimiter::new(&kv);
+    let audit = AuditLog::new(&kv);
+    let now_ms = Date::now().as_millis();
+    let client_ip = RequestContext::extract_client_ip(&req)?;
+    let ip_key = [(RateKind::Ip, client_ip.as_str())];
+    if let Some(wait) = limiter.check(&config.rate_limits, &ip_key, now_ms).await? {
+        return with_retry_after(Response::ok(DuckDnsRequest::failure())?, wait);
+    }
+    if audit.locked_for(&client_ip, now_ms).await?.is_some() {
+        return Response::ok(DuckDnsRequest::failure());
+    }
+
+    let credential = match update.token.as_deref() {
+        Some(token) => {
+            AuthUtils::authenticate_query_token(token, config.api_token.as_deref(), &kv, now_ms)
+                .await?
+        }
+        None => None,
+    };
+    let Some(credential) = credential else {
+        let failure = AuthFailure {
+            client_ip,
+            homename: Some(update.domains.join(",")).filter(|domains| !domains.is_empty()),
+            timestamp: now_ms,
+            reason: match update.token {
+                Some(_) => FailureReason::InvalidToken,
+                None => FailureReason::MissingCredentials,
+            },
+        };
+        record_failure(&audit, &failure, &req, &config).await;
+        return Response::ok(DuckDnsRequest::failure());
+    };
+    if !credential.has_scope(Scope::DnsUpdate) {
+        return Response::ok(DuckDnsRequest::failure());
+    }
+
+    let domains = config.domains();
+    let homenames: Option<Vec<String>> = update
+        .domains
+        .iter()
+        .map(|d| DuckDnsUtils::homename_from_domain(d, &domains))
+        .collect();
+    let homenames = match homenames {
+        Some(homenames) if !homenames.is_empty() => homenames,
+        _ => return Response::ok(DuckDnsRequest::failure()),
+    };
+    if homenames.iter().any(|homename| {
+        !credential.allows(homename)
+            || config.is_reserved(homename)
+            || config.ip_access.check(homename, &client_ip) != IpVerdict::Allowed
+    }) {
+        return Response::ok(DuckDnsRequest::failure());
+    }
+    // Taking a quarantined homename offline is as risky as moving it
+    if update.action == DuckDnsAction::Clear
+        && !credential.has_scope(Scope::Admin)
+        && homenames.iter().any(|h| config.quarantine.applies(h))
+    {
+        console_log!("Refused clearing quarantined homenames {:?}", homenames);
+        return Response::ok(DuckDnsRequest::failure());
+    }
+    // Check every homename before claiming any, so a refused request claims nothing
+    let claims = ClaimStore::new(&kv);
+    for homename in &homenames {
+        if claims.conflict(homename, &credential).await?.is_some() {
+            return Response::ok(DuckDnsRequest::failure());
+        }
+    }
+    for homename in &homenames {
+        let keys = [
+            (RateKind::Token, credential.rate_key()),
+            (RateKind::Homename, homename.as_str()),
+        ];
+        if let Some(wait) = limiter.check(&config.rate_limits, &keys, now_ms).await? {
+            return with_retry_after(Response::ok(DuckDnsRequest::failure())?, wait);
+        }
+    }
+    for homename in &homenames {
+        // Only a concurrent request can have claimed it since the check above
+        if claims.claim(homename, &credential, now_ms).await?.is_some() {
+            return Response::ok(DuckDnsRequest::failure());
+        }
+    }
+
+    let mut changed = false;
+    for homename in &homenames {
+        let result = match &update.action {
+            DuckDnsAction::Update { ipv4, ipv6 } => {
+                if let Err(e) =
+                    DnsUpdateService::record_seen(homename, ipv4, ipv6, &env, &config).await
+                {
+                    console_log!("Recording check-in failed for {}: {}", homename, e);
+                }
+                let caller = Caller::from_request(&req, &client_ip, &credential);
+                DnsUpdateService::screened_update(
+                    homename,
+                    ipv4,
+                    ipv6,
+                    &caller,
+                    false,
+                    &env,
+                    &worker_ctx,
+                    &config,
+                )
+                .await
+                .and_then(DnsUpdateOutcome::into_result)
+            }
+            DuckDnsAction::Clear => {
+                DnsUpdateService::clear_records(
+                    homename,
+                    &[RecordType::A, RecordType::AAAA],
+                    &env,
+                    &config,
+                )
+                .await
+            }
+            DuckDnsAction::SetTxt(txt) => {
+                DnsUpdateService::maybe_update_txt(homename, txt, &env, &worker_ctx, &config)
+                    .await
+                    .and_then(DnsUpdateOutcome::into_result)
+            }
+            DuckDnsAction::ClearTxt => {
+                DnsUpdateService::clear_records(homename, &[RecordType::TXT], &env, &config).await
+            }
+        };
+
+        match result {
+            Ok(record_changed) => changed |= record_changed,
+            Err(e) => {
+                console_log!("DuckDNS update failed for {}: {}", homename, e);
+                return Response::ok(DuckDnsRequest::failure());
+            }
+        }
+    }
+
+    Response::ok(update.reply(changed))
+}
+
+/// `/status` handler reporting when each homename last checked in
+pub async fn status_handler(req: Request, env: Env) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let kv = env.kv("IP_STORE")?;
+    if let Err(resp) = require_admin(&req, &config, &kv).await? {
+        return Ok(resp);
+    }
+
+    // An optional `homename` parameter narrows the report to one host
+    let url = req.url()?;
+    let filter = url
+        .query_pairs()
+        .find(|(key, _)| key == "homename")
+        .map(|(_, value)| value.to_string());
+
+    let hosts: Vec<HostStatus> = DnsUpdateService::host_statuses(&env, &config)
+        .await?
+        .into_iter()
+        .filter(|status| filter.as_ref().is_none_or(|h| *h == status.homename))
+        .collect();
+    Response::from_json(&serde_json::json!({ "hosts": hosts }))
+}
+
+/// `/admin/tokens` handler to create, list and revoke named tokens
+pub async fn tokens_handler(mut req: Request, env: Env) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let kv = env.kv("IP_STORE")?;
+    if let Err(resp) = require_admin(&req, &config, &kv).await? {
+        return Ok(resp);
+    }
+
+    let tokens = TokenStore::new(&kv);
+    let path = req.path();
+    match (req.method(), path.strip_prefix(tokens::ADMIN_TOKENS_PATH)) {
+        (Method::Get, Some("")) => {
+            Response::from_json(&serde_json::json!({ "tokens": tokens.list().await? }))
+        }
+        (Method::Post, Some("")) => {
+            let request: CreateTokenRequest = match req.json().await {
+                Ok(request) => request,
+                Err(e) => return Response::error(e.to_string(), HTTP_BAD_REQUEST),
+            };
+            if request.name.trim().is_empty() {
+                return Response::error("token name required", HTTP_BAD_REQUEST);
+            }
+            // The plaintext token is only ever part of this response
+            match tokens.create(request, Date::now().as_millis()).await? {
+                Some(created) => Ok(Response::from_json(&created)?.with_status(HTTP_CREATED)),
+                None => Response::error("invalid expires_in", HTTP_BAD_REQUEST),
+            }
+        }
+        (Method::Delete, Some(rest)) => match rest.strip_prefix('/') {
+            Some(id) if !id.is_empty() && tokens.revoke(id).await? => {
+                Ok(Response::empty()?.with_status(HTTP_NO_CONTENT))
+            }
+            _ => Response::error("Not Found", HTTP_NOT_FOUND),
+        },
+        _ => Response::error("Not Found", HTTP_NOT_FOUND),
+    }
+}
+
+/// `/enroll` handler where devices register their Ed25519 public key for approval
+pub async fn enroll_handler(mut req: Request, env: Env) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+    if req.method() != Method::Post {
+        return Response::error("Not Found", HTTP_NOT_FOUND);
+    }
+
+    // Enrollment is unauthenticated, so only the per-IP limit can slow down floods
+    let kv = env.kv("IP_STORE")?;
+    let now_ms = Date::now().as_millis();
+    let client_ip = RequestContext::extract_client_ip(&req)?;
+    let ip_key = [(RateKind::Ip, client_ip.as_str())];
+    if let Some(wait) = RateLimiter::new(&kv)
+        .check(&config.rate_limits, &ip_key, now_ms)
+        .await?
+    {
+        return too_many_requests(Format::Json, "Too many requests", wait);
+    }
+
+    let request: EnrollRequest = match req.json().await {
+        Ok(request) => request,
+        Err(e) => return Response::error(e.to_string(), HTTP_BAD_REQUEST),
+    };
+    if request.name.trim().is_empty() {
+        return Response::error("device name required", HTTP_BAD_REQUEST);
+    }
+    match DeviceStore::new(&kv).enroll(request, now_ms).await? {
+        Some(device) if device.record.status == DeviceStatus::Pending => {
+            Ok(Response::from_json(&device)?.with_status(HTTP_ACCEPTED))
+        }
+        Some(device) => Response::from_json(&device),
+        None => Response::error("invalid Ed25519 public key", HTTP_BAD_REQUEST),
+    }
+}
+
+/// `/admin/devices` handler to list, approve and revoke enrolled devices
+pub async fn devices_handler(mut req: Request, env: Env) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let kv = env.kv("IP_STORE")?;
+    if let Err(resp) = require_admin(&req, &config, &kv).await? {
+        return Ok(resp);
+    }
+
+    let devices = DeviceStore::new(&kv);
+    let path = req.path();
+    let rest = path
+        .strip_prefix(devices::ADMIN_DEVICES_PATH)
+        .unwrap_or_default();
+    match (req.method(), rest.strip_prefix('/')) {
+        (Method::Get, None) if rest.is_empty() => {
+            Response::from_json(&serde_json::json!({ "devices": devices.list().await? }))
+        }
+        (Method::Post, Some(action)) if action.ends_with("/approve") => {
+            let id = action.trim_end_matches("/approve");
+            // The body is optional and only overrides what the device asked for
+            let body = req.text().await?;
+            let request: ApproveRequest = match body.trim() {
+                "" => ApproveRequest::default(),
+                body => match serde_json::from_str(body) {
+                    Ok(request) => request,
+                    Err(e) => return Response::error(e.to_string(), HTTP_BAD_REQUEST),
+                },
+            };
+            match devices
+                .approve(id, request, Date::now().as_millis())
+                .await?
+            {
+                Some(device) => Response::from_json(&device),
+                None => Response::error("Not Found", HTTP_NOT_FOUND),
+            }
+        }
+        (Method::Delete, Some(id)) if !id.is_empty() && devices.revoke(id).await? => {
+            Ok(Response::empty()?.with_status(HTTP_NO_CONTENT))
+        }
+        _ => Response::error("Not Found", HTTP_NOT_FOUND),
+    }
+}
+
+/// `/admin/claims` handler to list, transfer and release homename claims
+pub async fn claims_handler(mut req: Request, env: Env) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let kv = env.kv("IP_STORE")?;
+    if let Err(resp) = require_admin(&req, &config, &kv).await? {
+        return Ok(resp);
+    }
+
+    let claims = ClaimStore::new(&kv);
+    let path = req.path();
+    let rest = path
+        .strip_prefix(claims::ADMIN_CLAIMS_PATH)
+        .unwrap_or_default();
+    match (req.method(), rest.strip_prefix('/')) {
+        (Method::Get, None) if rest.is_empty() => {
+            Response::from_json(&serde_json::json!({ "claims": claims.list().await? }))
+        }
+        (Method::Put, Some(homename)) if RequestContext::is_valid_homename(homename) => {
+            let homename = homename.to_ascii_lowercase();
+            let request: TransferRequest = match req.json().await {
+                Ok(request) => request,
+                Err(e) => return Response::error(e.to_string(), HTTP_BAD_REQUEST),
+            };
+            if request.owner.trim().is_empty() {
+                return Response::error("owner required", HTTP_BAD_REQUEST);
+            }
+            let claim = claims
+                .transfer(&homename, request, Date::now().as_millis())
+                .await?;
+            Response::from_json(&claim)
+        }
+        (Method::Delete, Some(homename))
+            if claims.release(&homename.to_ascii_lowercase()).await? =>
+        {
+            Ok(Response::empty()?.with_status(HTTP_NO_CONTENT))
+        }
+        _ => Response::error("Not Found", HTTP_NOT_FOUND),
+    }
+}
+
+/// `/admin/pending` handler to list, approve and reject changes held for approval
+pub async fn pending_handler(req: Request, env: Env, worker_ctx: Context) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let kv = env.kv("IP_STORE")?;
+    if let Err(resp) = require_admin(&req, &config, &kv).await? {
+        return Ok(resp);
+    }
+
+    let quarantine = Quarantine::new(&kv);
+    let path = req.path();
+    let rest = path
+        .strip_prefix(quarantine::ADMIN_PENDING_PATH)
+        .unwrap_or_default();
+    match (req.method(), rest.strip_prefix('/')) {
+        (Method::Get, None) if rest.is_empty() => {
+            Response::from_json(&serde_json::json!({ "pending": quarantine.list().await? }))
+        }
+        (Method::Post, Some(rest)) => match rest.strip_suffix("/approve") {
+            Some(homename) if RequestContext::is_valid_homename(homename) => {
+                let homename = homename.to_ascii_lowercase();
+                match DnsUpdateService::approve_change(&homename, &env, &worker_ctx, &config)
+                    .await?
+                {
+                    Some(dns) => Response::from_json(&dns),
+                    None => Response::error("Not Found", HTTP_NOT_FOUND),
+                }
+            }
+            _ => Response::error("Not Found", HTTP_NOT_FOUND),
+        },
+        (Method::Delete, Some(homename))
+            if quarantine.discard(&homename.to_ascii_lowercase()).await? =>
+        {
+            Ok(Response::empty()?.with_status(HTTP_NO_CONTENT))
+        }
+        _ => Response::error("Not Found", HTTP_NOT_FOUND),
+    }
+}
+
+/// `/admin/auth-failures` handler listing failed authentications and lifting lockouts
+pub async fn auth_failures_handler(req: Request, env: Env) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let kv = env.kv("IP_STORE")?;
+    if let Err(resp) = require_admin(&req, &config, &kv).await? {
+        return Ok(resp);
+    }
+
+    let audit = AuditLog::new(&kv);
+    let path = req.path();
+    match (req.method(), path.strip_prefix(audit::AUTH_FAILURES_PATH)) {
+        (Method::Get, Some("")) => Response::from_json(&serde_json::json!({
+            "failures": audit.recent().await?,
+            "lockouts": audit.lockouts(Date::now().as_millis()).await?,
+        })),
+        (Method::Delete, Some(rest)) => match rest.strip_prefix("/lockouts/") {
+            Some(ip) if !ip.is_empty() && audit.unlock(ip).await? => {
+                Ok(Response::empty()?.with_status(HTTP_NO_CONTENT))
+            }
+            _ => Response::error("Not Found", HTTP_NOT_FOUND),
+        },
+        _ => Response::error("Not Found", HTTP_NOT_FOUND),
+    }
+}
+
+/// `/admin/signed-url` handler issuing single-use update URLs for header-less clients
+pub async fn signed_url_handler(req: Request, env: Env) -> Result<Response> {
+    let config = Config::from_env(&env)?;
+
+    let kv = env.kv("IP_STORE")?;
+    if let Err(resp) = require_admin(&req, &config, &kv).await? {
+        return Ok(resp);
+    }
+    let Some(key) = config.url_signing_key.as_deref() else {
+        return Response::error("URL_SIGNING_KEY is not configured", HTTP_BAD_REQUEST);
+    };
+
+    let url = req.url()?;
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    let homename = match param("homename") {
+        Some(homename) if RequestContext::is_valid_homename(&homename) => homename,
+        _ => return Response::error("invalid homename", HTTP_BAD_REQUEST),
+    };
+    let Some(ttl) = AuthUtils::signed_url_ttl(param("expires_in").as_deref()) else {
+        return Response::error("invalid expires_in", HTTP_BAD_REQUEST);
+    };
+
+    let Some(expires) = (Date::now().as_millis() / 1000).checked_add(ttl) else {
+        return Response::error("invalid expires_in", HTTP_BAD_REQUEST);
+    };
+    let base_url = format!("{}/", url.origin().ascii_serialization());
+    let signed = AuthUtils::generate_signed_url(&base_url, key, &homename, expires)?;
+    Response::from_json(&serde_json::json!({ "url": signed, "expires": expires }))
 }
 
 /// Cloudflare Workers entry point
 #[event(fetch)]
-pub async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
-    handler(req, env).await
+pub async fn main(req: Request, env: Env, ctx: Context) -> Result<Response> {
+    match req.path().as_str() {
+        dyndns::DYNDNS_UPDATE_PATH => dyndns_handler(req, env, ctx).await,
+        duckdns::DUCKDNS_UPDATE_PATH => duckdns_handler(req, env, ctx).await,
+        heartbeat::STATUS_PATH => status_handler(req, env).await,
+        auth::SIGNED_URL_PATH => signed_url_handler(req, env).await,
+        path if path.starts_with(tokens::ADMIN_TOKENS_PATH) => tokens_handler(req, env).await,
+        path if path.starts_with(devices::ADMIN_DEVICES_PATH) => devices_handler(req, env).await,
+        devices::ENROLL_PATH => enroll_handler(req, env).await,
+        path if path.starts_with(claims::ADMIN_CLAIMS_PATH) => claims_handler(req, env).await,
+        path if path.starts_with(quarantine::ADMIN_PENDING_PATH) => {
+            pending_handler(req, env, ctx).await
+        }
+        path if path.starts_with(audit::AUTH_FAILURES_PATH) => {
+            auth_failures_handler(req, env).await
+        }
+        _ => handler(req, env, ctx).await,
+    }
+}
+
+/// Cron trigger entry point: repairs drift between KV state and live DNS
+/// and reports homenames that stopped checking in
+#[event(scheduled)]
+pub async fn scheduled(_event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    let config = match Config::from_env(&env) {
+        Ok(config) => config,
+        Err(e) => {
+            console_log!("Reconciliation skipped, invalid configuration: {}", e);
+            return;
+        }
+    };
+
+    match DnsUpdateService::reconcile_all(&env, &config).await {
+        Ok(summary) => {
+            for (homename, outcome) in &summary.hosts {
+                if outcome.has_errors() {
+                    console_log!(
+                        "Reconciliation failed for {}: {:?}",
+                        homename,
+                        outcome.records
+                    );
+                } else if outcome.changed() {
+                    console_log!("Repaired DNS drift for {}: {:?}", homename, outcome.records);
+                }
+            }
+            console_log!("{}", summary);
+        }
+        Err(e) => console_log!("Reconciliation failed: {}", e),
+    }
+
+    match DnsUpdateService::check_heartbeats(&env, &config).await {
+        Ok(alerts) => {
+            for alert in &alerts {
+                console_log!(
+                    "{:?}: {} silent for {}s (threshold {}s)",
+                    alert.event,
+                    alert.homename,
+                    alert.silent_for,
+                    alert.threshold
+                );
+                if let Some(url) = &config.alert_webhook_url
+                    && let Err(e) = NotifyUtils::post_json(url, alert).await
+                {
+                    console_log!("Alert delivery failed for {}: {}", alert.homename, e);
+                }
+            }
+        }
+        Err(e) => console_log!("Heartbeat check failed: {}", e),
+    }
 }
@@ -0,0 +1,449 @@
+use crate::config::Config;
+use crate::dns::{DnsUpdateOutcome, RecordType};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+use worker::*;
+
+// Constants
+const CONTENT_TYPE_JSON: &str = "application/json";
+const CONTENT_TYPE_TEXT: &str = "text/plain";
+const HEADER_SIGNATURE: &str = "X-Webhook-Signature";
+const SIGNATURE_PREFIX: &str = "sha256=";
+/// Delays before the second and third delivery attempt
+const RETRY_DELAYS_MS: [u64; 2] = [500, 2000];
+const GOTIFY_PRIORITY: u8 = 5;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Payload layout expected by a webhook receiver
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookPreset {
+    /// The raw change event as JSON
+    #[default]
+    Json,
+    Slack,
+    Discord,
+    Ntfy,
+    Gotify,
+}
+
+/// A webhook receiving record change events, configured through `WEBHOOKS`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebhookTarget {
+    /// Endpoint the events are POSTed to
+    pub url: String,
+    /// Payload layout, the raw event unless a preset is named
+    #[serde(default)]
+    pub preset: WebhookPreset,
+    /// Homename patterns the target is interested in, all homenames when empty
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// HMAC secret overriding `WEBHOOK_SECRET` for this target
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+impl WebhookTarget {
+    /// Whether events for the given homename are sent to this target
+    pub fn matches(&self, homename: &str) -> bool {
+        self.hosts.is_empty()
+            || self
+                .hosts
+                .iter()
+                .any(|pattern| Config::glob_match(pattern, homename))
+    }
+}
+
+/// A DNS record that was written with a new address
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangeEvent {
+    pub homename: String,
+    pub record_name: String,
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    pub old_ip: Option<String>,
+    pub new_ip: String,
+    /// Unix time of the change in milliseconds
+    pub timestamp: u64,
+}
+
+impl ChangeEvent {
+    /// One event per record the outcome reports as created or updated
+    ///
+    /// `contents` maps each record type to the content that was requested.
+    pub fn from_outcome(
+        homename: &str,
+        record_name: &str,
+        outcome: &DnsUpdateOutcome,
+        contents: &[(RecordType, &str)],
+        timestamp: u64,
+    ) -> Vec<Self> {
+        outcome
+            .records
+            .iter()
+            .filter(|record| record.changed())
+            .filter_map(|record| {
+                let (_, new_ip) = contents.iter().find(|(t, _)| *t == record.record_type)?;
+                Some(Self {
+                    homename: homename.to_string(),
+                    record_name: record_name.to_string(),
+                    record_type: record.record_type,
+                    old_ip: record.previous_ip.clone(),
+                    new_ip: new_ip.to_string(),
+                    timestamp,
+                })
+            })
+            .collect()
+    }
+
+    /// One-line human readable summary used by the chat presets
+    pub fn summary(&self) -> String {
+        match &self.old_ip {
+            Some(old_ip) => format!(
+                "{} {} changed from {} to {}",
+                self.record_name,
+                self.record_type.as_str(),
+                old_ip,
+                self.new_ip
+            ),
+            None => format!(
+                "{} {} set to {}",
+                self.record_name,
+                self.record_type.as_str(),
+                self.new_ip
+            ),
+        }
+    }
+}
+
+/// A rendered webhook request, ready to be sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Outbound notification utilities
+pub struct NotifyUtils;
+
+impl NotifyUtils {
+    /// POSTs a JSON payload to a webhook, failing once retries are exhausted
+    pub async fn post_json<T: Serialize>(url: &str, payload: &T) -> Result<()> {
+        let request = WebhookRequest {
+            url: url.to_string(),
+            headers: vec![("Content-Type".to_string(), CONTENT_TYPE_JSON.to_string())],
+            body: serde_json::to_string(payload)?,
+        };
+        Self::send_with_retry(&request).await
+    }
+
+    /// Hex HMAC-SHA256 of a payload, as sent in the signature header
+    pub fn sign(secret: &str, body: &str) -> String {
+        let mut mac =
+            HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(body.as_bytes());
+        format!(
+            "{}{}",
+            SIGNATURE_PREFIX,
+            hex::encode(mac.finalize().into_bytes())
+        )
+    }
+
+    /// Render an event in the target's payload layout, signing the body when a secret is known
+    pub fn render(
+        target: &WebhookTarget,
+        event: &ChangeEvent,
+        secret: Option<&str>,
+    ) -> Result<WebhookRequest> {
+        let summary = event.summary();
+        let (content_type, body, mut headers) = match target.preset {
+            WebhookPreset::Json => (CONTENT_TYPE_JSON, serde_json::to_string(event)?, vec![]),
+            WebhookPreset::Slack => (
+                CONTENT_TYPE_JSON,
+                serde_json::json!({ "text": summary }).to_string(),
+                vec![],
+            ),
+            WebhookPreset::Discord => (
+                CONTENT_TYPE_JSON,
+                serde_json::json!({ "content": summary }).to_string(),
+                vec![],
+            ),
+            WebhookPreset::Ntfy => (
+                CONTENT_TYPE_TEXT,
+                summary,
+                vec![(
+                    "Title".to_string(),
+                    format!("DNS change: {}", event.homename),
+                )],
+            ),
+            WebhookPreset::Gotify => (
+                CONTENT_TYPE_JSON,
+                serde_json::json!({
+                    "title": format!("DNS change: {}", event.homename),
+                    "message": summary,
+                    "priority": GOTIFY_PRIORITY,
+                })
+                .to_string(),
+                vec![],
+            ),
+        };
+
+        headers.insert(0, ("Content-Type".to_string(), content_type.to_string()));
+        if let Some(secret) = target.secret.as_deref().or(secret) {
+            headers.push((HEADER_SIGNATURE.to_string(), Self::sign(secret, &body)));
+        }
+
+        Ok(WebhookRequest {
+            url: target.url.clone(),
+            headers,
+            body,
+        })
+    }
+
+    /// Deliver change events to every interested webhook target
+    ///
+    /// Delivery failures are returned rather than raised so that a broken
+    /// receiver never fails the DNS update that triggered it.
+    pub async fn dispatch(events: &[ChangeEvent], config: &Config) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        for event in events {
+            for target in config
+                .webhooks
+                .iter()
+                .filter(|t| t.matches(&event.homename))
+            {
+                let delivered = match Self::render(target, event, config.webhook_secret.as_deref())
+                {
+                    Ok(request) => Self::send_with_retry(&request).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = delivered {
+                    failures.push(format!("{}: {}", event.record_name, e));
+                }
+            }
+        }
+
+        failures
+    }
+
+    /// Send a request, retrying with backoff on network errors and 429/5xx replies
+    async fn send_with_retry(request: &WebhookRequest) -> Result<()> {
+        let mut delays = RETRY_DELAYS_MS.iter();
+        loop {
+            let error = match Self::send(request).await {
+                Ok(200..=299) => return Ok(()),
+                Ok(status) => {
+                    let error = Error::RustError(format!("webhook replied with status {}", status));
+                    if !Self::is_retryable(status) {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(e) => e,
+            };
+            match delays.next() {
+                Some(&delay) => Delay::from(Duration::from_millis(delay)).await,
+                None => return Err(error),
+            }
+        }
+    }
+
+    /// Whether a reply status is worth another delivery attempt
+    fn is_retryable(status: u16) -> bool {
+        status == 429 || status >= 500
+    }
+
+    /// Send a request once, returning the reply status
+    async fn send(request: &WebhookRequest) -> Result<u16> {
+        let mut init = RequestInit::new();
+        init.with_method(Method::Post);
+        init.with_body(Some(request.body.clone().into()));
+
+        let mut req = Request::new_with_init(&request.url, &init)?;
+        for (name, value) in &request.headers {
+            req.headers_mut()?.set(name, value)?;
+        }
+
+        let resp = Fetch::Request(req).send().await?;
+        Ok(resp.status_code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(old_ip: Option<&str>) -> ChangeEvent {
+        ChangeEvent {
+            homename: "home".to_string(),
+            record_name: "home.example.com".to_string(),
+            record_type: RecordType::A,
+            old_ip: old_ip.map(str::to_string),
+            new_ip: "5.6.7.8".to_string(),
+            timestamp: 1_700_000_000_000,
+        }
+    }
+
+    fn target(preset: WebhookPreset) -> WebhookTarget {
+        WebhookTarget {
+            url: "https://hooks.example.com/dns".to_string(),
+            preset,
+            hosts: Vec::new(),
+            secret: None,
+        }
+    }
+
+    fn header<'a>(request: &'a WebhookRequest, name: &str) -> Option<&'a str> {
+        request
+            .headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn events_follow_changed_records() {
+        let outcome: DnsUpdateOutcome = serde_json::from_value(serde_json::json!({
+            "records": [
+                { "type": "A", "status": "updated", "previous_ip": "1.2.3.4" },
+                { "type": "AAAA", "status": "unchanged", "previous_ip": "2001:db8::1" },
+            ]
+        }))
+        .unwrap();
+        let contents = [
+            (RecordType::A, "5.6.7.8"),
+            (RecordType::AAAA, "2001:db8::1"),
+        ];
+
+        let events = ChangeEvent::from_outcome(
+            "home",
+            "home.example.com",
+            &outcome,
+            &contents,
+            1_700_000_000_000,
+        );
+        assert_eq!(events, vec![event(Some("1.2.3.4"))]);
+    }
+
+    #[test]
+    fn json_payload_is_signed() {
+        let request = NotifyUtils::render(
+            &target(WebhookPreset::Json),
+            &event(Some("1.2.3.4")),
+            Some("s3cret"),
+        )
+        .unwrap();
+
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "homename": "home",
+                "record_name": "home.example.com",
+                "type": "A",
+                "old_ip": "1.2.3.4",
+                "new_ip": "5.6.7.8",
+                "timestamp": 1_700_000_000_000u64,
+            })
+        );
+        assert_eq!(header(&request, "Content-Type"), Some(CONTENT_TYPE_JSON));
+        assert_eq!(
+            header(&request, HEADER_SIGNATURE),
+            Some(NotifyUtils::sign("s3cret", &request.body).as_str())
+        );
+
+        let unsigned =
+            NotifyUtils::render(&target(WebhookPreset::Json), &event(None), None).unwrap();
+        assert_eq!(header(&unsigned, HEADER_SIGNATURE), None);
+    }
+
+    #[test]
+    fn signature_matches_reference() {
+        // HMAC-SHA256 test vector from RFC 4231, test case 2
+        assert_eq!(
+            NotifyUtils::sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn preset_payloads() {
+        let event = event(Some("1.2.3.4"));
+        let summary = "home.example.com A changed from 1.2.3.4 to 5.6.7.8";
+        let test_cases = vec![
+            (
+                WebhookPreset::Slack,
+                serde_json::json!({ "text": summary }).to_string(),
+            ),
+            (
+                WebhookPreset::Discord,
+                serde_json::json!({ "content": summary }).to_string(),
+            ),
+            (WebhookPreset::Ntfy, summary.to_string()),
+            (
+                WebhookPreset::Gotify,
+                serde_json::json!({
+                    "title": "DNS change: home",
+                    "message": summary,
+                    "priority": GOTIFY_PRIORITY,
+                })
+                .to_string(),
+            ),
+        ];
+
+        for (preset, expected) in test_cases {
+            let request = NotifyUtils::render(&target(preset), &event, None).unwrap();
+            assert_eq!(request.body, expected, "Failed: {:?}", preset);
+        }
+
+        let ntfy = NotifyUtils::render(&target(WebhookPreset::Ntfy), &event, None).unwrap();
+        assert_eq!(header(&ntfy, "Title"), Some("DNS change: home"));
+        assert_eq!(header(&ntfy, "Content-Type"), Some(CONTENT_TYPE_TEXT));
+    }
+
+    #[test]
+    fn target_secret_and_host_filter() {
+        let target = WebhookTarget {
+            hosts: vec!["site-*".to_string()],
+            secret: Some("per-target".to_string()),
+            ..target(WebhookPreset::Json)
+        };
+        assert!(target.matches("site-berlin"));
+        assert!(!target.matches("home"));
+        assert!(WebhookTarget::matches(
+            &self::target(WebhookPreset::Json),
+            "home"
+        ));
+
+        let request = NotifyUtils::render(&target, &event(None), Some("global")).unwrap();
+        assert_eq!(
+            header(&request, HEADER_SIGNATURE),
+            Some(NotifyUtils::sign("per-target", &request.body).as_str())
+        );
+    }
+
+    #[test]
+    fn retryable_statuses() {
+        let test_cases = vec![
+            (500, true),
+            (503, true),
+            (429, true),
+            (404, false),
+            (400, false),
+        ];
+
+        for (status, expected) in test_cases {
+            assert_eq!(
+                NotifyUtils::is_retryable(status),
+                expected,
+                "Failed: {}",
+                status
+            );
+        }
+    }
+}
@@ -0,0 +1,556 @@
+use crate::acl::Cidr;
+use crate::config::Config;
+use crate::store::KeyValueStore;
+use crate::tokens::Credential;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use worker::*;
+
+// Constants
+pub const ADMIN_PENDING_PATH: &str = "/admin/pending";
+const PENDING_KEY_PREFIX: &str = "pending:";
+const PROFILE_KEY_PREFIX: &str = "profile:";
+/// Changes nobody approved are dropped after a week
+const PENDING_TTL_SECS: u64 = 7 * 24 * 3600;
+const DAY_MS: u64 = 24 * 3600 * 1000;
+/// Most countries, ASNs and ranges remembered per homename
+const MAX_REMEMBERED: usize = 16;
+/// Addresses are compared by the network they belong to, not one by one
+const IPV4_RANGE_PREFIX: u8 = 24;
+const IPV6_RANGE_PREFIX: u8 = 48;
+pub const HELD_EVENT: &str = "change_held";
+
+/// Rule flagging a change of a quarantined homename as suspicious
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiskRule {
+    /// The caller is in a country the homename was never updated from
+    NewCountry,
+    /// The caller is in an ASN the homename was never updated from
+    NewAsn,
+    /// The new address lies outside every network the homename used before
+    NewRange,
+    /// The homename already changed this many times in the last 24 hours
+    ChangesPerDay(u32),
+}
+
+impl RiskRule {
+    /// Parse `country`, `asn`, `range` or `changes=N`
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        if let Some(limit) = value.strip_prefix("changes=") {
+            return limit
+                .trim()
+                .parse()
+                .ok()
+                .filter(|l| *l > 0)
+                .map(RiskRule::ChangesPerDay);
+        }
+        match value.as_str() {
+            "country" => Some(RiskRule::NewCountry),
+            "asn" => Some(RiskRule::NewAsn),
+            "range" => Some(RiskRule::NewRange),
+            _ => None,
+        }
+    }
+}
+
+/// Homenames whose changes are screened and the rules screening them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuarantinePolicy {
+    /// Homename patterns subject to quarantine
+    pub hosts: Vec<String>,
+    pub rules: Vec<RiskRule>,
+}
+
+impl QuarantinePolicy {
+    /// Whether changes of the homename are screened
+    pub fn applies(&self, homename: &str) -> bool {
+        !self.rules.is_empty()
+            && self
+                .hosts
+                .iter()
+                .any(|pattern| Config::glob_match(pattern, homename))
+    }
+}
+
+/// Who asked for a change and where Cloudflare saw the request come from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Caller {
+    pub client_ip: String,
+    /// Name of the credential that authenticated the request
+    pub credential: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+}
+
+impl Caller {
+    /// Caller of an authenticated request, with country and ASN from `request.cf`
+    pub fn from_request(req: &Request, client_ip: &str, credential: &Credential) -> Self {
+        let cf = req.cf();
+        Self {
+            client_ip: client_ip.to_string(),
+            credential: credential.name.clone(),
+            country: cf.and_then(|cf| cf.country()),
+            asn: cf.map(|cf| cf.asn()).filter(|asn| *asn != 0),
+        }
+    }
+}
+
+/// What a homename was seen doing, stored under `profile:{homename}`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostProfile {
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub asns: Vec<u32>,
+    /// Networks of previously published addresses in CIDR notation
+    #[serde(default)]
+    pub ranges: Vec<String>,
+    /// Unix times in milliseconds of the changes in the last 24 hours
+    #[serde(default)]
+    pub changes: Vec<u64>,
+}
+
+impl HostProfile {
+    /// Network an address is compared by
+    pub fn range(ip: IpAddr) -> Cidr {
+        match ip {
+            IpAddr::V4(_) => Cidr::around(ip, IPV4_RANGE_PREFIX),
+            IpAddr::V6(_) => Cidr::around(ip, IPV6_RANGE_PREFIX),
+        }
+    }
+
+    fn remember<T: PartialEq>(list: &mut Vec<T>, value: T) {
+        if !list.contains(&value) {
+            list.push(value);
+        }
+        if list.len() > MAX_REMEMBERED {
+            list.remove(0);
+        }
+    }
+
+    /// Changes in the 24 hours before `now_ms`
+    fn recent_changes(&self, now_ms: u64) -> usize {
+        self.changes
+            .iter()
+            .filter(|&&at| now_ms.saturating_sub(at) < DAY_MS)
+            .count()
+    }
+
+    /// Reasons the change to `addresses` trips the policy, empty when it may proceed
+    ///
+    /// Nothing is new to a profile that has not seen anything yet, so the first
+    /// country, ASN and network of each address family are trusted. An update
+    /// changing no address is never held.
+    pub fn assess(
+        &self,
+        policy: &QuarantinePolicy,
+        addresses: &[&str],
+        caller: &Caller,
+        now_ms: u64,
+    ) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if addresses.is_empty() {
+            return reasons;
+        }
+        for rule in &policy.rules {
+            match *rule {
+                RiskRule::NewCountry => {
+                    if let Some(country) = &caller.country
+                        && !self.countries.is_empty()
+                        && !self.countries.contains(country)
+                    {
+                        reasons.push(format!("new country {}", country));
+                    }
+                }
+                RiskRule::NewAsn => {
+                    if let Some(asn) = caller.asn
+                        && !self.asns.is_empty()
+                        && !self.asns.contains(&asn)
+                    {
+                        reasons.push(format!("new ASN {}", asn));
+                    }
+                }
+                RiskRule::NewRange => {
+                    for ip in addresses.iter().filter_map(|a| a.parse::<IpAddr>().ok()) {
+                        let range = Self::range(ip).to_string();
+                        let known_family = self
+                            .ranges
+                            .iter()
+                            .any(|known| known.contains(':') == ip.is_ipv6());
+                        if known_family && !self.ranges.contains(&range) {
+                            reasons.push(format!("new range {}", range));
+                        }
+                    }
+                }
+                RiskRule::ChangesPerDay(limit) => {
+                    let recent = self.recent_changes(now_ms);
+                    if recent >= limit as usize {
+                        reasons.push(format!("{} changes in the last 24 hours", recent));
+                    }
+                }
+            }
+        }
+        reasons
+    }
+
+    /// Remember the caller and addresses of an accepted update
+    pub fn learn(&mut self, addresses: &[&str], caller: &Caller) {
+        if let Some(country) = &caller.country {
+            Self::remember(&mut self.countries, country.clone());
+        }
+        if let Some(asn) = caller.asn {
+            Self::remember(&mut self.asns, asn);
+        }
+        for ip in addresses.iter().filter_map(|a| a.parse::<IpAddr>().ok()) {
+            Self::remember(&mut self.ranges, Self::range(ip).to_string());
+        }
+    }
+
+    /// Count a change made at `now_ms`, forgetting those older than a day
+    pub fn record_change(&mut self, now_ms: u64) {
+        self.changes
+            .retain(|&at| now_ms.saturating_sub(at) < DAY_MS);
+        self.changes.push(now_ms);
+    }
+}
+
+/// An address change held for approval, stored under `pending:{homename}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingChange {
+    pub homename: String,
+    /// Requested IPv4 address, empty when none was requested
+    pub ipv4: String,
+    /// Requested IPv6 address, empty when none was requested
+    pub ipv6: String,
+    /// Whether the update may take over records this worker did not create
+    #[serde(default)]
+    pub adopt: bool,
+    #[serde(flatten)]
+    pub caller: Caller,
+    /// Rules the change tripped
+    pub reasons: Vec<String>,
+    /// Unix time the change was requested in milliseconds
+    pub created: u64,
+}
+
+/// Notification sent to `ALERT_WEBHOOK_URL` when a change is held
+#[derive(Debug, Serialize)]
+pub struct HeldAlert<'a> {
+    pub event: &'static str,
+    #[serde(flatten)]
+    pub change: &'a PendingChange,
+}
+
+/// Host profiles and changes waiting for approval
+pub struct Quarantine<'a> {
+    kv: &'a dyn KeyValueStore,
+}
+
+impl<'a> Quarantine<'a> {
+    /// Create a quarantine over the given KV store
+    pub fn new(kv: &'a dyn KeyValueStore) -> Self {
+        Self { kv }
+    }
+
+    fn profile_key(homename: &str) -> String {
+        format!("{}{}", PROFILE_KEY_PREFIX, homename)
+    }
+
+    fn pending_key(homename: &str) -> String {
+        format!("{}{}", PENDING_KEY_PREFIX, homename)
+    }
+
+    /// What the homename was seen doing so far
+    pub async fn profile(&self, homename: &str) -> Result<HostProfile> {
+        Ok(self
+            .kv
+            .get(&Self::profile_key(homename))
+            .await?
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default())
+    }
+
+    /// Remember the caller and addresses of an accepted update
+    ///
+    /// `changed_at` counts the update as a change; the profile is only written
+    /// when it learned something.
+    pub async fn learn(
+        &self,
+        homename: &str,
+        addresses: &[&str],
+        caller: &Caller,
+        changed_at: Option<u64>,
+    ) -> Result<()> {
+        let known = self.profile(homename).await?;
+        let mut profile = known.clone();
+        profile.learn(addresses, caller);
+        if let Some(now_ms) = changed_at {
+            profile.record_change(now_ms);
+        }
+        if profile == known {
+            return Ok(());
+        }
+        let value = serde_json::to_string(&profile)?;
+        self.kv.put(&Self::profile_key(homename), &value).await
+    }
+
+    /// Hold a change, replacing any older one for the same homename
+    ///
+    /// Returns `false` without writing when the same addresses were already
+    /// waiting, so a retrying client costs no KV write and raises no new alert.
+    pub async fn hold(&self, change: &PendingChange) -> Result<bool> {
+        if self.pending(&change.homename).await?.is_some_and(|p| {
+            p.ipv4 == change.ipv4 && p.ipv6 == change.ipv6 && p.adopt == change.adopt
+        }) {
+            return Ok(false);
+        }
+        let value = serde_json::to_string(change)?;
+        self.kv
+            .put_with_ttl(
+                &Self::pending_key(&change.homename),
+                &value,
+                PENDING_TTL_SECS,
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// The change waiting for the homename
+    pub async fn pending(&self, homename: &str) -> Result<Option<PendingChange>> {
+        match self.kv.get(&Self::pending_key(homename)).await? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Every change waiting for approval, oldest first
+    pub async fn list(&self) -> Result<Vec<PendingChange>> {
+        let mut changes = Vec::new();
+        for key in self.kv.list_keys(PENDING_KEY_PREFIX).await? {
+            if let Some(homename) = key.strip_prefix(PENDING_KEY_PREFIX)
+                && let Some(change) = self.pending(homename).await?
+            {
+                changes.push(change);
+            }
+        }
+        changes.sort_by_key(|change| change.created);
+        Ok(changes)
+    }
+
+    /// Drop the change waiting for the homename; returns whether there was one
+    pub async fn discard(&self, homename: &str) -> Result<bool> {
+        let key = Self::pending_key(homename);
+        let pending = self.kv.get(&key).await?.is_some();
+        if pending {
+            self.kv.delete(&key).await?;
+        }
+        Ok(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn caller(country: &str, asn: u32) -> Caller {
+        Caller {
+            client_ip: "198.51.100.7".to_string(),
+            credential: "vpn gateway".to_string(),
+            country: Some(country.to_string()),
+            asn: Some(asn),
+        }
+    }
+
+    #[test]
+    fn rule_parsing() {
+        let test_cases = vec![
+            ("country", Some(RiskRule::NewCountry)),
+            (" ASN ", Some(RiskRule::NewAsn)),
+            ("range", Some(RiskRule::NewRange)),
+            ("changes=5", Some(RiskRule::ChangesPerDay(5))),
+            ("changes=0", None),
+            ("changes=many", None),
+            ("city", None),
+        ];
+
+        for (input, expected) in test_cases {
+            assert_eq!(RiskRule::parse(input), expected, "Failed: {}", input);
+        }
+    }
+
+    #[test]
+    fn policy_applies_to_configured_hosts() {
+        let policy = QuarantinePolicy {
+            hosts: vec!["vpn*".to_string()],
+            rules: vec![RiskRule::NewCountry],
+        };
+        assert!(policy.applies("vpn"));
+        assert!(policy.applies("vpn-backup"));
+        assert!(!policy.applies("home"));
+
+        let no_rules = QuarantinePolicy {
+            rules: Vec::new(),
+            ..policy
+        };
+        assert!(!no_rules.applies("vpn"));
+    }
+
+    #[test]
+    fn risk_assessment() {
+        let policy = QuarantinePolicy {
+            hosts: vec!["*".to_string()],
+            rules: vec![
+                RiskRule::NewCountry,
+                RiskRule::NewAsn,
+                RiskRule::NewRange,
+                RiskRule::ChangesPerDay(3),
+            ],
+        };
+        let mut profile = HostProfile::default();
+        assert!(
+            profile
+                .assess(&policy, &["203.0.113.7"], &caller("DE", 64500), NOW)
+                .is_empty(),
+            "an empty profile trusts the first change"
+        );
+        profile.learn(&["203.0.113.7"], &caller("DE", 64500));
+        profile.record_change(NOW);
+
+        let test_cases = vec![
+            (
+                "203.0.113.99",
+                caller("DE", 64500),
+                vec![],
+                "same network, country and ASN",
+            ),
+            (
+                "198.51.100.1",
+                caller("DE", 64500),
+                vec!["new range 198.51.100.0/24"],
+                "new network",
+            ),
+            (
+                "203.0.113.99",
+                caller("FR", 64501),
+                vec!["new country FR", "new ASN 64501"],
+                "new country and ASN",
+            ),
+            (
+                "2001:db8::1",
+                caller("DE", 64500),
+                vec![],
+                "first network of another family",
+            ),
+        ];
+
+        for (address, caller, expected, description) in test_cases {
+            assert_eq!(
+                profile.assess(&policy, &[address], &caller, NOW + 1000),
+                expected,
+                "Failed: {}",
+                description
+            );
+        }
+
+        profile.record_change(NOW + 1000);
+        profile.record_change(NOW + 2000);
+        assert_eq!(
+            profile.assess(&policy, &["203.0.113.10"], &caller("DE", 64500), NOW + 3000),
+            vec!["3 changes in the last 24 hours"]
+        );
+        assert!(
+            profile
+                .assess(&policy, &[], &caller("FR", 64501), NOW + 3000)
+                .is_empty(),
+            "an update changing nothing is never held"
+        );
+        // Changes older than a day no longer count
+        assert!(
+            profile
+                .assess(
+                    &policy,
+                    &["203.0.113.10"],
+                    &caller("DE", 64500),
+                    NOW + DAY_MS
+                )
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn changes_are_held_until_discarded() {
+        let kv = MemoryStore::new();
+        let quarantine = Quarantine::new(&kv);
+        let change = PendingChange {
+            homename: "vpn".to_string(),
+            ipv4: "198.51.100.1".to_string(),
+            ipv6: String::new(),
+            adopt: false,
+            caller: caller("FR", 64501),
+            reasons: vec!["new country FR".to_string()],
+            created: NOW,
+        };
+
+        assert!(quarantine.hold(&change).await.unwrap());
+        // A retry of the same change is not news and leaves the first one in place
+        let retry = PendingChange {
+            created: NOW + 1000,
+            ..change.clone()
+        };
+        assert!(!quarantine.hold(&retry).await.unwrap());
+        assert_eq!(
+            quarantine.pending("vpn").await.unwrap(),
+            Some(change.clone())
+        );
+        let other = PendingChange {
+            ipv4: "198.51.100.2".to_string(),
+            ..change.clone()
+        };
+        assert!(quarantine.hold(&other).await.unwrap());
+        let adopting = PendingChange {
+            adopt: true,
+            ..other.clone()
+        };
+        assert!(quarantine.hold(&adopting).await.unwrap());
+        assert!(!quarantine.hold(&adopting).await.unwrap());
+
+        assert_eq!(quarantine.list().await.unwrap(), vec![adopting.clone()]);
+        let json = serde_json::to_value(HeldAlert {
+            event: HELD_EVENT,
+            change: &other,
+        })
+        .unwrap();
+        assert_eq!(json["event"], "change_held");
+        assert_eq!(json["country"], "FR");
+        // Changes held before `adopt` was stored still load, without adoption
+        let mut legacy = serde_json::to_value(&change).unwrap();
+        legacy.as_object_mut().unwrap().remove("adopt");
+        assert_eq!(
+            serde_json::from_value::<PendingChange>(legacy).unwrap(),
+            change
+        );
+
+        assert!(quarantine.discard("vpn").await.unwrap());
+        assert!(!quarantine.discard("vpn").await.unwrap());
+        assert_eq!(quarantine.pending("vpn").await.unwrap(), None);
+
+        quarantine
+            .learn("vpn", &["198.51.100.1"], &caller("FR", 64501), Some(NOW))
+            .await
+            .unwrap();
+        quarantine
+            .learn("vpn", &["198.51.100.1"], &caller("FR", 64501), None)
+            .await
+            .unwrap();
+        let profile = quarantine.profile("vpn").await.unwrap();
+        assert_eq!(profile.countries, vec!["FR"]);
+        assert_eq!(profile.ranges, vec!["198.51.100.0/24"]);
+        assert_eq!(profile.changes, vec![NOW]);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::store::KeyValueStore;
+use serde::{Deserialize, Serialize};
+use worker::*;
+
+// Constants
+const RATE_KEY_PREFIX: &str = "rate:";
+
+/// Number of requests allowed per fixed window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub window_secs: u64,
+}
+
+impl RateLimit {
+    /// Parse `requests/seconds`, e.g. `30/60`
+    pub fn parse(value: &str) -> Option<Self> {
+        let (requests, window_secs) = value.trim().split_once('/')?;
+        let requests = requests.trim().parse().ok()?;
+        let window_secs = window_secs.trim().parse().ok().filter(|w| *w > 0)?;
+        Some(Self {
+            requests,
+            window_secs,
+        })
+    }
+}
+
+/// What a rate limit is keyed on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateKind {
+    /// Caller address from `CF-Connecting-IP`
+    Ip,
+    /// Owner of the authenticated credential, see `Credential::rate_key`
+    Token,
+    /// Homename being updated
+    Homename,
+}
+
+impl RateKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateKind::Ip => "ip",
+            RateKind::Token => "token",
+            RateKind::Homename => "homename",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ip" => Some(RateKind::Ip),
+            "token" => Some(RateKind::Token),
+            "homename" => Some(RateKind::Homename),
+            _ => None,
+        }
+    }
+}
+
+/// Configured limits, each one optional
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimits {
+    pub ip: Option<RateLimit>,
+    pub token: Option<RateLimit>,
+    pub homename: Option<RateLimit>,
+}
+
+impl RateLimits {
+    /// Limit applying to the given kind of key
+    pub fn get(&self, kind: RateKind) -> Option<RateLimit> {
+        match kind {
+            RateKind::Ip => self.ip,
+            RateKind::Token => self.token,
+            RateKind::Homename => self.homename,
+        }
+    }
+
+    fn get_mut(&mut self, kind: RateKind) -> &mut Option<RateLimit> {
+        match kind {
+            RateKind::Ip => &mut self.ip,
+            RateKind::Token => &mut self.token,
+            RateKind::Homename => &mut self.homename,
+        }
+    }
+
+    /// Set the limit for a kind of key
+    pub fn set(&mut self, kind: RateKind, limit: RateLimit) {
+        *self.get_mut(kind) = Some(limit);
+    }
+}
+
+/// Request count of the current window, stored under `rate:{kind}:{id}`
+#[derive(Serialize, Deserialize)]
+struct Window {
+    /// Index of the window, Unix seconds divided by the window length
+    window: u64,
+    count: u32,
+}
+
+/// Fixed-window rate limiter backed by KV
+///
+/// KV is eventually consistent, so concurrent requests hitting different
+/// locations may briefly exceed a limit; a runaway client is still stopped
+/// within a few requests.
+pub struct RateLimiter<'a> {
+    kv: &'a dyn KeyValueStore,
+}
+
+impl<'a> RateLimiter<'a> {
+    /// Create a new limiter over the given store
+    pub fn new(kv: &'a dyn KeyValueStore) -> Self {
+        Self { kv }
+    }
+
+    fn key(kind: RateKind, id: &str) -> String {
+        format!("{}{}:{}", RATE_KEY_PREFIX, kind.as_str(), id)
+    }
+
+    /// Count a request; returns the seconds until the window resets when over the limit
+    ///
+    /// Refused requests are not counted, so they cost no KV write.
+    pub async fn hit(
+        &self,
+        kind: RateKind,
+        id: &str,
+        limit: RateLimit,
+        now_ms: u64,
+    ) -> Result<Option<u64>> {
+        let key = Self::key(kind, id);
+        let now = now_ms / 1000;
+        let window = now / limit.window_secs;
+
+        let count = match self.kv.get(&key).await? {
+            Some(value) => serde_json::from_str::<Window>(&value)
+                .ok()
+                .filter(|stored| stored.window == window)
+                .map_or(0, |stored| stored.count),
+            None => 0,
+        };
+        if count >= limit.requests {
+            let reset = (window + 1) * limit.window_secs;
+            return Ok(Some(reset.saturating_sub(now).max(1)));
+        }
+
+        let value = serde_json::to_string(&Window {
+            window,
+            count: count + 1,
+        })?;
+        self.kv
+            .put_with_ttl(&key, &value, limit.window_secs)
+            .await?;
+        Ok(None)
+    }
+
+    /// Count a request against every configured limit among `keys`
+    ///
+    /// Returns the longest wait when any limit is exceeded.
+    pub async fn check(
+        &self,
+        limits: &RateLimits,
+        keys: &[(RateKind, &str)],
+        now_ms: u64,
+    ) -> Result<Option<u64>> {
+        let mut retry_after = None;
+        for &(kind, id) in keys {
+            let Some(limit) = limits.get(kind) else {
+                continue;
+            };
+            if let Some(wait) = self.hit(kind, id, limit, now_ms).await? {
+                retry_after = retry_after.max(Some(wait));
+            }
+        }
+        Ok(retry_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    const START: u64 = 1_700_000_000_000;
+
+    #[test]
+    fn limit_parsing() {
+        let test_cases = vec![
+            ("30/60", Some((30, 60)), "requests per window"),
+            (" 5 / 1 ", Some((5, 1)), "surrounding whitespace"),
+            ("0/60", Some((0, 60)), "zero requests blocks everything"),
+            ("30/0", None, "empty window"),
+            ("30", None, "missing window"),
+            ("x/60", None, "non-numeric requests"),
+        ];
+
+        for (input, expected, description) in test_cases {
+            let expected = expected.map(|(requests, window_secs)| RateLimit {
+                requests,
+                window_secs,
+            });
+            assert_eq!(RateLimit::parse(input), expected, "Failed: {}", description);
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_limit_wait_for_the_next_window() {
+        let kv = MemoryStore::new();
+        let limiter = RateLimiter::new(&kv);
+        let limit = RateLimit::parse("2/60").unwrap();
+        // START lies 20 seconds into a one-minute window
+        let now = START;
+
+        for _ in 0..2 {
+            let wait = limiter.hit(RateKind::Ip, "192.0.2.1", limit, now).await;
+            assert_eq!(wait.unwrap(), None);
+        }
+        let wait = limiter.hit(RateKind::Ip, "192.0.2.1", limit, now + 5_000);
+        assert_eq!(wait.await.unwrap(), Some(35));
+
+        // Other keys and the next window start from zero
+        let wait = limiter.hit(RateKind::Ip, "192.0.2.2", limit, now);
+        assert_eq!(wait.await.unwrap(), None);
+        let wait = limiter.hit(RateKind::Ip, "192.0.2.1", limit, now + 40_000);
+        assert_eq!(wait.await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn check_applies_configured_limits_only() {
+        let kv = MemoryStore::new();
+        let limiter = RateLimiter::new(&kv);
+        let mut limits = RateLimits::default();
+        limits.set(RateKind::Token, RateLimit::parse("1/60").unwrap());
+        limits.set(RateKind::Homename, RateLimit::parse("1/3600").unwrap());
+        let keys = [
+            (RateKind::Ip, "192.0.2.1"),
+            (RateKind::Token, "token:3f9c1a2b4d5e6f70"),
+            (RateKind::Homename, "home"),
+        ];
+
+        assert_eq!(limiter.check(&limits, &keys, START).await.unwrap(), None);
+        assert_eq!(
+            limiter.check(&limits, &keys, START).await.unwrap(),
+            Some(2800)
+        );
+        assert!(kv.get("rate:ip:192.0.2.1").await.unwrap().is_none());
+    }
+}
@@ -1,9 +1,12 @@
+use crate::config::Config;
 use worker::*;
 
 // Constants
 const HEADER_CF_CONNECTING_IP: &str = "CF-Connecting-IP";
 const HEADER_ACCEPT: &str = "Accept";
 const PARAM_HOMENAME: &str = "homename";
+const PARAM_STRICT: &str = "strict";
+const PARAM_ADOPT: &str = "adopt";
 
 /// Supported response formats
 #[derive(Debug, PartialEq)]
@@ -21,6 +24,10 @@ pub struct RequestContext {
     pub client_ip: String,
     /// Desired response format (text, JSON, or XML)
     pub format: Format,
+    /// Whether a failed DNS update should fail the request
+    pub strict: bool,
+    /// Whether existing records this worker did not create may be taken over
+    pub adopt: bool,
 }
 
 impl RequestContext {
@@ -30,20 +37,31 @@ impl RequestContext {
         let homename = Self::extract_homename(&url)?;
         let client_ip = Self::extract_client_ip(req)?;
         let format = Self::detect_format(req);
+        let flag = |name: &str| {
+            url.query_pairs()
+                .any(|(k, v)| k == name && Config::parse_flag(&v))
+        };
+        let strict = flag(PARAM_STRICT);
+        let adopt = flag(PARAM_ADOPT);
 
         Ok(Self {
             homename,
             client_ip,
             format,
+            strict,
+            adopt,
         })
     }
 
-    /// Extract and validate homename from URL query parameters
+    /// Extract, validate and lowercase homename from URL query parameters
+    ///
+    /// DNS names are case-insensitive, so claims, profiles and record state are
+    /// all keyed by the lowercase form.
     fn extract_homename(url: &Url) -> Result<String> {
         let homename = url
             .query_pairs()
             .find(|(k, _)| k == PARAM_HOMENAME)
-            .map(|(_, v)| v.to_string())
+            .map(|(_, v)| v.to_ascii_lowercase())
             .ok_or_else(|| Error::RustError("homename parameter required".to_string()))?;
 
         if !Self::is_valid_homename(&homename) {
@@ -54,7 +72,7 @@ impl RequestContext {
     }
 
     /// Extract client IP from Cloudflare headers
-    fn extract_client_ip(req: &Request) -> Result<String> {
+    pub(crate) fn extract_client_ip(req: &Request) -> Result<String> {
         Ok(req
             .headers()
             .get(HEADER_CF_CONNECTING_IP)?
@@ -62,7 +80,7 @@ impl RequestContext {
     }
 
     /// Detects the desired response format from the request
-    fn detect_format(req: &Request) -> Format {
+    pub(crate) fn detect_format(req: &Request) -> Format {
         let accept_header = req.headers().get(HEADER_ACCEPT).ok().flatten();
         Self::detect_format_from_accept(accept_header.as_deref())
     }
@@ -93,6 +111,87 @@ impl RequestContext {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::auth::Scope;
+    use crate::claims::ClaimStore;
+    use crate::quarantine::{Caller, Quarantine, QuarantinePolicy, RiskRule};
+    use crate::store::MemoryStore;
+    use crate::tokens::Credential;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn homename(query: &str) -> Result<String> {
+        RequestContext::extract_homename(&Url::parse(&format!("https://w.example/?{}", query))?)
+    }
+
+    fn credential(owner: &str) -> Credential {
+        Credential {
+            name: owner.to_string(),
+            owner: Some(owner.to_string()),
+            hosts: vec!["*".to_string()],
+            scopes: Scope::defaults(),
+        }
+    }
+
+    #[test]
+    fn homename_is_lowercased() {
+        assert_eq!(homename("homename=HoMe").unwrap(), "home");
+        assert_eq!(homename("homename=home.Lab").unwrap(), "home.lab");
+        assert!(homename("homename=").is_err());
+        assert!(homename("other=home").is_err());
+    }
+
+    #[tokio::test]
+    async fn mixed_case_homename_is_refused_once_claimed() {
+        let kv = MemoryStore::new();
+        let claims = ClaimStore::new(&kv);
+        let owner = credential("token:owner");
+        assert_eq!(
+            claims
+                .claim(&homename("homename=home").unwrap(), &owner, NOW)
+                .await
+                .unwrap(),
+            None
+        );
+
+        let other = credential("token:other");
+        let refused = claims
+            .claim(&homename("homename=HOME").unwrap(), &other, NOW)
+            .await
+            .unwrap();
+        assert_eq!(
+            refused.map(|claim| claim.owner).as_deref(),
+            Some("token:owner")
+        );
+        assert_eq!(kv.keys(), vec!["claim:home"]);
+    }
+
+    #[tokio::test]
+    async fn mixed_case_homename_is_screened_by_quarantine() {
+        let kv = MemoryStore::new();
+        let quarantine = Quarantine::new(&kv);
+        let caller = |country: &str| Caller {
+            country: Some(country.to_string()),
+            ..Caller::default()
+        };
+        quarantine
+            .learn("vpn", &["203.0.113.7"], &caller("DE"), Some(NOW))
+            .await
+            .unwrap();
+
+        let policy = QuarantinePolicy {
+            hosts: vec!["vpn".to_string()],
+            rules: vec![RiskRule::NewCountry],
+        };
+        let homename = homename("homename=VPN").unwrap();
+        assert!(policy.applies(&homename));
+        let reasons = quarantine.profile(&homename).await.unwrap().assess(
+            &policy,
+            &["198.51.100.9"],
+            &caller("US"),
+            NOW,
+        );
+        assert_eq!(reasons, vec!["new country US"]);
+    }
 
     #[test]
     fn detect_format_test_cases() {
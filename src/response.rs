@@ -1,3 +1,4 @@
+use crate::dns::DnsUpdateOutcome;
 use crate::request::Format;
 use serde::Serialize;
 use worker::*;
@@ -11,6 +12,14 @@ const CONTENT_TYPE_XML: &str = "application/xml";
 pub struct IpPayload {
     pub ipv4: String,
     pub ipv6: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns: Option<DnsUpdateOutcome>,
+}
+
+/// Error body returned in the JSON format
+#[derive(Serialize)]
+pub struct ErrorPayload {
+    pub error: String,
 }
 
 /// Response formatting utilities
@@ -18,26 +27,108 @@ pub struct ResponseUtils;
 
 impl ResponseUtils {
     /// Creates a response in the specified format
-    pub async fn create_response(format: Format, ipv4: String, ipv6: String) -> Result<Response> {
+    pub async fn create_response(
+        format: Format,
+        ipv4: String,
+        ipv6: String,
+        dns: Option<DnsUpdateOutcome>,
+    ) -> Result<Response> {
         match format {
-            Format::Text => Response::ok(Self::format_text(&ipv4, &ipv6)),
-            Format::Json => Response::from_json(&IpPayload { ipv4, ipv6 }),
-            Format::Xml => Self::create_xml_response(&ipv4, &ipv6),
+            Format::Text => {
+                let mut body = Self::format_text(&ipv4, &ipv6);
+                if let Some(dns) = &dns {
+                    body.push_str(&Self::format_dns_text(dns));
+                }
+                Response::ok(body)
+            }
+            Format::Json => Response::from_json(&IpPayload { ipv4, ipv6, dns }),
+            Format::Xml => Self::create_xml_response(&ipv4, &ipv6, dns.as_ref()),
         }
     }
 
+    /// Creates an error response with the given status in the specified format
+    pub fn create_error(format: Format, message: &str, status: u16) -> Result<Response> {
+        let resp = match format {
+            Format::Text => Response::ok(format!("{}\n", message))?,
+            Format::Json => Response::from_json(&ErrorPayload {
+                error: message.to_string(),
+            })?,
+            Format::Xml => {
+                let mut resp = Response::ok(Self::format_error_xml(message))?;
+                resp.headers_mut()
+                    .set(HEADER_CONTENT_TYPE, CONTENT_TYPE_XML)?;
+                resp
+            }
+        };
+        Ok(resp.with_status(status))
+    }
+
+    /// Formats an error message as an XML document
+    pub fn format_error_xml(message: &str) -> String {
+        format!("<error>{}</error>", Self::escape_xml(message))
+    }
+
     /// Formats IP addresses as plain text
     pub fn format_text(ipv4: &str, ipv6: &str) -> String {
         format!("{}\n{}\n", ipv4, ipv6)
     }
 
+    /// Formats the DNS update outcome as plain text, one record per line
+    pub fn format_dns_text(dns: &DnsUpdateOutcome) -> String {
+        dns.records
+            .iter()
+            .map(|record| match &record.reason {
+                Some(reason) => format!(
+                    "{} {}: {}\n",
+                    record.record_type.as_str(),
+                    record.status.as_str(),
+                    reason
+                ),
+                None => format!(
+                    "{} {}\n",
+                    record.record_type.as_str(),
+                    record.status.as_str()
+                ),
+            })
+            .collect()
+    }
+
+    /// Formats the DNS update outcome as an XML fragment
+    pub fn format_dns_xml(dns: &DnsUpdateOutcome) -> String {
+        let records: String = dns
+            .records
+            .iter()
+            .map(|record| {
+                let mut attrs = format!(
+                    " type=\"{}\" status=\"{}\"",
+                    record.record_type.as_str(),
+                    record.status.as_str()
+                );
+                if let Some(id) = &record.record_id {
+                    attrs.push_str(&format!(" id=\"{}\"", Self::escape_xml(id)));
+                }
+                if let Some(previous_ip) = &record.previous_ip {
+                    attrs.push_str(&format!(" previous=\"{}\"", Self::escape_xml(previous_ip)));
+                }
+                let reason = record.reason.as_deref().unwrap_or_default();
+                format!("<record{}>{}</record>", attrs, Self::escape_xml(reason))
+            })
+            .collect();
+        format!("<dns>{}</dns>", records)
+    }
+
     /// Creates an XML response with proper escaping
-    fn create_xml_response(ipv4: &str, ipv6: &str) -> Result<Response> {
+    fn create_xml_response(
+        ipv4: &str,
+        ipv6: &str,
+        dns: Option<&DnsUpdateOutcome>,
+    ) -> Result<Response> {
         let ipv4_escaped = Self::escape_xml(ipv4);
         let ipv6_escaped = Self::escape_xml(ipv6);
+        let dns_xml = dns.map(Self::format_dns_xml).unwrap_or_default();
         let body = format!(
-            "<ip><ipv4>{}</ipv4><ipv6>{}</ipv6></ip>",
-            ipv4_escaped, ipv6_escaped
+            "<ip><ipv4>{}</ipv4><ipv6>{}</ipv6>{}</ip>",
+            ipv4_escaped, ipv6_escaped, dns_xml
         );
         let mut resp = Response::ok(body)?;
         resp.headers_mut()
@@ -96,6 +187,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn error_formatting() {
+        assert_eq!(
+            ResponseUtils::format_error_xml("caller <unknown> is not in an allowed range"),
+            "<error>caller &lt;unknown&gt; is not in an allowed range</error>"
+        );
+        let json = serde_json::to_string(&ErrorPayload {
+            error: "Forbidden".to_string(),
+        })
+        .unwrap();
+        assert_eq!(json, r#"{"error":"Forbidden"}"#);
+    }
+
     #[test]
     fn xml_response_formatting() {
         let test_cases = vec![
@@ -144,6 +248,7 @@ mod tests {
             let payload = IpPayload {
                 ipv4: ipv4.to_string(),
                 ipv6: ipv6.to_string(),
+                dns: None,
             };
             let json = serde_json::to_string(&payload).unwrap();
             assert!(
@@ -158,4 +263,45 @@ mod tests {
             );
         }
     }
+
+    fn sample_outcome() -> DnsUpdateOutcome {
+        let json = serde_json::json!({
+            "records": [
+                {"type": "A", "status": "updated", "record_id": "rec1", "previous_ip": "1.1.1.1"},
+                {"type": "AAAA", "status": "error", "reason": "DNS API error: <boom>"}
+            ]
+        });
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn dns_outcome_text_formatting() {
+        assert_eq!(
+            ResponseUtils::format_dns_text(&sample_outcome()),
+            "A updated\nAAAA error: DNS API error: <boom>\n"
+        );
+    }
+
+    #[test]
+    fn dns_outcome_xml_formatting() {
+        assert_eq!(
+            ResponseUtils::format_dns_xml(&sample_outcome()),
+            "<dns><record type=\"A\" status=\"updated\" id=\"rec1\" previous=\"1.1.1.1\"></record>\
+             <record type=\"AAAA\" status=\"error\">DNS API error: &lt;boom&gt;</record></dns>"
+        );
+    }
+
+    #[test]
+    fn dns_outcome_json_serialization() {
+        let payload = IpPayload {
+            ipv4: "1.2.3.4".to_string(),
+            ipv6: String::new(),
+            dns: Some(sample_outcome()),
+        };
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["dns"]["records"][0]["status"], "updated");
+        assert_eq!(json["dns"]["records"][0]["previous_ip"], "1.1.1.1");
+        assert_eq!(json["dns"]["records"][1]["type"], "AAAA");
+        assert!(json["dns"]["records"][1].get("record_id").is_none());
+    }
 }
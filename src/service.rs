@@ -1,29 +1,431 @@
-use crate::config::Config;
-use crate::dns::DnsManager;
+use crate::config::{Config, ProviderKind};
+use crate::dns::{
+    CloudflareProvider, DnsManager, DnsProvider, DnsUpdateOutcome, PowerDnsProvider, RecordType,
+    Rfc2136Provider, Route53Provider, TsigKey, WorkerTcpTransport,
+};
+use crate::heartbeat::{HeartbeatAlert, HeartbeatMonitor, HostStatus};
+use crate::notify::{ChangeEvent, NotifyUtils};
+use crate::quarantine::{Caller, HELD_EVENT, HeldAlert, PendingChange, Quarantine};
+use std::fmt;
 use worker::*;
 
+/// Result of a scheduled reconciliation run
+pub struct ReconcileSummary {
+    /// Outcome per managed homename
+    pub hosts: Vec<(String, DnsUpdateOutcome)>,
+    /// KV keys removed because no managed homename owned them
+    pub orphaned_keys: Vec<String>,
+}
+
+impl ReconcileSummary {
+    /// Homenames where at least one record was rewritten
+    pub fn repaired(&self) -> usize {
+        self.hosts.iter().filter(|(_, o)| o.changed()).count()
+    }
+
+    /// Homenames where at least one record could not be checked or repaired
+    pub fn failed(&self) -> usize {
+        self.hosts.iter().filter(|(_, o)| o.has_errors()).count()
+    }
+}
+
+impl fmt::Display for ReconcileSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reconciled {} hosts: {} repaired, {} failed, {} orphaned keys removed",
+            self.hosts.len(),
+            self.repaired(),
+            self.failed(),
+            self.orphaned_keys.len()
+        )
+    }
+}
+
 /// DNS update service
 pub struct DnsUpdateService;
 
 impl DnsUpdateService {
     /// Checks KV for stored IP and updates DNS if necessary
+    ///
+    /// Existing records this worker did not create are only taken over with `adopt`.
     pub async fn maybe_update_dns(
         homename: &str,
         ipv4: &str,
         ipv6: &str,
+        adopt: bool,
         env: &Env,
+        ctx: &Context,
         config: &Config,
-    ) -> Result<()> {
+    ) -> Result<DnsUpdateOutcome> {
         let kv = env.kv("IP_STORE")?;
-        let dns_manager =
-            DnsManager::new(config.cf_zone_id.clone(), config.cf_api_token.clone(), &kv);
+        let provider = Self::provider(homename, config)?;
+        let dns_manager = Self::manager(provider.as_ref(), &kv, config).with_adoption(adopt);
 
         // Construct the full DNS record name
-        let record_name = format!("{}.{}", homename, config.cf_domain);
+        let record_name = Self::record_name(homename, config);
 
         // Use the DNS manager to handle all DNS operations
-        dns_manager
+        let outcome = dns_manager
             .maybe_update_dns(homename, &record_name, ipv4, ipv6)
+            .await;
+
+        let contents = [(RecordType::A, ipv4), (RecordType::AAAA, ipv6)];
+        Self::notify_changes(homename, &record_name, &outcome, &contents, ctx, config);
+        Ok(outcome)
+    }
+
+    /// Updates DNS like `maybe_update_dns`, holding risky changes of quarantined homenames
+    ///
+    /// A held change is stored for an admin to approve and reported as pending.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn screened_update(
+        homename: &str,
+        ipv4: &str,
+        ipv6: &str,
+        caller: &Caller,
+        adopt: bool,
+        env: &Env,
+        ctx: &Context,
+        config: &Config,
+    ) -> Result<DnsUpdateOutcome> {
+        if !config.quarantine.applies(homename) {
+            return Self::maybe_update_dns(homename, ipv4, ipv6, adopt, env, ctx, config).await;
+        }
+
+        let kv = env.kv("IP_STORE")?;
+        let provider = Self::provider(homename, config)?;
+        let dns_manager = Self::manager(provider.as_ref(), &kv, config);
+        let mut changed = Vec::new();
+        for (record_type, content) in [(RecordType::A, ipv4), (RecordType::AAAA, ipv6)] {
+            if !content.is_empty()
+                && dns_manager
+                    .stored_ip(homename, record_type)
+                    .await?
+                    .as_deref()
+                    != Some(content)
+            {
+                changed.push(content);
+            }
+        }
+
+        let now_ms = Date::now().as_millis();
+        let quarantine = Quarantine::new(&kv);
+        let profile = quarantine.profile(homename).await?;
+        let reasons = profile.assess(&config.quarantine, &changed, caller, now_ms);
+        if reasons.is_empty() {
+            let outcome =
+                Self::maybe_update_dns(homename, ipv4, ipv6, adopt, env, ctx, config).await?;
+            let addresses: Vec<&str> = [ipv4, ipv6].into_iter().filter(|a| !a.is_empty()).collect();
+            let changed_at = outcome.changed().then_some(now_ms);
+            quarantine
+                .learn(homename, &addresses, caller, changed_at)
+                .await?;
+            // A change held earlier is stale now; approving it would undo this update
+            if outcome.clone().into_result().is_ok() {
+                quarantine.discard(homename).await?;
+            }
+            return Ok(outcome);
+        }
+
+        let change = PendingChange {
+            homename: homename.to_string(),
+            ipv4: ipv4.to_string(),
+            ipv6: ipv6.to_string(),
+            adopt,
+            caller: caller.clone(),
+            reasons,
+            created: now_ms,
+        };
+        console_log!(
+            "Held change of {} by {} from {}: {}",
+            change.homename,
+            change.caller.credential,
+            change.caller.client_ip,
+            change.reasons.join(", ")
+        );
+        if quarantine.hold(&change).await? {
+            Self::notify_held(&change, ctx, config);
+        }
+        let reason = format!("held for approval: {}", change.reasons.join(", "));
+        let contents = [(RecordType::A, ipv4), (RecordType::AAAA, ipv6)];
+        Ok(DnsUpdateOutcome::held(&contents, &reason))
+    }
+
+    /// Applies the change held for a homename
+    ///
+    /// The change stays pending when it could not be written. Returns `None`
+    /// when nothing was waiting.
+    pub async fn approve_change(
+        homename: &str,
+        env: &Env,
+        ctx: &Context,
+        config: &Config,
+    ) -> Result<Option<DnsUpdateOutcome>> {
+        let kv = env.kv("IP_STORE")?;
+        let quarantine = Quarantine::new(&kv);
+        let Some(change) = quarantine.pending(homename).await? else {
+            return Ok(None);
+        };
+
+        let outcome = Self::maybe_update_dns(
+            homename,
+            &change.ipv4,
+            &change.ipv6,
+            change.adopt,
+            env,
+            ctx,
+            config,
+        )
+        .await?;
+        if outcome.clone().into_result().is_ok() {
+            quarantine.discard(homename).await?;
+            let addresses: Vec<&str> = [change.ipv4.as_str(), change.ipv6.as_str()]
+                .into_iter()
+                .filter(|a| !a.is_empty())
+                .collect();
+            let changed_at = outcome.changed().then(|| Date::now().as_millis());
+            quarantine
+                .learn(homename, &addresses, &change.caller, changed_at)
+                .await?;
+        }
+        Ok(Some(outcome))
+    }
+
+    /// Tells the alert webhook that a change waits for approval, after the response is sent
+    fn notify_held(change: &PendingChange, ctx: &Context, config: &Config) {
+        let Some(url) = config.alert_webhook_url.clone() else {
+            return;
+        };
+        let change = change.clone();
+        ctx.wait_until(async move {
+            let alert = HeldAlert {
+                event: HELD_EVENT,
+                change: &change,
+            };
+            if let Err(e) = NotifyUtils::post_json(&url, &alert).await {
+                console_log!("Alert delivery failed for {}: {}", change.homename, e);
+            }
+        });
+    }
+
+    /// Sets the TXT record for a hostname
+    pub async fn maybe_update_txt(
+        homename: &str,
+        content: &str,
+        env: &Env,
+        ctx: &Context,
+        config: &Config,
+    ) -> Result<DnsUpdateOutcome> {
+        let kv = env.kv("IP_STORE")?;
+        let provider = Self::provider(homename, config)?;
+        let dns_manager = Self::manager(provider.as_ref(), &kv, config);
+        let record_name = Self::record_name(homename, config);
+
+        let outcome = dns_manager
+            .maybe_update_txt(homename, &record_name, content)
+            .await;
+
+        let contents = [(RecordType::TXT, content)];
+        Self::notify_changes(homename, &record_name, &outcome, &contents, ctx, config);
+        Ok(outcome)
+    }
+
+    /// Tells the configured webhooks about every record the update wrote
+    ///
+    /// Delivery, retries included, runs after the response is sent, so a slow
+    /// or dead receiver never delays the update.
+    fn notify_changes(
+        homename: &str,
+        record_name: &str,
+        outcome: &DnsUpdateOutcome,
+        contents: &[(RecordType, &str)],
+        ctx: &Context,
+        config: &Config,
+    ) {
+        if config.webhooks.is_empty() {
+            return;
+        }
+
+        let events = ChangeEvent::from_outcome(
+            homename,
+            record_name,
+            outcome,
+            contents,
+            Date::now().as_millis(),
+        );
+        if events.is_empty() {
+            return;
+        }
+        let config = config.clone();
+        ctx.wait_until(async move {
+            for failure in NotifyUtils::dispatch(&events, &config).await {
+                console_log!("Webhook delivery failed for {}", failure);
+            }
+        });
+    }
+
+    /// Deletes the given record types for a hostname
+    ///
+    /// Returns `true` when at least one record was deleted.
+    pub async fn clear_records(
+        homename: &str,
+        record_types: &[RecordType],
+        env: &Env,
+        config: &Config,
+    ) -> Result<bool> {
+        let kv = env.kv("IP_STORE")?;
+        let provider = Self::provider(homename, config)?;
+        let dns_manager = Self::manager(provider.as_ref(), &kv, config);
+        let record_name = Self::record_name(homename, config);
+
+        Ok(dns_manager
+            .clear_records(homename, &record_name, record_types)
+            .await?)
+    }
+
+    /// Repairs drift between KV state and live DNS for every managed homename
+    pub async fn reconcile_all(env: &Env, config: &Config) -> Result<ReconcileSummary> {
+        let kv = env.kv("IP_STORE")?;
+        let backfilled = DnsManager::backfill_managed_index(&kv).await?;
+        if backfilled > 0 {
+            console_log!(
+                "Indexed {} homenames cached before the managed index",
+                backfilled
+            );
+        }
+        let mut hosts = Vec::new();
+        let mut orphaned_keys = Vec::new();
+
+        for homename in DnsManager::managed_homenames(&kv).await? {
+            if let Some(removed) = DnsManager::remove_if_orphaned(&kv, &homename).await? {
+                orphaned_keys.extend(removed);
+                continue;
+            }
+            let outcome = match Self::provider(&homename, config) {
+                Ok(provider) => {
+                    let record_name = Self::record_name(&homename, config);
+                    Self::manager(provider.as_ref(), &kv, config)
+                        .reconcile(&homename, &record_name)
+                        .await
+                }
+                Err(e) => {
+                    DnsUpdateOutcome::failed(&[RecordType::A, RecordType::AAAA], &e.to_string())
+                }
+            };
+            hosts.push((homename, outcome));
+        }
+
+        Ok(ReconcileSummary {
+            hosts,
+            orphaned_keys,
+        })
+    }
+
+    /// Records that a homename checked in from the given addresses
+    ///
+    /// Homenames without a heartbeat threshold are not monitored and cost no KV access.
+    pub async fn record_seen(
+        homename: &str,
+        ipv4: &str,
+        ipv6: &str,
+        env: &Env,
+        config: &Config,
+    ) -> Result<()> {
+        let Some(threshold) = config.heartbeat_threshold(homename) else {
+            return Ok(());
+        };
+        let kv = env.kv("IP_STORE")?;
+        HeartbeatMonitor::new(&kv)
+            .record_seen(homename, ipv4, ipv6, threshold, Date::now().as_millis())
+            .await
+    }
+
+    /// Liveness of every homename that has checked in
+    pub async fn host_statuses(env: &Env, config: &Config) -> Result<Vec<HostStatus>> {
+        let kv = env.kv("IP_STORE")?;
+        HeartbeatMonitor::new(&kv)
+            .statuses(config, Date::now().as_millis())
+            .await
+    }
+
+    /// Flags homenames that went silent or recovered since the last check
+    pub async fn check_heartbeats(env: &Env, config: &Config) -> Result<Vec<HeartbeatAlert>> {
+        let kv = env.kv("IP_STORE")?;
+        HeartbeatMonitor::new(&kv)
+            .check(config, Date::now().as_millis())
             .await
     }
+
+    /// Builds a DNS manager honouring the configured revalidation and write intervals
+    fn manager<'a>(
+        provider: &'a dyn DnsProvider,
+        kv: &'a kv::KvStore,
+        config: &Config,
+    ) -> DnsManager<'a> {
+        let now_ms = Date::now().as_millis();
+        let manager = DnsManager::new(provider, kv);
+        let manager = match config.dns_revalidate_interval {
+            0 => manager,
+            interval => manager.with_revalidation(interval, now_ms),
+        };
+        match config.dns_write_interval {
+            0 => manager,
+            interval => manager.with_write_interval(interval, now_ms),
+        }
+    }
+
+    /// Builds the DNS provider serving the given homename
+    fn provider(homename: &str, config: &Config) -> Result<Box<dyn DnsProvider>> {
+        match config.provider_for(homename) {
+            ProviderKind::Cloudflare => Ok(Box::new(CloudflareProvider::new(
+                config.cf_zone_id.clone(),
+                config.cf_api_token.clone(),
+                config.dns_ttl,
+            ))),
+            ProviderKind::PowerDns => {
+                let pdns = config.powerdns.as_ref().ok_or_else(|| {
+                    Error::RustError("PowerDNS provider is not configured".to_string())
+                })?;
+                Ok(Box::new(PowerDnsProvider::new(
+                    pdns.api_url.clone(),
+                    pdns.api_key.clone(),
+                    pdns.server_id.clone(),
+                    pdns.zone.clone(),
+                    config.dns_ttl,
+                )))
+            }
+            ProviderKind::Route53 => {
+                let r53 = config.route53.as_ref().ok_or_else(|| {
+                    Error::RustError("Route 53 provider is not configured".to_string())
+                })?;
+                Ok(Box::new(Route53Provider::new(
+                    r53.api_url.clone(),
+                    r53.access_key_id.clone(),
+                    r53.secret_access_key.clone(),
+                    r53.region.clone(),
+                    r53.hosted_zone_id.clone(),
+                    config.dns_ttl,
+                )))
+            }
+            ProviderKind::Rfc2136 => {
+                let rfc2136 = config.rfc2136.as_ref().ok_or_else(|| {
+                    Error::RustError("RFC 2136 provider is not configured".to_string())
+                })?;
+                let key = TsigKey::from_base64(&rfc2136.key_name, &rfc2136.key_secret)?;
+                Ok(Box::new(Rfc2136Provider::new(
+                    Box::new(WorkerTcpTransport::new(rfc2136.host.clone(), rfc2136.port)),
+                    rfc2136.zone.clone(),
+                    key,
+                    config.dns_ttl,
+                )))
+            }
+        }
+    }
+
+    /// Builds the full DNS record name for a homename
+    fn record_name(homename: &str, config: &Config) -> String {
+        let domain = config.domain_for(config.provider_for(homename));
+        format!("{}.{}", homename, domain)
+    }
 }
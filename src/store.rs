@@ -0,0 +1,122 @@
+use async_trait::async_trait;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use worker::*;
+
+/// Shortest expiration Workers KV accepts
+const KV_MIN_TTL_SECS: u64 = 60;
+
+/// Minimal key-value storage used for IP and DNS record state
+///
+/// Implemented for the Workers KV binding and for an in-memory map used in tests.
+#[async_trait(?Send)]
+pub trait KeyValueStore {
+    /// Read a value, returning `None` if the key does not exist
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+    /// Write a value, replacing any previous one
+    async fn put(&self, key: &str, value: &str) -> Result<()>;
+    /// Write a value that the store drops after `ttl_secs` seconds
+    async fn put_with_ttl(&self, key: &str, value: &str, ttl_secs: u64) -> Result<()>;
+    /// Remove a key; removing a missing key is not an error
+    async fn delete(&self, key: &str) -> Result<()>;
+    /// Names of all keys starting with `prefix`
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+#[async_trait(?Send)]
+impl KeyValueStore for kv::KvStore {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(kv::KvStore::get(self, key).text().await?)
+    }
+
+    async fn put(&self, key: &str, value: &str) -> Result<()> {
+        kv::KvStore::put(self, key, value)?.execute().await?;
+        Ok(())
+    }
+
+    async fn put_with_ttl(&self, key: &str, value: &str, ttl_secs: u64) -> Result<()> {
+        // KV rejects expirations less than a minute away
+        kv::KvStore::put(self, key, value)?
+            .expiration_ttl(ttl_secs.max(KV_MIN_TTL_SECS))
+            .execute()
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        kv::KvStore::delete(self, key).await?;
+        Ok(())
+    }
+
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut cursor = None;
+
+        // KV returns at most 1000 keys per call, follow the cursor for the rest
+        loop {
+            let mut list = self.list().prefix(prefix.to_string());
+            if let Some(cursor) = cursor {
+                list = list.cursor(cursor);
+            }
+            let response = list.execute().await?;
+            keys.extend(response.keys.into_iter().map(|key| key.name));
+
+            match response.cursor {
+                Some(next) if !response.list_complete => cursor = Some(next),
+                _ => return Ok(keys),
+            }
+        }
+    }
+}
+
+/// In-memory key-value store
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: RefCell<BTreeMap<String, String>>,
+}
+
+impl MemoryStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of all keys currently stored
+    pub fn keys(&self) -> Vec<String> {
+        self.entries.borrow().keys().cloned().collect()
+    }
+}
+
+#[async_trait(?Send)]
+impl KeyValueStore for MemoryStore {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.entries.borrow().get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, value: &str) -> Result<()> {
+        self.entries
+            .borrow_mut()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Expiry is not simulated; entries live as long as the store
+    async fn put_with_ttl(&self, key: &str, value: &str, _ttl_secs: u64) -> Result<()> {
+        self.put(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.entries.borrow_mut().remove(key);
+        Ok(())
+    }
+
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(self
+            .entries
+            .borrow()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
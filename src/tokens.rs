@@ -0,0 +1,468 @@
+use crate::auth::{AuthUtils, Scope};
+use crate::config::Config;
+use crate::store::KeyValueStore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use worker::*;
+
+// Constants
+pub const ADMIN_TOKENS_PATH: &str = "/admin/tokens";
+const TOKEN_KEY_PREFIX: &str = "token:";
+const TOKEN_SEPARATOR: char = '.';
+/// Name reported for the legacy `API_TOKEN`
+const ADMIN_CREDENTIAL_NAME: &str = "API_TOKEN";
+/// Rate limit key shared by every credential acting with an admin's authority
+const ADMIN_RATE_KEY: &str = "admin";
+const TOKEN_ID_BYTES: usize = 8;
+const TOKEN_SECRET_BYTES: usize = 24;
+const SALT_BYTES: usize = 16;
+/// KV allows one write per key per second, so last use is only refreshed this often
+const LAST_USED_RESOLUTION_MS: u64 = 60_000;
+
+/// A named token stored in KV under `token:{id}`
+///
+/// Clients present `{id}.{secret}`; only a salted SHA-256 of the secret is stored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenRecord {
+    /// Human readable name, e.g. the device holding the token
+    pub name: String,
+    /// Hex salt mixed into the hash, empty for tokens created by hand
+    #[serde(default)]
+    pub salt: String,
+    /// Hex SHA-256 of the salt followed by the token secret
+    pub hash: String,
+    /// Homenames or glob patterns the token may update
+    pub hosts: Vec<String>,
+    /// What the token may do, `ip:read` and `dns:update` when absent
+    #[serde(default = "Scope::defaults")]
+    pub scopes: Vec<Scope>,
+    /// Unix time of creation in milliseconds
+    #[serde(default)]
+    pub created: u64,
+    /// Unix time of the last successful use in milliseconds
+    #[serde(default)]
+    pub last_used: Option<u64>,
+    /// Unix time in milliseconds after which the token is rejected
+    #[serde(default)]
+    pub expires: Option<u64>,
+}
+
+/// Token metadata as returned by the admin API, without the hash
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub scopes: Vec<Scope>,
+    pub created: u64,
+    pub last_used: Option<u64>,
+    pub expires: Option<u64>,
+}
+
+impl TokenInfo {
+    fn new(id: &str, record: TokenRecord) -> Self {
+        Self {
+            id: id.to_string(),
+            name: record.name,
+            hosts: record.hosts,
+            scopes: record.scopes,
+            created: record.created,
+            last_used: record.last_used,
+            expires: record.expires,
+        }
+    }
+}
+
+/// Body of a token creation request
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default = "Scope::defaults")]
+    pub scopes: Vec<Scope>,
+    /// Lifetime in seconds, unlimited when absent
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+}
+
+/// A freshly created token; the plaintext is never available again
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedToken {
+    /// Value clients send as `Bearer` token
+    pub token: String,
+    #[serde(flatten)]
+    pub info: TokenInfo,
+}
+
+/// The identity behind an authenticated request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credential {
+    pub name: String,
+    /// Stable identity homenames are claimed for, e.g. `token:{id}`
+    ///
+    /// `None` when acting with an admin's authority, as `API_TOKEN` and signed URLs do.
+    pub owner: Option<String>,
+    /// Homenames or glob patterns the credential may update
+    pub hosts: Vec<String>,
+    pub scopes: Vec<Scope>,
+}
+
+impl Credential {
+    /// The legacy `API_TOKEN`, allowed to update every homename
+    pub fn admin() -> Self {
+        Self {
+            name: ADMIN_CREDENTIAL_NAME.to_string(),
+            owner: None,
+            hosts: vec!["*".to_string()],
+            scopes: vec![Scope::Admin],
+        }
+    }
+
+    /// Whether the credential was granted the scope; `admin` implies every scope
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin)
+    }
+
+    /// Whether the credential may update the given homename
+    pub fn allows(&self, homename: &str) -> bool {
+        self.hosts
+            .iter()
+            .any(|pattern| Config::glob_match(pattern, homename))
+    }
+
+    /// Identity the per-token rate limit is counted against
+    ///
+    /// Names are chosen freely and need not be unique, so the stable owner is
+    /// used; credentials without one share the admin bucket.
+    pub fn rate_key(&self) -> &str {
+        self.owner.as_deref().unwrap_or(ADMIN_RATE_KEY)
+    }
+}
+
+/// Record a use of a stored credential, returning whether the record must be written back
+///
+/// Shared by named tokens and device keys so both refresh at the same resolution.
+pub(crate) fn touch_last_used(last_used: &mut Option<u64>, now_ms: u64) -> bool {
+    if last_used.is_some_and(|at| now_ms.saturating_sub(at) < LAST_USED_RESOLUTION_MS) {
+        return false;
+    }
+    *last_used = Some(now_ms);
+    true
+}
+
+/// Named tokens kept in KV
+pub struct TokenStore<'a> {
+    kv: &'a dyn KeyValueStore,
+}
+
+impl<'a> TokenStore<'a> {
+    /// Create a token store over the given KV store
+    pub fn new(kv: &'a dyn KeyValueStore) -> Self {
+        Self { kv }
+    }
+
+    fn token_key(id: &str) -> String {
+        format!("{}{}", TOKEN_KEY_PREFIX, id)
+    }
+
+    /// Hex SHA-256 of the hex salt followed by the secret, as stored in `TokenRecord::hash`
+    pub fn hash_secret(salt: &str, secret: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(salt.as_bytes());
+        hasher.update(secret.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Split a presented token into its id and secret
+    pub fn split_token(token: &str) -> Option<(&str, &str)> {
+        match token.split_once(TOKEN_SEPARATOR) {
+            Some((id, secret)) if !id.is_empty() && !secret.is_empty() => Some((id, secret)),
+            _ => None,
+        }
+    }
+
+    /// Join a token id and secret into the presented form
+    pub fn join_token(id: &str, secret: &str) -> String {
+        format!("{}{}{}", id, TOKEN_SEPARATOR, secret)
+    }
+
+    async fn load(&self, id: &str) -> Result<Option<TokenRecord>> {
+        match self.kv.get(&Self::token_key(id)).await? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a token under the given id
+    pub async fn put(&self, id: &str, record: &TokenRecord) -> Result<()> {
+        let value = serde_json::to_string(record)?;
+        self.kv.put(&Self::token_key(id), &value).await
+    }
+
+    /// Generate and store a new token, returning its plaintext
+    ///
+    /// Returns `None` when `expires_in` lies too far in the future to represent.
+    pub async fn create(
+        &self,
+        request: CreateTokenRequest,
+        now_ms: u64,
+    ) -> Result<Option<CreatedToken>> {
+        let expires = match request.expires_in {
+            Some(secs) => match secs.checked_mul(1000).and_then(|ms| now_ms.checked_add(ms)) {
+                Some(expires) => Some(expires),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+        let id = AuthUtils::random_hex(TOKEN_ID_BYTES)?;
+        let secret = AuthUtils::random_hex(TOKEN_SECRET_BYTES)?;
+        let salt = AuthUtils::random_hex(SALT_BYTES)?;
+
+        let record = TokenRecord {
+            name: request.name,
+            hash: Self::hash_secret(&salt, &secret),
+            salt,
+            hosts: request.hosts,
+            scopes: request.scopes,
+            created: now_ms,
+            last_used: None,
+            expires,
+        };
+        self.put(&id, &record).await?;
+
+        Ok(Some(CreatedToken {
+            token: Self::join_token(&id, &secret),
+            info: TokenInfo::new(&id, record),
+        }))
+    }
+
+    /// Metadata of every stored token
+    pub async fn list(&self) -> Result<Vec<TokenInfo>> {
+        let mut tokens = Vec::new();
+        for key in self.kv.list_keys(TOKEN_KEY_PREFIX).await? {
+            let id = &key[TOKEN_KEY_PREFIX.len()..];
+            if let Some(record) = self.load(id).await? {
+                tokens.push(TokenInfo::new(id, record));
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Delete a token, returning whether it existed
+    pub async fn revoke(&self, id: &str) -> Result<bool> {
+        if self.load(id).await?.is_none() {
+            return Ok(false);
+        }
+        self.kv.delete(&Self::token_key(id)).await?;
+        Ok(true)
+    }
+
+    /// Resolve a presented `{id}.{secret}` token to its credential
+    ///
+    /// Returns `None` for malformed, unknown, mismatching or expired tokens.
+    pub async fn verify(&self, token: &str, now_ms: u64) -> Result<Option<Credential>> {
+        let Some((id, secret)) = Self::split_token(token) else {
+            return Ok(None);
+        };
+        let Some(mut record) = self.load(id).await? else {
+            return Ok(None);
+        };
+
+        let hash = Self::hash_secret(&record.salt, secret);
+        if !bool::from(hash.as_bytes().ct_eq(record.hash.as_bytes())) {
+            return Ok(None);
+        }
+        if record.expires.is_some_and(|expires| now_ms >= expires) {
+            return Ok(None);
+        }
+
+        if touch_last_used(&mut record.last_used, now_ms) {
+            self.put(id, &record).await?;
+        }
+
+        Ok(Some(Credential {
+            name: record.name,
+            owner: Some(Self::token_key(id)),
+            hosts: record.hosts,
+            scopes: record.scopes,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn camera() -> CreateTokenRequest {
+        CreateTokenRequest {
+            name: "garden camera".to_string(),
+            hosts: vec!["cam-*".to_string(), "garden".to_string()],
+            scopes: Scope::defaults(),
+            expires_in: None,
+        }
+    }
+
+    #[test]
+    fn token_splitting() {
+        let test_cases = vec![
+            ("cam1.s3cret", Some(("cam1", "s3cret"))),
+            ("cam1.s3c.ret", Some(("cam1", "s3c.ret"))),
+            ("cam1", None),
+            (".s3cret", None),
+            ("cam1.", None),
+        ];
+
+        for (token, expected) in test_cases {
+            assert_eq!(
+                TokenStore::split_token(token),
+                expected,
+                "Failed: {}",
+                token
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn created_tokens_verify() {
+        let kv = MemoryStore::new();
+        let tokens = TokenStore::new(&kv);
+        let created = tokens.create(camera(), NOW).await.unwrap().unwrap();
+        let (id, secret) = TokenStore::split_token(&created.token).unwrap();
+        assert_eq!(id, created.info.id);
+
+        let credential = tokens.verify(&created.token, NOW).await.unwrap().unwrap();
+        assert_eq!(credential.name, "garden camera");
+
+        let stored = kv.get(&format!("token:{}", id)).await.unwrap().unwrap();
+        assert!(!stored.contains(secret));
+        let record: TokenRecord = serde_json::from_str(&stored).unwrap();
+        assert_eq!(record.hash, TokenStore::hash_secret(&record.salt, secret));
+        assert_ne!(record.hash, TokenStore::hash_secret("", secret));
+
+        let wrong = format!("{}.wrong", id);
+        assert_eq!(tokens.verify(&wrong, NOW).await.unwrap(), None);
+        assert_eq!(tokens.verify("cam2.s3cret", NOW).await.unwrap(), None);
+        assert_eq!(tokens.verify(secret, NOW).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_rejected() {
+        let kv = MemoryStore::new();
+        let tokens = TokenStore::new(&kv);
+        let created = tokens
+            .create(
+                CreateTokenRequest {
+                    expires_in: Some(3600),
+                    ..camera()
+                },
+                NOW,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(created.info.expires, Some(NOW + 3_600_000));
+
+        assert!(tokens.verify(&created.token, NOW).await.unwrap().is_some());
+        let expired = tokens.verify(&created.token, NOW + 3_600_000).await;
+        assert_eq!(expired.unwrap(), None);
+
+        for expires_in in [u64::MAX, u64::MAX / 1000] {
+            let request = CreateTokenRequest {
+                expires_in: Some(expires_in),
+                ..camera()
+            };
+            let created = tokens.create(request, NOW).await.unwrap();
+            assert!(created.is_none(), "Failed: {}", expires_in);
+        }
+        assert_eq!(tokens.list().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn last_use_is_recorded() {
+        let kv = MemoryStore::new();
+        let tokens = TokenStore::new(&kv);
+        let created = tokens.create(camera(), NOW).await.unwrap().unwrap();
+        let last_used = || async { tokens.list().await.unwrap()[0].last_used };
+        assert_eq!(last_used().await, None);
+
+        tokens.verify(&created.token, NOW + 1000).await.unwrap();
+        assert_eq!(last_used().await, Some(NOW + 1000));
+        tokens.verify(&created.token, NOW + 2000).await.unwrap();
+        assert_eq!(last_used().await, Some(NOW + 1000));
+        tokens.verify(&created.token, NOW + 61_000).await.unwrap();
+        assert_eq!(last_used().await, Some(NOW + 61_000));
+    }
+
+    #[tokio::test]
+    async fn tokens_are_listed_and_revoked() {
+        let kv = MemoryStore::new();
+        let tokens = TokenStore::new(&kv);
+        let created = tokens.create(camera(), NOW).await.unwrap().unwrap();
+
+        assert_eq!(tokens.list().await.unwrap(), vec![created.info.clone()]);
+        let listed = serde_json::to_string(&tokens.list().await.unwrap()).unwrap();
+        assert!(!listed.contains("hash") && !listed.contains("salt"));
+
+        assert!(tokens.revoke(&created.info.id).await.unwrap());
+        assert!(!tokens.revoke(&created.info.id).await.unwrap());
+        assert!(tokens.list().await.unwrap().is_empty());
+        assert_eq!(tokens.verify(&created.token, NOW).await.unwrap(), None);
+    }
+
+    #[test]
+    fn credentials_are_bound_to_homenames() {
+        let credential = Credential {
+            name: "garden camera".to_string(),
+            owner: Some("token:cam1".to_string()),
+            hosts: camera().hosts,
+            scopes: camera().scopes,
+        };
+        assert!(credential.allows("cam-front"));
+        assert!(credential.allows("garden"));
+        assert!(!credential.allows("vpn"));
+        assert!(Credential::admin().allows("vpn"));
+    }
+
+    #[test]
+    fn rate_limits_count_per_owner() {
+        let credential = Credential {
+            name: Credential::admin().name,
+            owner: Some("device:3f2a9c0d5e7b1a46".to_string()),
+            hosts: camera().hosts,
+            scopes: camera().scopes,
+        };
+        assert_eq!(credential.rate_key(), "device:3f2a9c0d5e7b1a46");
+        assert_eq!(Credential::admin().rate_key(), ADMIN_RATE_KEY);
+    }
+
+    #[tokio::test]
+    async fn hand_written_tokens_still_verify() {
+        let kv = MemoryStore::new();
+        let hash = TokenStore::hash_secret("", "s3cret");
+        let legacy = format!(r#"{{"name":"nas","hash":"{}","hosts":["nas"]}}"#, hash);
+        let dashboard = format!(
+            r#"{{"name":"dashboard","hash":"{}","hosts":[],"scopes":["ip:read"]}}"#,
+            hash
+        );
+        kv.put("token:nas", &legacy).await.unwrap();
+        kv.put("token:dash", &dashboard).await.unwrap();
+        let tokens = TokenStore::new(&kv);
+
+        let nas = tokens.verify("nas.s3cret", NOW).await.unwrap().unwrap();
+        assert!(nas.has_scope(Scope::IpRead));
+        assert!(nas.has_scope(Scope::DnsUpdate));
+        assert!(!nas.has_scope(Scope::Admin));
+
+        let dashboard = tokens.verify("dash.s3cret", NOW).await.unwrap().unwrap();
+        assert!(dashboard.has_scope(Scope::IpRead));
+        assert!(!dashboard.has_scope(Scope::DnsUpdate));
+
+        let admin = Credential::admin();
+        assert!(admin.has_scope(Scope::IpRead) && admin.has_scope(Scope::DnsUpdate));
+    }
+}